@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use doubao_translator::{Cache, TranslateParams};
+
+fn bench_cache(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let cache = Cache::new(10_000, Duration::from_secs(3600));
+    let keys: Vec<String> = (0..1000)
+        .map(|i| cache.key(&format!("paragraph {i}"), &TranslateParams::new("zh")))
+        .collect();
+    runtime.block_on(async {
+        for key in &keys {
+            cache.set(key.clone(), "译文".to_string()).await;
+        }
+    });
+
+    c.bench_function("cache/key", |b| {
+        b.iter(|| cache.key("The quick brown fox", &TranslateParams::new("zh")))
+    });
+    c.bench_function("cache/get_hit", |b| {
+        b.to_async(&runtime).iter(|| cache.get(&keys[500]))
+    });
+    c.bench_function("cache/set", |b| {
+        b.to_async(&runtime)
+            .iter(|| cache.set(keys[500].clone(), "译文".to_string()))
+    });
+
+    let mut group = c.benchmark_group("cache/contended_get");
+    for tasks in [1, 8, 64] {
+        group.bench_with_input(BenchmarkId::from_parameter(tasks), &tasks, |b, &tasks| {
+            b.to_async(&runtime).iter(|| {
+                let handles: Vec<_> = (0..tasks)
+                    .map(|t| {
+                        let cache = cache.clone();
+                        let key = keys[t % keys.len()].clone();
+                        tokio::spawn(async move { cache.get(&key).await })
+                    })
+                    .collect();
+                async move {
+                    for handle in handles {
+                        handle.await.unwrap();
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_cache);
+criterion_main!(benches);
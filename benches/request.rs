@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use doubao_translator::{ApiFormat, DoubaoProvider, TranslateParams, TranslatorConfig};
+
+fn bench_request_body(c: &mut Criterion) {
+    let chunks: Vec<String> = (0..1000).map(|i| format!("Short sentence {i}.")).collect();
+    let params = TranslateParams {
+        source: Some("en"),
+        instruction: Some("Keep product names in English."),
+        ..TranslateParams::new("zh")
+    };
+
+    let mut group = c.benchmark_group("request_body");
+    group.throughput(Throughput::Elements(chunks.len() as u64));
+    for format in [ApiFormat::Responses, ApiFormat::Chat] {
+        let mut config = TranslatorConfig::new("key");
+        config.api_format = format;
+        let provider = DoubaoProvider::new(reqwest::Client::new(), Arc::new(config));
+        group.bench_function(format!("{format:?}").to_lowercase(), |b| {
+            b.iter(|| {
+                for chunk in &chunks {
+                    provider.request_body(chunk, &params).unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_request_body);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use doubao_translator::split_text;
+
+const MAX_CHUNK_CHARS: usize = 800;
+
+fn document(paragraph: &str, bytes: usize) -> String {
+    let mut text = String::with_capacity(bytes + paragraph.len());
+    while text.len() < bytes {
+        text.push_str(paragraph);
+        text.push_str("\n\n");
+    }
+    text
+}
+
+fn bench_split(c: &mut Criterion) {
+    let latin = "The quick brown fox jumps over the lazy dog. ".repeat(6);
+    let cjk = "敏捷的棕色狐狸跳过了那只懒狗。".repeat(6);
+
+    let fixed = document(&latin, 100 * 1024);
+    assert_eq!(
+        split_text(&fixed, MAX_CHUNK_CHARS).len(),
+        189,
+        "chunk count for the fixed 100KB Latin document changed"
+    );
+
+    let mut group = c.benchmark_group("split_text");
+    for (script, paragraph) in [("latin", &latin), ("cjk", &cjk)] {
+        for (label, bytes) in [("1KB", 1024), ("100KB", 100 * 1024), ("1MB", 1024 * 1024)] {
+            let text = document(paragraph, bytes);
+            group.throughput(Throughput::Bytes(text.len() as u64));
+            group.bench_with_input(BenchmarkId::new(script, label), &text, |b, text| {
+                b.iter(|| split_text(text, MAX_CHUNK_CHARS))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_split);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+use std::{path::Path, process::Command};
+
+/// Captures the git commit and its commit time for `GET /api/version`.
+/// Both only change with the checked-out commit, so unlike a wall-clock
+/// build time they stay accurate without rerunning on every build.
+fn main() {
+    if let Some(sha) = git(&["rev-parse", "--short=12", "HEAD"]) {
+        println!("cargo:rustc-env=GIT_SHA={sha}");
+    }
+    if let Some(time) = git(&["log", "-1", "--format=%ct"]) {
+        println!("cargo:rustc-env=GIT_COMMIT_TIME={time}");
+    }
+
+    // Missing paths would make cargo rerun this on every build.
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|out| out.trim().to_string())
+        .filter(|out| !out.is_empty())
+}
@@ -0,0 +1,47 @@
+const LABELS: &[&str] = &[
+    "translation:",
+    "translated text:",
+    "译文：",
+    "译文:",
+    "翻译：",
+    "翻译:",
+];
+
+pub(crate) fn strip_model_artifacts(source: &str, output: String) -> String {
+    let mut text = output.trim();
+
+    if !source.trim_start().starts_with("```") {
+        if let Some(inner) = unfence(text) {
+            text = inner;
+        }
+    }
+
+    if let Some(rest) = strip_label(text) {
+        if strip_label(source.trim_start()).is_none() {
+            text = rest;
+        }
+    }
+
+    if text.len() == output.len() {
+        output
+    } else {
+        text.to_string()
+    }
+}
+
+fn unfence(text: &str) -> Option<&str> {
+    let body = text.strip_prefix("```")?.strip_suffix("```")?;
+    let (info, inner) = body.split_once('\n')?;
+    if info.trim().contains(char::is_whitespace) || inner.contains("```") {
+        return None;
+    }
+    Some(inner.trim_end_matches(['\r', '\n']))
+}
+
+fn strip_label(text: &str) -> Option<&str> {
+    LABELS.iter().find_map(|label| {
+        let head = text.get(..label.len())?;
+        head.eq_ignore_ascii_case(label)
+            .then(|| text[label.len()..].trim_start())
+    })
+}
@@ -0,0 +1,53 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+/// An append-only JSONL sink, one line per record. Meant for long-term
+/// retention, so callers should record sizes and outcomes, not content.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl AuditLog {
+    pub fn stdout() -> Self {
+        Self::from_writer(io::stdout())
+    }
+
+    /// Appends to `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::from_writer(file))
+    }
+
+    pub fn from_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            sink: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Writes `record` as one line. Failures are reported on stderr rather
+    /// than failing the request being audited.
+    pub fn write(&self, record: &impl Serialize) {
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("Failed to encode audit record: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+        let mut sink = self
+            .sink
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(err) = sink.write_all(&line).and_then(|()| sink.flush()) {
+            eprintln!("Failed to write audit record: {err}");
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+const DAY_SECS: u64 = 24 * 3600;
+
+/// Upstream usage for one UTC day, counted in days since the Unix epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetUsage {
+    pub day: u64,
+    pub requests: u64,
+    pub tokens: u64,
+}
+
+/// Caps upstream calls, and the tokens they report, per UTC day. Only real
+/// upstream calls are counted, never cache hits. With a state file the
+/// counters survive restarts within the same day.
+pub struct Budget {
+    max_requests: Option<u64>,
+    max_tokens: Option<u64>,
+    state_file: Option<PathBuf>,
+    usage: Mutex<BudgetUsage>,
+}
+
+impl Budget {
+    pub fn new(max_requests: Option<u64>, max_tokens: Option<u64>) -> Self {
+        Self {
+            max_requests,
+            max_tokens,
+            state_file: None,
+            usage: Mutex::new(BudgetUsage {
+                day: today(),
+                ..BudgetUsage::default()
+            }),
+        }
+    }
+
+    /// Persists the counters to `path`, resuming from it if it holds today's.
+    pub fn with_state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match std::fs::read_to_string(&path) {
+            Ok(raw) => match serde_json::from_str::<BudgetUsage>(&raw) {
+                Ok(saved) if saved.day == today() => *self.usage.get_mut().unwrap() = saved,
+                Ok(_) => {}
+                Err(err) => eprintln!("Ignoring budget state {}: {err}", path.display()),
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => eprintln!("Failed to read budget state {}: {err}", path.display()),
+        }
+        self.state_file = Some(path);
+        self
+    }
+
+    /// `Err` holds the time left until the budget resets at UTC midnight.
+    pub fn check(&self) -> Result<(), Duration> {
+        let usage = self.current();
+        let spent = |max: Option<u64>, used: u64| max.is_some_and(|max| used >= max);
+        if spent(self.max_requests, usage.requests) || spent(self.max_tokens, usage.tokens) {
+            return Err(until_tomorrow());
+        }
+        Ok(())
+    }
+
+    /// Counts one upstream call and the tokens it reported, if any.
+    pub fn record(&self, tokens: Option<u64>) {
+        let usage = {
+            let mut usage = self.usage.lock().unwrap();
+            roll_over(&mut usage);
+            usage.requests += 1;
+            usage.tokens += tokens.unwrap_or(0);
+            *usage
+        };
+        if let Some(path) = &self.state_file {
+            let saved = serde_json::to_vec(&usage)
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(err) = saved {
+                eprintln!("Failed to save budget state {}: {err}", path.display());
+            }
+        }
+    }
+
+    pub fn usage(&self) -> BudgetUsage {
+        self.current()
+    }
+
+    fn current(&self) -> BudgetUsage {
+        let mut usage = self.usage.lock().unwrap();
+        roll_over(&mut usage);
+        *usage
+    }
+}
+
+fn roll_over(usage: &mut BudgetUsage) {
+    let day = today();
+    if usage.day != day {
+        *usage = BudgetUsage {
+            day,
+            ..BudgetUsage::default()
+        };
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn today() -> u64 {
+    now_secs() / DAY_SECS
+}
+
+fn until_tomorrow() -> Duration {
+    Duration::from_secs(DAY_SECS - now_secs() % DAY_SECS)
+}
@@ -0,0 +1,394 @@
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{Formality, TextFormat, TranslateParams};
+
+#[derive(Clone)]
+pub struct Cache {
+    ttl: Duration,
+    ttl_jitter_pct: u8,
+    key_prefix: Arc<str>,
+    collapse_whitespace: bool,
+    stale_window: Duration,
+    inner: Arc<Mutex<LruCache<String, CacheEntry>>>,
+    refreshing: Arc<std::sync::Mutex<HashSet<String>>>,
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    capacity_evictions: AtomicU64,
+    ttl_expirations: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// The `CACHE_KEY_PREFIX` namespace these counts cover.
+    pub key_prefix: String,
+    pub entries: usize,
+    pub capacity: usize,
+    /// Live entries displaced by `set` because the cache was full.
+    pub capacity_evictions: u64,
+    /// Entries found expired (and dropped) by `get`.
+    pub ttl_expirations: u64,
+}
+
+#[derive(Clone)]
+struct CacheEntry {
+    value: String,
+    expires_at: Instant,
+    /// Past `expires_at` the value may still be served by `lookup`, flagged
+    /// stale, until this point.
+    stale_until: Instant,
+    created_at_ms: u64,
+    /// Set for whole-document translations, which `export` can dump.
+    origin: Option<Arc<Origin>>,
+}
+
+/// What a document entry was translated from, since its key is a hash.
+struct Origin {
+    text: String,
+    source: Option<String>,
+    target: String,
+    formality: Option<Formality>,
+    instruction: Option<String>,
+}
+
+/// One translation-memory entry, as written by [`Cache::export`] and read
+/// back by [`Cache::import`]. Timestamps are Unix milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formality: Option<Formality>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instruction: Option<String>,
+    pub translation: String,
+    pub created_at_ms: u64,
+    pub expires_at_ms: u64,
+}
+
+/// A value found by [`Cache::lookup`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheHit {
+    pub value: String,
+    pub stale: bool,
+}
+
+/// Marks a key as being refreshed until dropped; see [`Cache::begin_refresh`].
+pub struct RefreshGuard {
+    key: String,
+    refreshing: Arc<std::sync::Mutex<HashSet<String>>>,
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        if let Ok(mut refreshing) = self.refreshing.lock() {
+            refreshing.remove(&self.key);
+        }
+    }
+}
+
+impl Cache {
+    pub fn new(max_size: usize, ttl: Duration) -> Self {
+        let max = NonZeroUsize::new(max_size.max(1)).unwrap();
+        Self {
+            ttl,
+            ttl_jitter_pct: 0,
+            key_prefix: Arc::from(""),
+            collapse_whitespace: false,
+            stale_window: Duration::ZERO,
+            inner: Arc::new(Mutex::new(LruCache::new(max))),
+            refreshing: Arc::default(),
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn with_whitespace_collapse(mut self, collapse: bool) -> Self {
+        self.collapse_whitespace = collapse;
+        self
+    }
+
+    /// Spreads each entry's TTL randomly within ±`pct` percent so entries
+    /// written together don't all expire at once.
+    pub fn with_ttl_jitter(mut self, pct: u8) -> Self {
+        self.ttl_jitter_pct = pct.min(100);
+        self
+    }
+
+    /// Keeps expired entries for `window` longer so `lookup` can serve them
+    /// as stale while the caller refreshes them.
+    pub fn with_stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.stale_window = window;
+        self
+    }
+
+    /// Namespaces every key so deployments sharing a backing store don't collide.
+    pub fn with_key_prefix(mut self, prefix: &str) -> Self {
+        self.key_prefix = Arc::from(prefix);
+        self
+    }
+
+    pub fn key_prefix(&self) -> &str {
+        &self.key_prefix
+    }
+
+    pub fn key(&self, text: &str, params: &TranslateParams<'_>) -> String {
+        let hash = build_cache_key(&normalize_key_text(text, self.collapse_whitespace), params);
+        format!("{}{hash}", self.key_prefix)
+    }
+
+    pub fn chunk_key(&self, model: &str, text: &str, params: &TranslateParams<'_>) -> String {
+        let base = format!("chunk|{model}|{}", self.key(text, params));
+        format!("{}{:x}", self.key_prefix, md5::compute(base))
+    }
+
+    pub fn summary_key(&self, model: &str, text: &str, target: &str, max_chars: usize) -> String {
+        let base = format!(
+            "summary|{model}|{max_chars}|{}",
+            self.key(text, &TranslateParams::new(target))
+        );
+        format!("{}{:x}", self.key_prefix, md5::compute(base))
+    }
+
+    /// Returns `None` only for missing or expired keys; an empty string is a
+    /// valid cached translation. Failed translations are never stored.
+    pub async fn get(&self, key: &str) -> Option<String> {
+        self.lookup(key)
+            .await
+            .filter(|hit| !hit.stale)
+            .map(|hit| hit.value)
+    }
+
+    /// Like `get`, but also returns entries inside the stale-while-revalidate
+    /// window, flagged as stale.
+    pub async fn lookup(&self, key: &str) -> Option<CacheHit> {
+        let mut cache = self.inner.lock().await;
+        let entry = cache.get(key)?;
+        let now = Instant::now();
+        if now <= entry.stale_until {
+            return Some(CacheHit {
+                value: entry.value.clone(),
+                stale: now > entry.expires_at,
+            });
+        }
+        cache.pop(key);
+        self.counters
+            .ttl_expirations
+            .fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Claims the refresh of `key`, or returns `None` if one is already running.
+    pub fn begin_refresh(&self, key: &str) -> Option<RefreshGuard> {
+        let mut refreshing = self.refreshing.lock().ok()?;
+        refreshing.insert(key.to_string()).then(|| RefreshGuard {
+            key: key.to_string(),
+            refreshing: Arc::clone(&self.refreshing),
+        })
+    }
+
+    pub async fn ttl_remaining(&self, key: &str) -> Option<Duration> {
+        let cache = self.inner.lock().await;
+        let entry = cache.peek(key)?;
+        entry.expires_at.checked_duration_since(Instant::now())
+    }
+
+    pub async fn set(&self, key: String, value: String) {
+        self.set_with_ttl(key, value, None).await;
+    }
+
+    /// Like `set`, but `ttl` (when given) replaces the cache-wide TTL, and
+    /// its jitter, for this entry.
+    pub async fn set_with_ttl(&self, key: String, value: String, ttl: Option<Duration>) {
+        self.insert(key, value, ttl, unix_ms(), None).await;
+    }
+
+    /// Stores the translation of the whole of `text` under `key` (its
+    /// [`Cache::key`]), remembering the request so [`Cache::export`] includes
+    /// it. Entries keyed on context are stored but not exported, as the
+    /// context can't be rebuilt on import.
+    pub async fn set_translation(
+        &self,
+        key: String,
+        text: &str,
+        params: &TranslateParams<'_>,
+        value: String,
+    ) {
+        let origin = (!params.use_context).then(|| {
+            Arc::new(Origin {
+                text: text.to_string(),
+                source: params.source.map(str::to_string),
+                target: params.target.to_string(),
+                formality: params.formality,
+                instruction: params.instruction.map(str::to_string),
+            })
+        });
+        self.insert(key, value, params.cache_ttl, unix_ms(), origin)
+            .await;
+    }
+
+    /// The live document translations, least recently used first so an
+    /// import replays them in the same order.
+    pub async fn export(&self) -> Vec<MemoryEntry> {
+        let now = Instant::now();
+        let now_ms = unix_ms();
+        let cache = self.inner.lock().await;
+        cache
+            .iter()
+            .rev()
+            .filter(|(_, entry)| entry.expires_at > now)
+            .filter_map(|(_, entry)| {
+                let origin = entry.origin.as_deref()?;
+                Some(MemoryEntry {
+                    text: origin.text.clone(),
+                    source: origin.source.clone(),
+                    target: origin.target.clone(),
+                    formality: origin.formality,
+                    instruction: origin.instruction.clone(),
+                    translation: entry.value.clone(),
+                    created_at_ms: entry.created_at_ms,
+                    expires_at_ms: now_ms + (entry.expires_at - now).as_millis() as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// Loads exported entries, keyed under this cache's prefix (whatever
+    /// namespace they were exported from) and keeping their original expiry.
+    /// Already expired entries are skipped; returns how many were stored.
+    pub async fn import(&self, entries: Vec<MemoryEntry>) -> usize {
+        let now_ms = unix_ms();
+        let mut imported = 0;
+        for entry in entries {
+            let remaining = entry.expires_at_ms.saturating_sub(now_ms);
+            if remaining == 0 || entry.text.is_empty() || entry.target.trim().is_empty() {
+                continue;
+            }
+            let params = TranslateParams {
+                source: entry.source.as_deref(),
+                formality: entry.formality,
+                instruction: entry.instruction.as_deref(),
+                ..TranslateParams::new(&entry.target)
+            };
+            let key = self.key(&entry.text, &params);
+            let origin = Arc::new(Origin {
+                source: params.source.map(str::to_string),
+                target: entry.target.clone(),
+                formality: entry.formality,
+                instruction: entry.instruction.clone(),
+                text: entry.text,
+            });
+            let ttl = Duration::from_millis(remaining);
+            self.insert(
+                key,
+                entry.translation,
+                Some(ttl),
+                entry.created_at_ms,
+                Some(origin),
+            )
+            .await;
+            imported += 1;
+        }
+        imported
+    }
+
+    async fn insert(
+        &self,
+        key: String,
+        value: String,
+        ttl: Option<Duration>,
+        created_at_ms: u64,
+        origin: Option<Arc<Origin>>,
+    ) {
+        let expires_at = Instant::now() + ttl.unwrap_or_else(|| self.entry_ttl());
+        let entry = CacheEntry {
+            value,
+            expires_at,
+            stale_until: expires_at + self.stale_window,
+            created_at_ms,
+            origin,
+        };
+        let mut cache = self.inner.lock().await;
+        if cache.len() == cache.cap().get() && !cache.contains(&key) {
+            self.counters
+                .capacity_evictions
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        cache.put(key, entry);
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let cache = self.inner.lock().await;
+        CacheStats {
+            key_prefix: self.key_prefix.to_string(),
+            entries: cache.len(),
+            capacity: cache.cap().get(),
+            capacity_evictions: self.counters.capacity_evictions.load(Ordering::Relaxed),
+            ttl_expirations: self.counters.ttl_expirations.load(Ordering::Relaxed),
+        }
+    }
+
+    fn entry_ttl(&self) -> Duration {
+        if self.ttl_jitter_pct == 0 {
+            return self.ttl;
+        }
+        let pct = f64::from(self.ttl_jitter_pct) / 100.0;
+        let factor = 1.0 + pct * (fastrand::f64() * 2.0 - 1.0);
+        self.ttl.mul_f64(factor)
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn build_cache_key(text: &str, params: &TranslateParams<'_>) -> String {
+    let formality = params.formality.map(Formality::as_str).unwrap_or("");
+    let mut base = format!(
+        "{}|{}|{}|{}|{}",
+        params.source.unwrap_or(""),
+        params.target,
+        formality,
+        params.instruction.unwrap_or(""),
+        text
+    );
+    // Appended only when set, so existing plain-text keys stay as they were.
+    if params.use_context {
+        base.push_str("|context|");
+        base.push_str(params.context.unwrap_or(""));
+    }
+    if params.format != TextFormat::default() {
+        base.push_str("|format|");
+        base.push_str(params.format.as_str());
+    }
+    format!("{:x}", md5::compute(base))
+}
+
+fn normalize_key_text(text: &str, collapse_whitespace: bool) -> Cow<'_, str> {
+    let trimmed = text.trim();
+    if collapse_whitespace {
+        Cow::Owned(trimmed.split_whitespace().collect::<Vec<_>>().join(" "))
+    } else if trimmed.contains("\r\n") {
+        Cow::Owned(trimmed.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(trimmed)
+    }
+}
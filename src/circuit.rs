@@ -0,0 +1,76 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Opens after `threshold` consecutive upstream outages and lets a single
+/// probe through once `cooldown` has elapsed. A threshold of 0 disables it.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    started: Instant,
+    failures: AtomicU32,
+    opened_at_ms: AtomicU64,
+    probing: AtomicBool,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            started: Instant::now(),
+            failures: AtomicU32::new(0),
+            opened_at_ms: AtomicU64::new(0),
+            probing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(0, Duration::ZERO)
+    }
+
+    pub fn allow(&self) -> bool {
+        if self.threshold == 0 {
+            return true;
+        }
+        let opened_at = self.opened_at_ms.load(Ordering::SeqCst);
+        if opened_at == 0 {
+            return true;
+        }
+        if self.now_ms().saturating_sub(opened_at) < self.cooldown.as_millis() as u64 {
+            return false;
+        }
+        self.probing
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        self.opened_at_ms.store(0, Ordering::SeqCst);
+        self.probing.store(false, Ordering::SeqCst);
+    }
+
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        if self.probing.swap(false, Ordering::SeqCst) {
+            self.opened_at_ms.store(self.now_ms(), Ordering::SeqCst);
+            return;
+        }
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= self.threshold {
+            self.opened_at_ms.store(self.now_ms(), Ordering::SeqCst);
+        }
+    }
+
+    pub fn release_probe(&self) {
+        self.probing.store(false, Ordering::SeqCst);
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64 + 1
+    }
+}
@@ -0,0 +1,46 @@
+use whatlang::{Detector, Lang};
+
+/// whatlang's languages as the server's language codes.
+const CODES: &[(Lang, &str)] = &[
+    (Lang::Cmn, "zh"),
+    (Lang::Eng, "en"),
+    (Lang::Jpn, "ja"),
+    (Lang::Kor, "ko"),
+    (Lang::Deu, "de"),
+    (Lang::Fra, "fr"),
+    (Lang::Spa, "es"),
+    (Lang::Ita, "it"),
+    (Lang::Por, "pt"),
+    (Lang::Rus, "ru"),
+    (Lang::Tha, "th"),
+    (Lang::Vie, "vi"),
+    (Lang::Ara, "ar"),
+];
+
+/// Best-effort language guess for `text`, returned as one of the server's
+/// language codes. `None` when the language is unknown or unsupported.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let lang = whatlang::detect_lang(text)?;
+    CODES
+        .iter()
+        .find(|(known, _)| *known == lang)
+        .map(|(_, code)| *code)
+}
+
+/// Which of the two `pair` languages `text` is written in. Falls back to the
+/// first one when neither can be told apart (or detected at all).
+pub fn detect_between<'a>(text: &str, pair: [&'a str; 2]) -> &'a str {
+    let lang = |code: &str| {
+        CODES
+            .iter()
+            .find(|(_, known)| known.eq_ignore_ascii_case(code.trim()))
+            .map(|(lang, _)| *lang)
+    };
+    let (Some(first), Some(second)) = (lang(pair[0]), lang(pair[1])) else {
+        return pair[0];
+    };
+    match Detector::with_allowlist(vec![first, second]).detect_lang(text) {
+        Some(detected) if detected == second => pair[1],
+        _ => pair[0],
+    }
+}
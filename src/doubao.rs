@@ -0,0 +1,543 @@
+use std::{borrow::Cow, fmt::Write, future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::StreamExt;
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    ApiFormat, Formality, ProviderOutput, TokenSink, TranslateError, TranslateParams,
+    TranslationProvider, TranslatorConfig,
+};
+
+pub struct DoubaoProvider {
+    client: Client,
+    config: Arc<TranslatorConfig>,
+}
+
+impl DoubaoProvider {
+    pub fn new(client: Client, config: Arc<TranslatorConfig>) -> Self {
+        Self { client, config }
+    }
+
+    /// The JSON body `translate` posts upstream for `text`.
+    pub fn request_body(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> serde_json::Result<Vec<u8>> {
+        let model = params.model.unwrap_or(&self.config.model);
+        let stream = self.streams(params);
+        let mut body = Vec::with_capacity(text.len() + 256);
+        match self.config.api_format {
+            ApiFormat::Responses => serde_json::to_writer(
+                &mut body,
+                &DoubaoRequest {
+                    stream,
+                    ..DoubaoRequest::new(model, text, params)
+                },
+            )?,
+            ApiFormat::Chat => serde_json::to_writer(
+                &mut body,
+                &ChatRequest {
+                    stream,
+                    ..ChatRequest::new(model, text, params)
+                },
+            )?,
+        }
+        Ok(body)
+    }
+
+    fn streams(&self, params: &TranslateParams<'_>) -> bool {
+        self.config.upstream_streaming && params.tokens.is_some()
+    }
+
+    fn summary_body(
+        &self,
+        text: &str,
+        target: &str,
+        max_chars: usize,
+    ) -> serde_json::Result<Vec<u8>> {
+        let prompt = format!(
+            "Summarize the user's text in {target} in at most {max_chars} characters. \
+             Reply with the summary only."
+        );
+        let model = self.config.model.as_str();
+        match self.config.api_format {
+            ApiFormat::Responses => {
+                serde_json::to_vec(&DoubaoRequest::prompted(model, &prompt, text))
+            }
+            ApiFormat::Chat => serde_json::to_vec(&ChatRequest::prompted(model, prompt, text)),
+        }
+    }
+
+    /// Posts a caller-built `input` array (`messages` in the chat format)
+    /// with the configured model, unless `model` is given, and returns the
+    /// upstream response body untouched.
+    pub async fn raw(&self, input: &Value, model: Option<&str>) -> Result<String, TranslateError> {
+        let model = model.unwrap_or(&self.config.model);
+        let body = match self.config.api_format {
+            ApiFormat::Responses => json!({ "model": model, "input": input }),
+            ApiFormat::Chat => json!({ "model": model, "messages": input }),
+        };
+        self.post(serde_json::to_vec(&body)).await
+    }
+
+    /// Posts `body`, failing over to the backup endpoints on outages, and
+    /// returns the raw response text of a successful call.
+    async fn post(&self, body: serde_json::Result<Vec<u8>>) -> Result<String, TranslateError> {
+        let body = encode(body)?;
+        self.with_failover(|url| self.post_to(url, body.clone()))
+            .await
+    }
+
+    async fn with_failover<'s, T, F>(
+        &'s self,
+        call: impl Fn(&'s str) -> F,
+    ) -> Result<T, TranslateError>
+    where
+        F: Future<Output = Result<T, TranslateError>>,
+    {
+        let mut outcome = call(&self.config.api_url).await;
+        for backup in &self.config.api_url_backups {
+            match &outcome {
+                Err(err) if err.is_outage() => {
+                    eprintln!("Upstream endpoint failed ({err}), trying backup {backup}")
+                }
+                _ => break,
+            }
+            outcome = call(backup).await;
+            if outcome.is_ok() {
+                println!("Request served by backup endpoint {backup}");
+            }
+        }
+        outcome
+    }
+
+    async fn post_to(&self, url: &str, body: Bytes) -> Result<String, TranslateError> {
+        let resp = self.send_to(url, body).await?;
+        read_limited(resp, self.config.max_response_bytes).await
+    }
+
+    /// Sends `body` to `url`, returning the response unread unless its
+    /// status is an error.
+    async fn send_to(&self, url: &str, body: Bytes) -> Result<reqwest::Response, TranslateError> {
+        let resp = self
+            .client
+            .post(url)
+            .bearer_auth(&self.config.api_key)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| TranslateError::from_reqwest("HTTP请求失败", e))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(TranslateError::Status {
+                status: status.as_u16(),
+                body: read_limited(resp, self.config.max_response_bytes).await?,
+            });
+        }
+        Ok(resp)
+    }
+
+    /// Like `translate_detailed`, but reads an SSE response, passing each
+    /// text delta to `tokens` as it arrives.
+    async fn translate_streamed(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+        tokens: TokenSink<'_>,
+    ) -> Result<ProviderOutput, TranslateError> {
+        let body = encode(self.request_body(text, params))?;
+        let resp = self
+            .with_failover(|url| self.send_to(url, body.clone()))
+            .await?;
+
+        let limit = self.config.max_response_bytes;
+        let mut output = ProviderOutput::default();
+        let mut read = 0usize;
+        let mut pending = Vec::new();
+        let mut stream = resp.bytes_stream();
+        while let Some(bytes) = stream.next().await {
+            let bytes = bytes.map_err(|e| TranslateError::from_reqwest("读取响应失败", e))?;
+            read += bytes.len();
+            if read > limit {
+                return Err(too_large(limit));
+            }
+            pending.extend_from_slice(&bytes);
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                match parse_stream_line(&String::from_utf8_lossy(&line)) {
+                    Some(StreamEvent::Delta(delta)) => {
+                        (tokens.0)(&delta);
+                        output.text.push_str(&delta);
+                    }
+                    Some(StreamEvent::Completed(response)) => {
+                        output.tokens = parse_total_tokens(&response);
+                        output.detected_source = parse_detected_source(&response);
+                    }
+                    Some(StreamEvent::Failed(message)) => {
+                        return Err(TranslateError::Upstream(format!(
+                            "上游流式响应失败: {message}"
+                        )));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn encode(body: serde_json::Result<Vec<u8>>) -> Result<Bytes, TranslateError> {
+    body.map(Bytes::from)
+        .map_err(|e| TranslateError::Internal(format!("请求序列化失败: {e}")))
+}
+
+#[async_trait]
+impl TranslationProvider for DoubaoProvider {
+    async fn translate(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<String, TranslateError> {
+        Ok(self.translate_detailed(text, params).await?.text)
+    }
+
+    async fn translate_detailed(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<ProviderOutput, TranslateError> {
+        if let Some(tokens) = params.tokens.filter(|_| self.streams(params)) {
+            return self.translate_streamed(text, params, tokens).await;
+        }
+        let body = self.post(self.request_body(text, params)).await?;
+        let text = parse_doubao_response(&body)
+            .map_err(|e| TranslateError::Upstream(format!("响应解析失败: {e}")))?;
+        Ok(ProviderOutput {
+            text,
+            detected_source: parse_detected_source(&body),
+            tokens: parse_total_tokens(&body),
+        })
+    }
+
+    async fn summarize(
+        &self,
+        text: &str,
+        target: &str,
+        max_chars: usize,
+    ) -> Result<String, TranslateError> {
+        let body = self
+            .post(self.summary_body(text, target, max_chars))
+            .await?;
+        parse_doubao_response(&body)
+            .map_err(|e| TranslateError::Upstream(format!("响应解析失败: {e}")))
+    }
+}
+
+fn too_large(limit: usize) -> TranslateError {
+    TranslateError::Upstream(format!("上游响应超过大小限制（最大{limit}字节）"))
+}
+
+async fn read_limited(resp: reqwest::Response, limit: usize) -> Result<String, TranslateError> {
+    if resp.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(too_large(limit));
+    }
+
+    let mut body = Vec::new();
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| TranslateError::from_reqwest("读取响应失败", e))?;
+        if body.len() + chunk.len() > limit {
+            return Err(too_large(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+#[derive(Serialize)]
+pub(crate) struct DoubaoRequest<'a> {
+    model: &'a str,
+    input: Vec<DoubaoInputMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct DoubaoInputMessage<'a> {
+    role: &'static str,
+    content: [DoubaoContent<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct DoubaoContent<'a> {
+    #[serde(rename = "type")]
+    content_type: &'static str,
+    text: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    translation_options: Option<TranslationOptions<'a>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ChatRequest<'a> {
+    model: &'a str,
+    messages: [ChatMessage<'a>; 2],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: Cow<'a, str>,
+}
+
+#[derive(Serialize)]
+struct TranslationOptions<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_language: Option<&'a str>,
+    target_language: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    formality: Option<Formality>,
+}
+
+impl<'a> DoubaoRequest<'a> {
+    pub(crate) fn new(model: &'a str, text: &'a str, params: &TranslateParams<'a>) -> Self {
+        let mut input = Vec::with_capacity(2);
+        if let Some(instruction) = params.instruction {
+            input.push(DoubaoInputMessage {
+                role: "system",
+                content: [DoubaoContent {
+                    content_type: "input_text",
+                    text: Cow::Borrowed(instruction),
+                    translation_options: None,
+                }],
+            });
+        }
+        if let Some(context) = params.context {
+            input.push(DoubaoInputMessage {
+                role: "system",
+                content: [DoubaoContent {
+                    content_type: "input_text",
+                    text: Cow::Owned(context_prompt(context)),
+                    translation_options: None,
+                }],
+            });
+        }
+        input.push(DoubaoInputMessage {
+            role: "user",
+            content: [DoubaoContent {
+                content_type: "input_text",
+                text: Cow::Borrowed(text),
+                translation_options: Some(TranslationOptions {
+                    source_language: params.source,
+                    target_language: params.target,
+                    formality: params.formality,
+                }),
+            }],
+        });
+
+        Self {
+            model,
+            input,
+            temperature: params.temperature,
+            stream: false,
+        }
+    }
+
+    /// A plain prompt + text exchange, without translation options.
+    pub(crate) fn prompted(model: &'a str, prompt: &'a str, text: &'a str) -> Self {
+        let message = |role, text| DoubaoInputMessage {
+            role,
+            content: [DoubaoContent {
+                content_type: "input_text",
+                text: Cow::Borrowed(text),
+                translation_options: None,
+            }],
+        };
+        Self {
+            model,
+            input: vec![message("system", prompt), message("user", text)],
+            temperature: None,
+            stream: false,
+        }
+    }
+}
+
+impl<'a> ChatRequest<'a> {
+    pub(crate) fn new(model: &'a str, text: &'a str, params: &TranslateParams<'a>) -> Self {
+        let mut prompt = String::with_capacity(96);
+        match params.source {
+            Some(source) => {
+                let _ = write!(
+                    prompt,
+                    "Translate the user's text from {source} to {}.",
+                    params.target
+                );
+            }
+            None => {
+                let _ = write!(prompt, "Translate the user's text to {}.", params.target);
+            }
+        }
+        if let Some(formality) = params.formality {
+            let _ = write!(prompt, " Use a {} register.", formality.as_str());
+        }
+        prompt.push_str(" Reply with the translation only.");
+        if let Some(instruction) = params.instruction {
+            prompt.push('\n');
+            prompt.push_str(instruction);
+        }
+        if let Some(context) = params.context {
+            prompt.push('\n');
+            prompt.push_str(&context_prompt(context));
+        }
+        Self {
+            temperature: params.temperature,
+            ..Self::prompted(model, prompt, text)
+        }
+    }
+
+    pub(crate) fn prompted(model: &'a str, prompt: String, text: &'a str) -> Self {
+        Self {
+            model,
+            messages: [
+                ChatMessage {
+                    role: "system",
+                    content: Cow::Owned(prompt),
+                },
+                ChatMessage {
+                    role: "user",
+                    content: Cow::Borrowed(text),
+                },
+            ],
+            temperature: None,
+            stream: false,
+        }
+    }
+}
+
+fn context_prompt(context: &str) -> String {
+    format!(
+        "For context only, the text just before the user's text was (do not translate it):\n{context}"
+    )
+}
+
+enum StreamEvent {
+    Delta(String),
+    /// The final response (responses format) or usage chunk (chat format).
+    Completed(String),
+    Failed(String),
+}
+
+/// One line of an upstream SSE response in either API format; `None` for
+/// anything that carries no text or outcome.
+fn parse_stream_line(line: &str) -> Option<StreamEvent> {
+    let data = line.trim_end().strip_prefix("data:")?.trim_start();
+    let value: Value = serde_json::from_str(data).ok()?;
+    match value.get("type").and_then(|v| v.as_str()) {
+        Some("response.output_text.delta") => {
+            let delta = value.get("delta")?.as_str()?;
+            Some(StreamEvent::Delta(delta.to_string()))
+        }
+        Some("response.completed") => {
+            Some(StreamEvent::Completed(value.get("response")?.to_string()))
+        }
+        Some("response.failed" | "error") => {
+            let message = ["/response/error/message", "/error/message", "/message"]
+                .iter()
+                .find_map(|pointer| value.pointer(pointer)?.as_str())
+                .unwrap_or("unknown error");
+            Some(StreamEvent::Failed(message.to_string()))
+        }
+        Some(_) => None,
+        None => {
+            let delta = value
+                .pointer("/choices/0/delta/content")
+                .and_then(|v| v.as_str())
+                .filter(|delta| !delta.is_empty());
+            match delta {
+                Some(delta) => Some(StreamEvent::Delta(delta.to_string())),
+                None if value.get("usage").is_some_and(|u| !u.is_null()) => {
+                    Some(StreamEvent::Completed(data.to_string()))
+                }
+                None => None,
+            }
+        }
+    }
+}
+
+/// `usage.total_tokens`, reported by both API formats.
+pub(crate) fn parse_total_tokens(body: &str) -> Option<u64> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    value.get("usage")?.get("total_tokens")?.as_u64()
+}
+
+/// `detected_source_language`, either at the top level or on an output item.
+pub(crate) fn parse_detected_source(body: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    let field = |v: &Value| {
+        v.get("detected_source_language")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+    field(&value).or_else(|| {
+        value
+            .get("output")
+            .and_then(|v| v.as_array())?
+            .iter()
+            .find_map(field)
+    })
+}
+
+pub(crate) fn parse_doubao_response(body: &str) -> Result<String, String> {
+    let value: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+
+    if value.get("status").and_then(|v| v.as_str()) == Some("completed") {
+        if let Some(output) = value.get("output").and_then(|v| v.as_array()) {
+            for item in output {
+                let is_message = item.get("type").and_then(|v| v.as_str()) == Some("message");
+                let is_assistant = item.get("role").and_then(|v| v.as_str()) == Some("assistant");
+                if !is_message || !is_assistant {
+                    continue;
+                }
+                if let Some(content) = item.get("content").and_then(|v| v.as_array()) {
+                    for part in content {
+                        let is_output =
+                            part.get("type").and_then(|v| v.as_str()) == Some("output_text");
+                        if is_output {
+                            if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
+                                return Ok(text.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        return Err("new format missing output_text".to_string());
+    }
+
+    if let Some(choices) = value.get("choices").and_then(|v| v.as_array()) {
+        if let Some(choice) = choices.first() {
+            if let Some(content) = choice
+                .get("message")
+                .and_then(|m| m.get("content"))
+                .and_then(|v| v.as_str())
+            {
+                return Ok(content.to_string());
+            }
+        }
+    }
+
+    Err("unknown response format".to_string())
+}
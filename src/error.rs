@@ -0,0 +1,69 @@
+use reqwest::StatusCode;
+
+#[derive(Debug)]
+pub enum TranslateError {
+    Upstream(String),
+    Timeout(String),
+    Status {
+        status: u16,
+        body: String,
+    },
+    Unavailable(String),
+    Placeholder(String),
+    Internal(String),
+    /// The daily upstream budget is used up until `retry_after` has passed.
+    BudgetExceeded {
+        retry_after: std::time::Duration,
+    },
+}
+
+impl TranslateError {
+    pub(crate) fn from_reqwest(context: &str, err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout(format!("{context}: 上游请求超时"))
+        } else if err.is_builder() {
+            Self::Internal(format!("{context}: {err}"))
+        } else {
+            Self::Upstream(format!("{context}: {err}"))
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Upstream(_) | Self::Status { .. } | Self::Placeholder(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            Self::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::BudgetExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn is_outage(&self) -> bool {
+        match self {
+            Self::Upstream(_) | Self::Timeout(_) => true,
+            Self::Status { status, .. } => *status >= 500,
+            Self::Unavailable(_)
+            | Self::Placeholder(_)
+            | Self::Internal(_)
+            | Self::BudgetExceeded { .. } => false,
+        }
+    }
+}
+
+impl std::fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Upstream(msg)
+            | Self::Timeout(msg)
+            | Self::Unavailable(msg)
+            | Self::Placeholder(msg)
+            | Self::Internal(msg) => f.write_str(msg),
+            Self::Status { status, body } => write!(f, "API错误 {status}: {body}"),
+            Self::BudgetExceeded { .. } => f.write_str("今日上游调用预算已用完"),
+        }
+    }
+}
+
+impl std::error::Error for TranslateError {}
@@ -0,0 +1,85 @@
+/// Names and ISO 639-2/3 codes clients send instead of the codes the backend
+/// expects, plus the Chinese regions that use traditional script (which the
+/// subtag fallback below would otherwise turn into simplified `zh`).
+const ALIASES: &[(&str, &str)] = &[
+    ("chinese", "zh"),
+    ("zho", "zh"),
+    ("chi", "zh"),
+    ("cn", "zh"),
+    ("zh-tw", "zh-Hant"),
+    ("zh-hk", "zh-Hant"),
+    ("zh-mo", "zh-Hant"),
+    ("english", "en"),
+    ("eng", "en"),
+    ("japanese", "ja"),
+    ("jpn", "ja"),
+    ("jp", "ja"),
+    ("korean", "ko"),
+    ("kor", "ko"),
+    ("german", "de"),
+    ("deu", "de"),
+    ("ger", "de"),
+    ("french", "fr"),
+    ("fra", "fr"),
+    ("fre", "fr"),
+    ("spanish", "es"),
+    ("spa", "es"),
+    ("italian", "it"),
+    ("ita", "it"),
+    ("portuguese", "pt"),
+    ("por", "pt"),
+    ("russian", "ru"),
+    ("rus", "ru"),
+    ("thai", "th"),
+    ("tha", "th"),
+    ("vietnamese", "vi"),
+    ("vie", "vi"),
+    ("arabic", "ar"),
+    ("ara", "ar"),
+];
+
+/// Maps `code` onto a code `is_supported` accepts, so `ZH`, `zh_CN`,
+/// `zh-Hans` and `chinese` all become `zh`. Tries the code as given, then,
+/// dropping BCP-47 subtags from the end (`pt-BR` -> `pt`), each prefix as an
+/// alias and in canonical casing (`zh-hant` -> `zh-Hant`). `None` if nothing
+/// matches.
+pub fn canonical_language(code: &str, is_supported: impl Fn(&str) -> bool) -> Option<String> {
+    let code = code.trim();
+    if is_supported(code) {
+        return Some(code.to_string());
+    }
+    let tag = code.replace('_', "-").to_ascii_lowercase();
+    let subtags: Vec<&str> = tag.split('-').collect();
+    (1..=subtags.len()).rev().find_map(|len| {
+        let prefix = subtags[..len].join("-");
+        let alias = ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == prefix)
+            .map(|(_, canonical)| canonical.to_string());
+        alias
+            .into_iter()
+            .chain([canonical_case(&subtags[..len])])
+            .find(|candidate| is_supported(candidate))
+    })
+}
+
+/// `zh-hant-tw` -> `zh-Hant-TW`: scripts title-cased, regions upper-cased.
+fn canonical_case(subtags: &[&str]) -> String {
+    let mut tag = String::new();
+    for (i, subtag) in subtags.iter().enumerate() {
+        if i > 0 {
+            tag.push('-');
+        }
+        match subtag.len() {
+            _ if i == 0 => tag.push_str(subtag),
+            4 => {
+                let (first, rest) = subtag.split_at(1);
+                tag.push_str(&first.to_ascii_uppercase());
+                tag.push_str(rest);
+            }
+            2 | 3 => tag.push_str(&subtag.to_ascii_uppercase()),
+            _ => tag.push_str(subtag),
+        }
+    }
+    tag
+}
@@ -0,0 +1,818 @@
+//! Translation core of the Doubao translator server.
+//!
+//! [`Translator`] splits long text into chunks, sends them to a
+//! [`TranslationProvider`] (the Doubao/ARK API by default) and caches both
+//! whole documents and individual chunks, so documents sharing paragraphs
+//! reuse each other's translations. The HTTP server in `main.rs` is a thin
+//! wrapper around it.
+//!
+//! ```
+//! use doubao_translator::{TranslateParams, Translator, TranslatorConfig};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), doubao_translator::TranslateError> {
+//! let translator = Translator::new(reqwest::Client::new(), TranslatorConfig::new("ark-api-key"));
+//!
+//! // Seed the cache so the example never reaches the network.
+//! let key = translator.cache().key("hello", &TranslateParams::new("zh"));
+//! translator.cache().set(key, "你好".to_string()).await;
+//!
+//! assert_eq!(translator.translate("hello", None, "zh").await?, "你好");
+//! # Ok(())
+//! # }
+//! ```
+
+mod artifacts;
+mod audit;
+mod budget;
+mod cache;
+mod circuit;
+mod detect;
+mod doubao;
+mod error;
+mod language;
+mod normalize;
+mod postprocess;
+mod protect;
+mod provider;
+mod quality;
+mod rate_limit;
+mod split;
+mod truncate;
+
+use std::{
+    borrow::Cow,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future;
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+pub use audit::AuditLog;
+pub use budget::{Budget, BudgetUsage};
+pub use cache::{Cache, CacheHit, CacheStats, MemoryEntry, RefreshGuard};
+pub use circuit::CircuitBreaker;
+pub use detect::{detect_between, detect_language};
+pub use doubao::DoubaoProvider;
+pub use error::TranslateError;
+pub use language::canonical_language;
+pub use normalize::normalize_input;
+pub use postprocess::{Pipeline, PostContext, PostProcessor, StripArtifacts};
+pub use provider::{MockProvider, ProviderOutput, TranslationProvider};
+pub use quality::{estimate_quality, Quality};
+pub use rate_limit::{LanguageRateLimiter, RateLimited, RateLimiter};
+pub use split::{
+    is_whitespace_only, split_markdown, split_text, split_text_limited, split_text_with,
+    DEFAULT_SENTENCE_TERMINATORS,
+};
+pub use truncate::truncate_graphemes;
+
+pub const DEFAULT_API_URL: &str = "https://ark.cn-beijing.volces.com/api/v3/responses";
+pub const DEFAULT_MODEL: &str = "doubao-seed-translation-250915";
+
+/// Temperature of the first alternative translation; each further one adds
+/// [`ALTERNATIVE_TEMPERATURE_STEP`].
+const ALTERNATIVE_TEMPERATURE: f32 = 0.7;
+const ALTERNATIVE_TEMPERATURE_STEP: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ApiFormat {
+    #[default]
+    Responses,
+    Chat,
+}
+
+impl std::str::FromStr for ApiFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "responses" => Ok(Self::Responses),
+            "chat" => Ok(Self::Chat),
+            other => Err(format!(
+                "unknown API format {other:?} (expected responses or chat)"
+            )),
+        }
+    }
+}
+
+/// How `Translator::split` finds chunk boundaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextFormat {
+    #[default]
+    Plain,
+    /// Keeps fenced code blocks whole and prefers breaking before headings.
+    Markdown,
+}
+
+impl TextFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Markdown => "markdown",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TranslatorConfig {
+    pub api_key: String,
+    pub api_url: String,
+    /// Tried in order when `api_url` is unreachable or answers with a 5xx.
+    pub api_url_backups: Vec<String>,
+    pub api_format: ApiFormat,
+    pub model: String,
+    /// Retried once, per chunk, when the primary model fails.
+    pub fallback_model: Option<String>,
+    pub max_chunk_chars: usize,
+    /// Paragraphs per plain-text chunk, however short they are (0 = no limit).
+    pub max_paragraphs_per_chunk: usize,
+    /// Characters that end a sentence, for splitting lines longer than a chunk.
+    pub sentence_terminators: String,
+    pub max_response_bytes: usize,
+    pub strip_model_artifacts: bool,
+    /// Ask once more when a non-blank chunk comes back blank.
+    pub retry_on_empty: bool,
+    /// Request SSE responses upstream for calls that pass a
+    /// [`TranslateParams::tokens`] sink, forwarding the output as it arrives.
+    pub upstream_streaming: bool,
+    /// Pass whitespace-only chunks through as is instead of sending them
+    /// upstream.
+    pub skip_whitespace_chunks: bool,
+    /// Upper bound on the previous-chunk context sent with `use_context`.
+    pub context_chars: usize,
+    /// Apply [`normalize_input`] before hashing and translating.
+    pub normalize_input: bool,
+}
+
+impl TranslatorConfig {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            api_url: DEFAULT_API_URL.to_string(),
+            api_url_backups: Vec::new(),
+            api_format: ApiFormat::default(),
+            model: DEFAULT_MODEL.to_string(),
+            fallback_model: None,
+            max_chunk_chars: 800,
+            max_paragraphs_per_chunk: 0,
+            sentence_terminators: DEFAULT_SENTENCE_TERMINATORS.to_string(),
+            max_response_bytes: 8 * 1024 * 1024,
+            strip_model_artifacts: false,
+            retry_on_empty: true,
+            upstream_streaming: false,
+            skip_whitespace_chunks: true,
+            context_chars: 200,
+            normalize_input: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Formality {
+    Formal,
+    Informal,
+}
+
+impl Formality {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Formal => "formal",
+            Self::Informal => "informal",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TranslateParams<'a> {
+    pub source: Option<&'a str>,
+    pub target: &'a str,
+    pub formality: Option<Formality>,
+    pub instruction: Option<&'a str>,
+    pub no_cache: bool,
+    pub format: TextFormat,
+    /// Skip cache reads but still store the fresh result, replacing any
+    /// stale entry.
+    pub refresh: bool,
+    /// Overrides [`TranslatorConfig::model`] for this call.
+    pub model: Option<&'a str>,
+    /// Return per-chunk source/translation pairs. Whole-document cache hits
+    /// carry no alignment, so only the chunk cache is consulted.
+    pub segments: bool,
+    /// Incremented as each chunk finishes, so callers that give up early can
+    /// report how far the translation got.
+    pub progress: Option<&'a AtomicUsize>,
+    /// Send the tail of the previous chunk along with each chunk, as context
+    /// the model should not translate.
+    pub use_context: bool,
+    /// The context for a single upstream call; set per chunk by the
+    /// translator when `use_context` is on.
+    pub context: Option<&'a str>,
+    /// Sampling temperature sent upstream; `None` keeps the model default.
+    pub temperature: Option<f32>,
+    /// Lifetime of the cache entries this call writes, instead of the
+    /// cache-wide TTL.
+    pub cache_ttl: Option<Duration>,
+    /// Receives each upstream call's output as it streams in, when
+    /// [`TranslatorConfig::upstream_streaming`] is on.
+    pub tokens: Option<TokenSink<'a>>,
+}
+
+/// A callback for streamed output. The pieces are a preview: they come
+/// before post-processing and placeholder restoration, and a retried call
+/// streams again, so the returned translation is what counts.
+#[derive(Clone, Copy)]
+pub struct TokenSink<'a>(pub &'a (dyn Fn(&str) + Send + Sync));
+
+impl fmt::Debug for TokenSink<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenSink")
+    }
+}
+
+impl<'a> TranslateParams<'a> {
+    pub fn new(target: &'a str) -> Self {
+        Self {
+            source: None,
+            target,
+            formality: None,
+            instruction: None,
+            no_cache: false,
+            format: TextFormat::Plain,
+            refresh: false,
+            model: None,
+            segments: false,
+            progress: None,
+            use_context: false,
+            context: None,
+            temperature: None,
+            cache_ttl: None,
+            tokens: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Translation {
+    pub text: String,
+    pub cached: bool,
+    /// Served from an expired cache entry while a background task refreshes it.
+    pub stale: bool,
+    pub skipped: bool,
+    /// A non-blank chunk came back blank (after the retry, if enabled).
+    pub empty_output: bool,
+    pub chunks: Vec<ChunkInfo>,
+    /// Source language reported upstream or guessed locally; only set when
+    /// the caller did not give one.
+    pub detected_source: Option<String>,
+    pub segments: Vec<Segment>,
+    /// The fallback model if it served any chunk, otherwise the primary one.
+    /// `None` when the translation was skipped.
+    pub model_used: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Segment {
+    pub source: String,
+    pub translation: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ChunkInfo {
+    pub chars: usize,
+    pub cached: bool,
+}
+
+#[derive(Clone)]
+pub struct Translator {
+    config: Arc<TranslatorConfig>,
+    provider: Arc<dyn TranslationProvider>,
+    cache: Cache,
+    breaker: Arc<CircuitBreaker>,
+    budget: Option<Arc<Budget>>,
+    protect_pattern: Option<Regex>,
+    redact_pattern: Option<Regex>,
+    post_processors: Pipeline,
+    chunk_cache: bool,
+}
+
+impl Translator {
+    pub fn new(client: Client, config: TranslatorConfig) -> Self {
+        let config = Arc::new(config);
+        let mut post_processors = Pipeline::new();
+        if config.strip_model_artifacts {
+            post_processors = post_processors.with(StripArtifacts);
+        }
+        Self {
+            provider: Arc::new(DoubaoProvider::new(client, Arc::clone(&config))),
+            config,
+            cache: Cache::new(1000, Duration::from_secs(3600)),
+            breaker: Arc::new(CircuitBreaker::disabled()),
+            budget: None,
+            protect_pattern: None,
+            redact_pattern: None,
+            post_processors,
+            chunk_cache: true,
+        }
+    }
+
+    pub fn with_provider(mut self, provider: Arc<dyn TranslationProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Appends `processor` to the output pipeline; `STRIP_MODEL_ARTIFACTS`,
+    /// when on, is always the first step.
+    pub fn with_post_processor(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.post_processors = self.post_processors.with(processor);
+        self
+    }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(Arc::new(budget));
+        self
+    }
+
+    pub fn budget(&self) -> Option<&Budget> {
+        self.budget.as_deref()
+    }
+
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Arc::new(breaker);
+        self
+    }
+
+    pub fn with_protect_pattern(mut self, pattern: Regex) -> Self {
+        self.protect_pattern = Some(pattern);
+        self
+    }
+
+    /// Masks matches of `pattern` (e.g. emails, phone numbers) before any text
+    /// is sent upstream and restores them in the output.
+    pub fn with_redact_pattern(mut self, pattern: Regex) -> Self {
+        self.redact_pattern = Some(pattern);
+        self
+    }
+
+    pub fn with_chunk_cache(mut self, enabled: bool) -> Self {
+        self.chunk_cache = enabled;
+        self
+    }
+
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    pub async fn translate(
+        &self,
+        text: &str,
+        source: Option<&str>,
+        target: &str,
+    ) -> Result<String, TranslateError> {
+        let params = TranslateParams {
+            source,
+            ..TranslateParams::new(target)
+        };
+        Ok(self.translate_with(text, &params).await?.text)
+    }
+
+    /// The chunks `translate_with` would send upstream for `text`.
+    pub fn split(&self, text: &str, format: TextFormat) -> Vec<String> {
+        let max = self.config.max_chunk_chars;
+        let terminators = &self.config.sentence_terminators;
+        match format {
+            TextFormat::Plain => {
+                split_text_limited(text, max, self.config.max_paragraphs_per_chunk, terminators)
+            }
+            TextFormat::Markdown => split_markdown(text, max, terminators),
+        }
+    }
+
+    /// Chunks are translated inside this future rather than on spawned
+    /// tasks, so dropping it (e.g. when the HTTP client disconnects) stops
+    /// any further upstream calls.
+    pub async fn translate_with(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<Translation, TranslateError> {
+        if params
+            .source
+            .is_some_and(|source| same_language(source, params.target))
+        {
+            let segments = if params.segments {
+                self.split(text, params.format)
+                    .into_iter()
+                    .map(|chunk| Segment {
+                        translation: chunk.clone(),
+                        source: chunk,
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            return Ok(Translation {
+                text: text.to_string(),
+                cached: false,
+                stale: false,
+                skipped: true,
+                empty_output: false,
+                chunks: Vec::new(),
+                detected_source: None,
+                segments,
+                model_used: None,
+            });
+        }
+
+        let normalized = if self.config.normalize_input {
+            normalize_input(text)
+        } else {
+            Cow::Borrowed(text)
+        };
+        let text = normalized.as_ref();
+
+        let model = params.model.unwrap_or(&self.config.model);
+        let cache_key = (!params.no_cache).then(|| self.cache.key(text, params));
+        let cached = match &cache_key {
+            Some(key) if !params.segments && !params.refresh => self.cache.lookup(key).await,
+            _ => None,
+        };
+        if let (Some(cached), Some(key)) = (cached, &cache_key) {
+            if cached.stale {
+                self.spawn_refresh(key, text, params);
+            }
+            let chunks = self
+                .split(text, params.format)
+                .iter()
+                .map(|chunk| ChunkInfo {
+                    chars: chunk.chars().count(),
+                    cached: true,
+                })
+                .collect();
+            return Ok(Translation {
+                text: cached.value,
+                cached: true,
+                stale: cached.stale,
+                skipped: false,
+                empty_output: false,
+                chunks,
+                detected_source: self.detect_source(text, params, None),
+                segments: Vec::new(),
+                model_used: Some(model.to_string()),
+            });
+        }
+
+        let redacted = self
+            .redact_pattern
+            .as_ref()
+            .map(|pattern| protect::redact(text, pattern))
+            .filter(|redacted| !redacted.is_empty());
+        if let Some(redacted) = &redacted {
+            println!(
+                "Redacted {} sensitive spans before translation",
+                redacted.len()
+            );
+        }
+        let redacted_text = redacted.as_ref().map_or(text, |r| r.text.as_str());
+        let protected = self
+            .protect_pattern
+            .as_ref()
+            .map(|pattern| protect::protect(redacted_text, pattern))
+            .filter(|protected| !protected.is_empty());
+        let source_text = protected
+            .as_ref()
+            .map_or(redacted_text, |p| p.text.as_str());
+
+        let chunks = self.split(source_text, params.format);
+        let mut results = Vec::with_capacity(chunks.len());
+        let mut infos = Vec::with_capacity(chunks.len());
+        let mut fresh = Vec::new();
+        let mut reported_source = None;
+        let mut used_fallback = false;
+        let mut empty_output = false;
+        let chunk_cache = self.chunk_cache && !params.no_cache;
+        for (i, chunk) in chunks.iter().enumerate() {
+            if self.config.skip_whitespace_chunks && is_whitespace_only(chunk) {
+                infos.push(ChunkInfo {
+                    chars: chunk.chars().count(),
+                    cached: false,
+                });
+                results.push(chunk.clone());
+                if let Some(progress) = params.progress {
+                    progress.fetch_add(1, Ordering::Relaxed);
+                }
+                continue;
+            }
+            let context = match i.checked_sub(1) {
+                Some(prev) if params.use_context => {
+                    context_tail(&chunks[prev], self.config.context_chars)
+                }
+                _ => None,
+            };
+            // The context changes the output, so it is part of the chunk key.
+            let params = &TranslateParams { context, ..*params };
+            let chunk_key = chunk_cache.then(|| self.cache.chunk_key(model, chunk, params));
+            let cached = match &chunk_key {
+                Some(key) if !params.refresh => self.cache.get(key).await,
+                _ => None,
+            };
+            infos.push(ChunkInfo {
+                chars: chunk.chars().count(),
+                cached: cached.is_some(),
+            });
+            match cached {
+                Some(translated) => results.push(translated),
+                None => {
+                    let (output, fallback) = self.call_upstream(chunk, params).await?;
+                    reported_source = reported_source.or(output.detected_source);
+                    used_fallback |= fallback.is_some();
+                    empty_output |= is_blank_output(chunk, &output.text);
+                    // Cache under the model that actually produced the text.
+                    let chunk_key = match fallback {
+                        Some(fallback) if chunk_cache => {
+                            Some(self.cache.chunk_key(fallback, chunk, params))
+                        }
+                        _ => chunk_key,
+                    };
+                    if let Some(key) = chunk_key {
+                        fresh.push((key, output.text.clone()));
+                    }
+                    results.push(output.text);
+                }
+            }
+            if let Some(progress) = params.progress {
+                progress.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut final_text = results.join("\n");
+        if let Some(protected) = &protected {
+            final_text = protected.restore(&final_text)?;
+        }
+        if let Some(redacted) = &redacted {
+            final_text = redacted.restore(&final_text)?;
+        }
+        let segments = if params.segments {
+            let unprotect = |chunk: &str| {
+                [&protected, &redacted]
+                    .into_iter()
+                    .flatten()
+                    .fold(chunk.to_string(), |chunk, masked| masked.unprotect(&chunk))
+            };
+            chunks
+                .iter()
+                .zip(&results)
+                .map(|(source, translation)| Segment {
+                    source: unprotect(source),
+                    translation: unprotect(translation),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        for (chunk_key, translated) in fresh {
+            self.cache
+                .set_with_ttl(chunk_key, translated, params.cache_ttl)
+                .await;
+        }
+        // Document keys are implicitly the primary model's.
+        if let Some(key) = cache_key.filter(|_| !used_fallback) {
+            self.cache
+                .set_translation(key, text, params, final_text.clone())
+                .await;
+        }
+        let model_used = match &self.config.fallback_model {
+            Some(fallback) if used_fallback => fallback.as_str(),
+            _ => model,
+        };
+
+        Ok(Translation {
+            text: final_text,
+            cached: !infos.is_empty() && infos.iter().all(|info| info.cached),
+            stale: false,
+            skipped: false,
+            empty_output,
+            chunks: infos,
+            detected_source: self.detect_source(text, params, reported_source),
+            segments,
+            model_used: Some(model_used.to_string()),
+        })
+    }
+
+    /// `n` candidate translations besides the primary one, each from its own
+    /// uncached upstream call at a progressively higher temperature.
+    pub async fn alternatives(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+        n: usize,
+    ) -> Result<Vec<String>, TranslateError> {
+        let candidates = (0..n).map(|i| async move {
+            let params = TranslateParams {
+                no_cache: true,
+                segments: false,
+                progress: None,
+                temperature: Some(
+                    ALTERNATIVE_TEMPERATURE + ALTERNATIVE_TEMPERATURE_STEP * i as f32,
+                ),
+                ..*params
+            };
+            Ok(self.translate_with(text, &params).await?.text)
+        });
+        future::try_join_all(candidates).await
+    }
+
+    /// Re-translates a stale document in the background, at most once per key
+    /// at a time; the fresh result replaces the cache entry. This is the one
+    /// detached task: it outlives the request that noticed the stale entry.
+    fn spawn_refresh(&self, key: &str, text: &str, params: &TranslateParams<'_>) {
+        let Some(guard) = self.cache.begin_refresh(key) else {
+            return;
+        };
+        let translator = self.clone();
+        let text = text.to_string();
+        let source = params.source.map(str::to_string);
+        let target = params.target.to_string();
+        let formality = params.formality;
+        let instruction = params.instruction.map(str::to_string);
+        let model = params.model.map(str::to_string);
+        let use_context = params.use_context;
+        let context = params.context.map(str::to_string);
+        let format = params.format;
+        let cache_ttl = params.cache_ttl;
+        tokio::spawn(async move {
+            let _guard = guard;
+            let params = TranslateParams {
+                source: source.as_deref(),
+                formality,
+                instruction: instruction.as_deref(),
+                model: model.as_deref(),
+                refresh: true,
+                use_context,
+                context: context.as_deref(),
+                format,
+                cache_ttl,
+                ..TranslateParams::new(&target)
+            };
+            if let Err(err) = translator.translate_with(&text, &params).await {
+                eprintln!("Background cache refresh failed: {err}");
+            }
+        });
+    }
+
+    fn detect_source(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+        reported: Option<String>,
+    ) -> Option<String> {
+        if params.source.is_some() {
+            return None;
+        }
+        reported.or_else(|| detect_language(text).map(str::to_string))
+    }
+
+    async fn call_upstream(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<(ProviderOutput, Option<&str>), TranslateError> {
+        if !self.breaker.allow() {
+            return Err(TranslateError::Unavailable(
+                "上游服务暂时不可用，请稍后再试".to_string(),
+            ));
+        }
+
+        let mut result = self.provider_call(text, params).await;
+        let mut fallback = None;
+        if let (Err(_), Some(model)) = (&result, self.config.fallback_model.as_deref()) {
+            let params = TranslateParams {
+                model: Some(model),
+                ..*params
+            };
+            result = self.provider_call(text, &params).await;
+            fallback = Some(model);
+        }
+        self.record_outcome(&result);
+        let mut output = result?;
+        if self.config.retry_on_empty && is_blank_output(text, &output.text) {
+            let params = TranslateParams {
+                model: fallback.or(params.model),
+                ..*params
+            };
+            let retry = self.provider_call(text, &params).await;
+            self.record_outcome(&retry);
+            output = retry?;
+        }
+        if !self.post_processors.is_empty() {
+            let ctx = PostContext {
+                source: text,
+                target: params.target,
+                model: fallback.or(params.model).unwrap_or(&self.config.model),
+            };
+            output.text = self.post_processors.run(output.text, &ctx);
+        }
+        Ok((output, fallback))
+    }
+
+    /// Summarizes an (already translated) `text` in `params.target`. Summaries
+    /// are cached like translations, honoring `no_cache` and `refresh`, and
+    /// count against the circuit breaker.
+    pub async fn summarize(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+        max_chars: usize,
+    ) -> Result<String, TranslateError> {
+        let target = params.target;
+        let key = (!params.no_cache).then(|| {
+            self.cache
+                .summary_key(&self.config.model, text, target, max_chars)
+        });
+        let cached = match &key {
+            Some(key) if !params.refresh => self.cache.get(key).await,
+            _ => None,
+        };
+        if let Some(summary) = cached {
+            return Ok(summary);
+        }
+        self.check_budget()?;
+        if !self.breaker.allow() {
+            return Err(TranslateError::Unavailable(
+                "上游服务暂时不可用，请稍后再试".to_string(),
+            ));
+        }
+        let result = self.provider.summarize(text, target, max_chars).await;
+        if let (Ok(_), Some(budget)) = (&result, &self.budget) {
+            budget.record(None);
+        }
+        self.record_outcome(&result);
+        let summary = result?;
+        if let Some(key) = key {
+            self.cache.set(key, summary.clone()).await;
+        }
+        Ok(summary)
+    }
+
+    /// One upstream translation call, counted against the daily budget.
+    async fn provider_call(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<ProviderOutput, TranslateError> {
+        self.check_budget()?;
+        let output = self.provider.translate_detailed(text, params).await?;
+        if let Some(budget) = &self.budget {
+            budget.record(output.tokens);
+        }
+        Ok(output)
+    }
+
+    fn check_budget(&self) -> Result<(), TranslateError> {
+        match &self.budget {
+            Some(budget) => budget
+                .check()
+                .map_err(|retry_after| TranslateError::BudgetExceeded { retry_after }),
+            None => Ok(()),
+        }
+    }
+
+    fn record_outcome<T>(&self, result: &Result<T, TranslateError>) {
+        match result {
+            Ok(_) => self.breaker.record_success(),
+            Err(err) if err.is_outage() => self.breaker.record_failure(),
+            Err(_) => self.breaker.release_probe(),
+        }
+    }
+}
+
+/// The last `max_chars` characters of `chunk`, or `None` if that is blank.
+fn context_tail(chunk: &str, max_chars: usize) -> Option<&str> {
+    let start = match max_chars.checked_sub(1) {
+        Some(skip) => chunk.char_indices().rev().nth(skip).map_or(0, |(i, _)| i),
+        None => chunk.len(),
+    };
+    Some(chunk[start..].trim()).filter(|tail| !tail.is_empty())
+}
+
+fn is_blank_output(source: &str, output: &str) -> bool {
+    output.trim().is_empty() && !source.trim().is_empty()
+}
+
+pub fn same_language(source: &str, target: &str) -> bool {
+    source.trim().eq_ignore_ascii_case(target.trim())
+}
@@ -1,537 +1,3965 @@
+use arc_swap::ArcSwap;
+use axum::routing::get_service;
 use axum::{
-    extract::State,
-    http::StatusCode,
+    async_trait,
+    body::{Body, BodyDataStream, Bytes},
+    extract::{
+        multipart::{MultipartError, MultipartRejection},
+        rejection::JsonRejection,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, FromRequest, FromRequestParts, Multipart, Query, Request,
+        State,
+    },
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
     routing::{get, post},
     Json, Router,
 };
+use axum_server::{accept::Accept, tls_rustls::RustlsConfig};
 use dotenvy::dotenv;
-use lru::LruCache;
+use doubao_translator::{
+    canonical_language, detect_between, estimate_quality, truncate_graphemes, AuditLog, Budget,
+    Cache, ChunkInfo, CircuitBreaker, DoubaoProvider, Formality, LanguageRateLimiter, MemoryEntry,
+    MockProvider, Quality, RateLimited, RateLimiter, Segment, TextFormat, TokenSink,
+    TranslateError, TranslateParams, Translation, Translator, TranslatorConfig,
+};
+use futures::{channel::mpsc, future, stream, Stream, StreamExt};
+use hyper_util::{
+    rt::{TokioExecutor, TokioTimer},
+    server::conn::auto,
+};
+use regex::Regex;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::{
-    collections::VecDeque,
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
     env,
-    num::NonZeroUsize,
-    sync::Arc,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::Mutex;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::{Mutex, OnceCell, OwnedSemaphorePermit, Semaphore},
+};
+use tokio_util::sync::CancellationToken;
+use tower_http::services::ServeFile;
 use tower_http::{
-    cors::CorsLayer,
+    cors::{Any, CorsLayer},
     services::ServeDir,
 };
-use axum::routing::get_service;
-use tower_http::services::ServeFile;
 
 #[derive(Clone)]
 struct AppState {
-    config: Config,
-    client: Client,
-    cache: Cache,
+    config: Arc<ArcSwap<Config>>,
+    translator: Translator,
     limiter: RateLimiter,
+    language_limiter: LanguageRateLimiter,
+    api_keys: Arc<HashMap<String, Arc<Semaphore>>>,
+    idempotency: IdempotencyStore,
+    jobs: JobStore,
+    inflight: Option<Arc<Semaphore>>,
+    audit: Option<AuditLog>,
+    /// Talks to the Doubao API directly for `POST /api/debug/raw`.
+    raw_provider: Arc<DoubaoProvider>,
 }
 
-#[derive(Clone)]
-struct Config {
-    api_key: String,
-    api_url: String,
-    port: u16,
-    cache_ttl: Duration,
-    cache_max_size: usize,
-    max_text_length: usize,
-    rate_limit_rpm: usize,
-}
-
-#[derive(Debug, Deserialize)]
-struct TranslateRequest {
-    text: String,
-    source: Option<String>,
-    target: String,
-}
-
-#[derive(Serialize)]
-struct TranslateResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    text: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    cached: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+impl AppState {
+    /// The live configuration; `POST /api/admin/reload` may swap it at any time.
+    fn config(&self) -> Arc<Config> {
+        self.config.load_full()
+    }
 }
 
-#[derive(Serialize)]
-struct DoubaoRequest {
-    model: String,
-    input: Vec<DoubaoInputMessage>,
+/// Successful responses remembered per `Idempotency-Key` for a short window.
+#[derive(Clone)]
+struct IdempotencyStore {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, IdempotencyEntry>>>,
 }
 
-#[derive(Serialize)]
-struct DoubaoInputMessage {
-    role: String,
-    content: Vec<DoubaoContent>,
+struct IdempotencyEntry {
+    created: Instant,
+    fingerprint: String,
+    response: Arc<OnceCell<TranslateResponse>>,
 }
 
-#[derive(Serialize)]
-struct DoubaoContent {
-    #[serde(rename = "type")]
-    content_type: String,
-    text: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    translation_options: Option<TranslationOptions>,
-}
+impl IdempotencyStore {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::default(),
+        }
+    }
 
-#[derive(Serialize)]
-struct TranslationOptions {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    source_language: Option<String>,
-    target_language: String,
+    /// The shared slot for `key`, or `None` if the key was already used for a
+    /// different request body.
+    async fn slot(
+        &self,
+        key: String,
+        fingerprint: String,
+    ) -> Option<Arc<OnceCell<TranslateResponse>>> {
+        let mut entries = self.entries.lock().await;
+        entries.retain(|_, entry| entry.created.elapsed() < self.ttl);
+        let entry = entries.entry(key).or_insert_with(|| IdempotencyEntry {
+            created: Instant::now(),
+            fingerprint: fingerprint.clone(),
+            response: Arc::default(),
+        });
+        (entry.fingerprint == fingerprint).then(|| Arc::clone(&entry.response))
+    }
 }
 
+/// Batch jobs from `POST /api/jobs`, kept for `ttl` after they finish.
 #[derive(Clone)]
-struct Cache {
+struct JobStore {
     ttl: Duration,
-    inner: Arc<Mutex<LruCache<String, CacheEntry>>>,
+    /// One permit per job allowed to run at once (`MAX_RUNNING_JOBS`).
+    running: Arc<Semaphore>,
+    jobs: Arc<Mutex<HashMap<String, Arc<Job>>>>,
 }
 
-#[derive(Clone)]
-struct CacheEntry {
-    value: String,
-    expires_at: Instant,
+struct Job {
+    cancel: CancellationToken,
+    progress: std::sync::Mutex<JobProgress>,
 }
 
-#[derive(Clone)]
-struct RateLimiter {
-    window: Duration,
-    max: usize,
-    hits: Arc<Mutex<VecDeque<Instant>>>,
+struct JobProgress {
+    status: JobStatus,
+    finished: Option<Instant>,
+    completed: usize,
+    failed: usize,
+    results: Vec<Option<TranslateResponse>>,
 }
 
-#[tokio::main]
-async fn main() {
-    dotenv().ok();
-    let config = match load_config() {
-        Ok(cfg) => cfg,
-        Err(err) => {
-            eprintln!("Config error: {err}");
-            std::process::exit(1);
-        }
-    };
-
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-        .expect("failed to build HTTP client");
-
-    let cache = Cache::new(config.cache_max_size, config.cache_ttl);
-    let limiter = RateLimiter::new(Duration::from_secs(60), config.rate_limit_rpm);
-
-    let state = AppState {
-        config,
-        client,
-        cache,
-        limiter,
-    };
-
-    let addr = format!("0.0.0.0:{}", state.config.port);
-
-    let static_service = ServeDir::new("static");
-    let libs_service = ServeDir::new("static/libs");
-
-    let app = Router::new()
-        .route("/api/translate", post(translate_handler))
-        .route("/api/languages", get(languages_handler))
-        .route("/api/health", get(health_handler))
-        .nest_service("/static", static_service)
-        .nest_service("/libs", libs_service)
-        .route("/", get_service(ServeFile::new("static/index.html")))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
-    println!("Server listening on {addr}");
-
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .expect("failed to bind address");
-    axum::serve(listener, app)
-        .await
-        .expect("server error");
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    Running,
+    Completed,
+    Cancelled,
 }
 
-async fn translate_handler(
-    State(state): State<AppState>,
-    Json(payload): Json<TranslateRequest>,
-) -> (StatusCode, Json<TranslateResponse>) {
-    if !state.limiter.allow().await {
-        return (
-            StatusCode::TOO_MANY_REQUESTS,
-            Json(TranslateResponse {
-                success: false,
-                text: None,
-                cached: None,
-                error: Some("请求过于频繁，请稍后再试".to_string()),
-            }),
-        );
+impl JobStore {
+    fn new(ttl: Duration, max_running: usize) -> Self {
+        Self {
+            ttl,
+            running: Arc::new(Semaphore::new(max_running)),
+            jobs: Arc::default(),
+        }
     }
 
-    let source = payload.source.as_deref().filter(|s| !s.is_empty());
-
-    let text_len = payload.text.chars().count();
-    if text_len == 0 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(TranslateResponse {
-                success: false,
-                text: None,
-                cached: None,
-                error: Some("文本不能为空".to_string()),
-            }),
-        );
-    }
-    if text_len > state.config.max_text_length {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(TranslateResponse {
-                success: false,
-                text: None,
-                cached: None,
-                error: Some(format!("文本长度超过限制（最大{}字符）", state.config.max_text_length)),
-            }),
-        );
+    /// A running-job slot, held until the job finishes or is cancelled.
+    fn reserve(&self) -> Option<OwnedSemaphorePermit> {
+        Arc::clone(&self.running).try_acquire_owned().ok()
     }
 
-    if payload.target.trim().is_empty() {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(TranslateResponse {
-                success: false,
-                text: None,
-                cached: None,
-                error: Some("目标语言不能为空".to_string()),
+    async fn insert(&self, total: usize) -> (String, Arc<Job>) {
+        let id = format!("{:016x}{:016x}", fastrand::u64(..), fastrand::u64(..));
+        let job = Arc::new(Job {
+            cancel: CancellationToken::new(),
+            progress: std::sync::Mutex::new(JobProgress {
+                status: JobStatus::Running,
+                finished: None,
+                completed: 0,
+                failed: 0,
+                results: vec![None; total],
             }),
-        );
+        });
+        let mut jobs = self.jobs.lock().await;
+        jobs.retain(|_, job| {
+            let progress = job.progress.lock().unwrap();
+            progress.finished.is_none_or(|at| at.elapsed() < self.ttl)
+        });
+        jobs.insert(id.clone(), Arc::clone(&job));
+        (id, job)
     }
 
-    let cache_key = build_cache_key(&payload.text, source, &payload.target);
-    if let Some(cached) = state.cache.get(&cache_key).await {
-        return (
-            StatusCode::OK,
-            Json(TranslateResponse {
-                success: true,
-                text: Some(cached),
-                cached: Some(true),
-                error: None,
-            }),
-        );
+    async fn get(&self, id: &str) -> Option<Arc<Job>> {
+        self.jobs.lock().await.get(id).cloned()
     }
+}
 
-    let chunks = split_text(&payload.text, 800);
-    let mut results = Vec::with_capacity(chunks.len());
-
-    for chunk in chunks {
-        match translate_chunk(&state, &chunk, source, &payload.target).await {
-            Ok(text) => results.push(text),
-            Err(err) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(TranslateResponse {
-                        success: false,
-                        text: None,
-                        cached: None,
-                        error: Some(format!("翻译失败: {err}")),
-                    }),
-                );
-            }
+impl Job {
+    fn record(&self, index: usize, response: TranslateResponse) {
+        let mut progress = self.progress.lock().unwrap();
+        if response.success {
+            progress.completed += 1;
+        } else {
+            progress.failed += 1;
         }
+        progress.results[index] = Some(response);
     }
 
-    let final_text = results.join("\n");
-    state.cache.set(cache_key, final_text.clone()).await;
-
-    (
-        StatusCode::OK,
-        Json(TranslateResponse {
-            success: true,
-            text: Some(final_text),
-            cached: Some(false),
-            error: None,
-        }),
-    )
-}
-
-async fn translate_chunk(
-    state: &AppState,
-    text: &str,
-    source: Option<&str>,
-    target: &str,
-) -> Result<String, String> {
-    let req_body = DoubaoRequest {
-        model: "doubao-seed-translation-250915".to_string(),
-        input: vec![DoubaoInputMessage {
-            role: "user".to_string(),
-            content: vec![DoubaoContent {
-                content_type: "input_text".to_string(),
-                text: text.to_string(),
-                translation_options: Some(TranslationOptions {
-                    source_language: source.map(|s| s.to_string()),
-                    target_language: target.to_string(),
-                }),
-            }],
-        }],
-    };
-
-    let resp = state
-        .client
-        .post(&state.config.api_url)
-        .bearer_auth(&state.config.api_key)
-        .json(&req_body)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP请求失败: {e}"))?;
-
-    let status = resp.status();
-    let body = resp
-        .text()
-        .await
-        .map_err(|e| format!("读取响应失败: {e}"))?;
+    /// Moves a running job to `status`; a finished job keeps its status.
+    fn finish(&self, status: JobStatus) {
+        let mut progress = self.progress.lock().unwrap();
+        if progress.status == JobStatus::Running {
+            progress.status = status;
+            progress.finished = Some(Instant::now());
+        }
+    }
 
-    if !status.is_success() {
-        return Err(format!("API错误 {}: {}", status.as_u16(), body));
+    fn snapshot(&self, id: &str) -> Value {
+        let progress = self.progress.lock().unwrap();
+        json!({
+            "job_id": id,
+            "status": progress.status,
+            "total": progress.results.len(),
+            "completed": progress.completed,
+            "failed": progress.failed,
+            "results": progress.results,
+        })
     }
+}
 
-    parse_doubao_response(&body).map_err(|e| format!("响应解析失败: {e}"))
+#[derive(Clone)]
+struct Config {
+    translator: TranslatorConfig,
+    provider: String,
+    port: u16,
+    tls: Option<(String, String)>,
+    serve_static: bool,
+    static_dir: PathBuf,
+    cache_ttl: Duration,
+    /// Upper bound on a request's `cache_ttl_secs`.
+    max_request_cache_ttl: Duration,
+    cache_ttl_jitter_pct: u8,
+    stale_while_revalidate: Duration,
+    cache_key_prefix: String,
+    cache_max_size: usize,
+    cache_key_collapse_whitespace: bool,
+    cache_chunks: bool,
+    max_text_length: usize,
+    max_chunks: usize,
+    max_targets: usize,
+    max_alternatives: usize,
+    max_upload_bytes: usize,
+    max_body_bytes: usize,
+    total_timeout: Option<Duration>,
+    idempotency_ttl: Duration,
+    server_api_keys: Vec<String>,
+    enable_debug_endpoints: bool,
+    trust_proxy: bool,
+    /// Chance of failing a translate request on purpose, and the status used.
+    fault_injection: Option<(f64, StatusCode)>,
+    per_key_concurrency: usize,
+    max_inflight_requests: usize,
+    max_connections: usize,
+    ndjson_concurrency: usize,
+    stream_concurrency: usize,
+    job_concurrency: usize,
+    max_job_items: usize,
+    job_ttl: Duration,
+    max_running_jobs: usize,
+    rate_limit_rpm: usize,
+    ws_rate_limit_rpm: usize,
+    rate_limit_sweep_interval: Duration,
+    rate_limit_per_lang: Vec<(String, usize)>,
+    circuit_fail_threshold: u32,
+    circuit_cooldown: Duration,
+    daily_request_budget: Option<u64>,
+    daily_token_budget: Option<u64>,
+    budget_state_file: Option<String>,
+    /// `-` (or `stdout`) writes audit records to stdout.
+    audit_log_file: Option<String>,
+    languages: Arc<BTreeMap<String, String>>,
+    languages_response: Arc<LanguagesResponse>,
+    default_source: Option<String>,
+    require_source: bool,
+    cache_seed_file: Option<String>,
+    default_instruction: Option<String>,
+    protect_pattern: Option<Regex>,
+    response_schema: ResponseSchema,
+    /// `(category, pattern)`; input matching any of them is refused.
+    deny_patterns: Vec<(String, Regex)>,
+    redact_pattern: Option<Regex>,
+    /// Limit on a single upstream call; `TOTAL_TIMEOUT_SECS` bounds the request.
+    http_request_timeout: Duration,
+    http_pool_max_idle: usize,
+    http_pool_idle_timeout: Duration,
+    http_tcp_keepalive: Duration,
+    http_proxy: Option<String>,
+    http_disable_proxy: bool,
+    http_user_agent: String,
+    http_extra_headers: HeaderMap,
+    enable_http2: bool,
+    http2_keepalive: Option<Duration>,
+    verify_key_on_start: bool,
+    run_selftest: bool,
+    /// Lets the self-test make one real upstream translation.
+    selftest_upstream: bool,
+    log_translation_content: bool,
+    /// The raw values this config was built from, to tell what a reload changed.
+    sources: BTreeMap<&'static str, String>,
 }
 
-fn parse_doubao_response(body: &str) -> Result<String, String> {
-    let value: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+/// Settings `POST /api/admin/reload` applies; the rest are read once at
+/// startup and need a restart.
+const RELOADABLE_KEYS: &[&str] = &[
+    "DEFAULT_INSTRUCTION",
+    "DEFAULT_SOURCE_LANGUAGE",
+    "JOB_CONCURRENCY",
+    "LANGUAGES_FILE",
+    "MAX_ALTERNATIVES",
+    "MAX_CHUNKS",
+    "MAX_JOB_ITEMS",
+    "MAX_TARGETS",
+    "MAX_TEXT_LENGTH",
+    "NDJSON_CONCURRENCY",
+    "RATE_LIMIT_RPM",
+    "REQUIRE_SOURCE",
+    "STREAM_CONCURRENCY",
+    "TOTAL_TIMEOUT_SECS",
+    "WS_RATE_LIMIT_RPM",
+];
 
-    if value.get("status").and_then(|v| v.as_str()) == Some("completed") {
-        if let Some(output) = value.get("output").and_then(|v| v.as_array()) {
-            for item in output {
-                let is_message = item.get("type").and_then(|v| v.as_str()) == Some("message");
-                let is_assistant = item.get("role").and_then(|v| v.as_str()) == Some("assistant");
-                if !is_message || !is_assistant {
-                    continue;
-                }
-                if let Some(content) = item.get("content").and_then(|v| v.as_array()) {
-                    for part in content {
-                        let is_output = part.get("type").and_then(|v| v.as_str()) == Some("output_text");
-                        if is_output {
-                            if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
-                                return Ok(text.to_string());
-                            }
-                        }
-                    }
-                }
-            }
+impl Config {
+    /// `self` with the reloadable settings taken from `fresh`.
+    fn reloaded(&self, fresh: Config) -> Config {
+        let sources = CONFIG_KEYS
+            .iter()
+            .filter_map(|key| {
+                let from = if RELOADABLE_KEYS.contains(key) {
+                    &fresh
+                } else {
+                    self
+                };
+                from.sources.get(key).map(|value| (*key, value.clone()))
+            })
+            .collect();
+        Config {
+            languages: fresh.languages,
+            languages_response: fresh.languages_response,
+            default_source: fresh.default_source,
+            require_source: fresh.require_source,
+            default_instruction: fresh.default_instruction,
+            max_text_length: fresh.max_text_length,
+            max_chunks: fresh.max_chunks,
+            max_targets: fresh.max_targets,
+            max_alternatives: fresh.max_alternatives,
+            total_timeout: fresh.total_timeout,
+            ndjson_concurrency: fresh.ndjson_concurrency,
+            stream_concurrency: fresh.stream_concurrency,
+            job_concurrency: fresh.job_concurrency,
+            max_job_items: fresh.max_job_items,
+            rate_limit_rpm: fresh.rate_limit_rpm,
+            ws_rate_limit_rpm: fresh.ws_rate_limit_rpm,
+            sources,
+            ..self.clone()
         }
-        return Err("new format missing output_text".to_string());
     }
 
-    if let Some(choices) = value.get("choices").and_then(|v| v.as_array()) {
-        if let Some(choice) = choices.first() {
-            if let Some(content) = choice
-                .get("message")
-                .and_then(|m| m.get("content"))
-                .and_then(|v| v.as_str())
-            {
-                return Ok(content.to_string());
-            }
-        }
+    /// Settings that differ in `fresh` but only take effect after a restart.
+    fn restart_only_changes(&self, fresh: &Config) -> Vec<&'static str> {
+        CONFIG_KEYS
+            .iter()
+            .copied()
+            .filter(|key| !RELOADABLE_KEYS.contains(key))
+            .filter(|key| self.sources.get(key) != fresh.sources.get(key))
+            .collect()
     }
+}
 
-    Err("unknown response format".to_string())
+#[derive(Debug, Clone, Default, Deserialize)]
+struct TranslateRequest {
+    text: String,
+    source: Option<String>,
+    #[serde(default)]
+    target: String,
+    targets: Option<Vec<String>>,
+    /// Two languages; the text is translated from whichever it is written
+    /// in to the other, replacing `source` and `target`.
+    pair: Option<Vec<String>>,
+    formality: Option<Formality>,
+    instruction: Option<String>,
+    #[serde(default)]
+    verbose: bool,
+    #[serde(default)]
+    timing: bool,
+    /// Add a heuristic `quality` estimate for review triage.
+    #[serde(default)]
+    quality: bool,
+    #[serde(default)]
+    no_cache: bool,
+    #[serde(default)]
+    format: TextFormat,
+    #[serde(default)]
+    refresh: bool,
+    /// Send each chunk with the tail of the previous one as context.
+    #[serde(default)]
+    use_context: bool,
+    #[serde(default)]
+    include_source: bool,
+    max_output_chars: Option<usize>,
+    #[serde(default)]
+    summarize: bool,
+    summary_max_chars: Option<usize>,
+    /// Extra candidate translations to return alongside `text`.
+    alternatives: Option<usize>,
+    /// Overrides `CACHE_TTL` for the entries this request writes.
+    cache_ttl_secs: Option<u64>,
 }
 
-async fn languages_handler() -> Json<Value> {
-    Json(json!({
-        "success": true,
-        "languages": {
-            "zh": "中文（简体）",
-            "zh-Hant": "中文（繁体）",
-            "en": "英语",
-            "ja": "日语",
-            "ko": "韩语",
-            "de": "德语",
-            "fr": "法语",
-            "es": "西班牙语",
-            "it": "意大利语",
-            "pt": "葡萄牙语",
-            "ru": "俄语",
-            "th": "泰语",
-            "vi": "越南语",
-            "ar": "阿拉伯语"
-        }
-    }))
+#[derive(Debug, Deserialize)]
+struct TranslateOptions {
+    #[serde(default)]
+    verbose: bool,
+    #[serde(default)]
+    timing: bool,
+    #[serde(default)]
+    quality: bool,
 }
 
-async fn health_handler() -> Json<Value> {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-    Json(json!({ "status": "healthy", "time": now }))
+#[derive(Debug, Deserialize)]
+struct TranslateJsonRequest {
+    data: Value,
+    source: Option<String>,
+    #[serde(default)]
+    target: String,
+    formality: Option<Formality>,
+    instruction: Option<String>,
+    /// String leaves matching this are returned untranslated.
+    skip_pattern: Option<String>,
 }
 
-impl Cache {
-    fn new(max_size: usize, ttl: Duration) -> Self {
-        let max = NonZeroUsize::new(max_size.max(1)).unwrap();
-        Self {
-            ttl,
-            inner: Arc::new(Mutex::new(LruCache::new(max))),
-        }
-    }
+type ApiResponse = (StatusCode, Json<TranslateResponse>);
+
+const DEFAULT_SUMMARY_MAX_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum ErrorCode {
+    Unauthorized,
+    AdminDisabled,
+    ContentBlocked,
+    RateLimited,
+    ConcurrencyLimited,
+    BudgetExceeded,
+    InvalidRequest,
+    BadJson,
+    EmptyText,
+    TextTooLong,
+    TooManyChunks,
+    InvalidTarget,
+    MissingSource,
+    TooManyTargets,
+    FileTooLarge,
+    UnsupportedMediaType,
+    IdempotencyConflict,
+    JobNotFound,
+    TooManyJobs,
+    InjectedFault,
+    InvalidEncoding,
+    UpstreamError,
+    PlaceholderMismatch,
+    UpstreamTimeout,
+    DeadlineExceeded,
+    ServiceUnavailable,
+    InvalidConfig,
+    InternalError,
+}
 
-    async fn get(&self, key: &str) -> Option<String> {
-        let mut cache = self.inner.lock().await;
-        if let Some(entry) = cache.get(key) {
-            if Instant::now() <= entry.expires_at {
-                return Some(entry.value.clone());
+impl ErrorCode {
+    fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::AdminDisabled | ErrorCode::ContentBlocked => StatusCode::FORBIDDEN,
+            ErrorCode::RateLimited | ErrorCode::ConcurrencyLimited | ErrorCode::BudgetExceeded => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ErrorCode::InvalidRequest
+            | ErrorCode::BadJson
+            | ErrorCode::EmptyText
+            | ErrorCode::TextTooLong
+            | ErrorCode::TooManyChunks
+            | ErrorCode::InvalidTarget
+            | ErrorCode::MissingSource
+            | ErrorCode::TooManyTargets
+            | ErrorCode::InvalidEncoding => StatusCode::BAD_REQUEST,
+            ErrorCode::FileTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::IdempotencyConflict => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::JobNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::UpstreamError | ErrorCode::PlaceholderMismatch => StatusCode::BAD_GATEWAY,
+            ErrorCode::UpstreamTimeout | ErrorCode::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::ServiceUnavailable | ErrorCode::TooManyJobs | ErrorCode::InjectedFault => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ErrorCode::InvalidConfig | ErrorCode::InternalError => {
+                StatusCode::INTERNAL_SERVER_ERROR
             }
         }
-        cache.pop(key);
-        None
     }
+}
 
-    async fn set(&self, key: String, value: String) {
-        let entry = CacheEntry {
-            value,
-            expires_at: Instant::now() + self.ttl,
-        };
-        let mut cache = self.inner.lock().await;
-        cache.put(key, entry);
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Locale {
+    Zh,
+    En,
 }
 
-impl RateLimiter {
-    fn new(window: Duration, max: usize) -> Self {
-        Self {
-            window,
-            max: max.max(1),
-            hits: Arc::new(Mutex::new(VecDeque::new())),
-        }
-    }
+impl Locale {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(value) = headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+        else {
+            return Locale::Zh;
+        };
 
-    async fn allow(&self) -> bool {
-        let now = Instant::now();
-        let mut hits = self.hits.lock().await;
-        while let Some(front) = hits.front() {
-            if now.duration_since(*front) > self.window {
-                hits.pop_front();
-            } else {
-                break;
+        let mut best: Option<(Locale, f32)> = None;
+        for entry in value.split(',') {
+            let mut parts = entry.split(';');
+            let tag = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let locale = match tag.split('-').next() {
+                Some("zh") => Locale::Zh,
+                Some("en") => Locale::En,
+                _ => continue,
+            };
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((locale, quality));
             }
         }
-        if hits.len() >= self.max {
-            return false;
-        }
-        hits.push_back(now);
-        true
+        best.map_or(Locale::Zh, |(locale, _)| locale)
     }
 }
 
-fn build_cache_key(text: &str, source: Option<&str>, target: &str) -> String {
-    let base = format!("{}|{}|{}", source.unwrap_or(""), target, text);
-    format!("{:x}", md5::compute(base))
+enum ApiError<'a> {
+    Unauthorized,
+    AdminDisabled,
+    RateLimited(RateLimited),
+    LanguageRateLimited(&'a str, RateLimited),
+    ConcurrencyLimited,
+    Overloaded,
+    InvalidRequest(String),
+    BadJson(String),
+    EmptyText,
+    TextTooLong(usize),
+    TooManyChunks { chunks: usize, max: usize },
+    EmptyTarget,
+    UnsupportedTarget(&'a str),
+    EmptyTargets,
+    MissingSource,
+    TooManyTargets(usize),
+    MissingFile,
+    FileTooLarge(usize),
+    NotUtf8,
+    UnsupportedMediaType(&'static str),
+    ContentBlocked,
+    IdempotencyConflict,
+    JobNotFound,
+    TooManyJobs(usize),
+    InjectedFault,
+    InvalidConfig(String),
+    DeadlineExceeded { secs: u64, completed: usize },
+    Translate(&'a TranslateError),
+    TranslateTarget(&'a str, &'a TranslateError),
 }
 
-fn split_text(text: &str, max_chars: usize) -> Vec<String> {
-    if text.chars().count() <= max_chars {
-        return vec![text.to_string()];
+impl ApiError<'_> {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::Unauthorized => ErrorCode::Unauthorized,
+            ApiError::AdminDisabled => ErrorCode::AdminDisabled,
+            ApiError::RateLimited(_) | ApiError::LanguageRateLimited(..) => ErrorCode::RateLimited,
+            ApiError::ConcurrencyLimited => ErrorCode::ConcurrencyLimited,
+            ApiError::Overloaded => ErrorCode::ServiceUnavailable,
+            ApiError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            ApiError::BadJson(_) => ErrorCode::BadJson,
+            ApiError::EmptyText => ErrorCode::EmptyText,
+            ApiError::TextTooLong(_) => ErrorCode::TextTooLong,
+            ApiError::TooManyChunks { .. } => ErrorCode::TooManyChunks,
+            ApiError::EmptyTarget | ApiError::UnsupportedTarget(_) | ApiError::EmptyTargets => {
+                ErrorCode::InvalidTarget
+            }
+            ApiError::MissingSource => ErrorCode::MissingSource,
+            ApiError::TooManyTargets(_) => ErrorCode::TooManyTargets,
+            ApiError::MissingFile => ErrorCode::InvalidRequest,
+            ApiError::FileTooLarge(_) => ErrorCode::FileTooLarge,
+            ApiError::NotUtf8 => ErrorCode::InvalidEncoding,
+            ApiError::UnsupportedMediaType(_) => ErrorCode::UnsupportedMediaType,
+            ApiError::IdempotencyConflict => ErrorCode::IdempotencyConflict,
+            ApiError::JobNotFound => ErrorCode::JobNotFound,
+            ApiError::TooManyJobs(_) => ErrorCode::TooManyJobs,
+            ApiError::InjectedFault => ErrorCode::InjectedFault,
+            ApiError::ContentBlocked => ErrorCode::ContentBlocked,
+            ApiError::InvalidConfig(_) => ErrorCode::InvalidConfig,
+            ApiError::DeadlineExceeded { .. } => ErrorCode::DeadlineExceeded,
+            ApiError::Translate(err) | ApiError::TranslateTarget(_, err) => (*err).into(),
+        }
     }
 
-    let paragraphs: Vec<&str> = text.split("\n\n").collect();
-    let mut chunks = Vec::new();
-    let mut current = String::new();
-    let mut current_len = 0usize;
-
-    for paragraph in paragraphs {
-        let para_len = paragraph.chars().count();
-        if para_len > max_chars {
-            if !current.is_empty() {
-                chunks.push(current);
-                current = String::new();
-                current_len = 0;
+    fn message(&self, locale: Locale) -> String {
+        match (self, locale) {
+            (ApiError::Unauthorized, Locale::Zh) => "缺少或无效的 API 密钥".into(),
+            (ApiError::Unauthorized, Locale::En) => "Missing or invalid API key".into(),
+            (ApiError::AdminDisabled, Locale::Zh) => "管理接口需要先配置 SERVER_API_KEYS".into(),
+            (ApiError::AdminDisabled, Locale::En) => {
+                "Admin endpoints require SERVER_API_KEYS to be set".into()
             }
-            for part in split_by_chars(paragraph, max_chars) {
-                chunks.push(part);
+            (ApiError::ConcurrencyLimited, Locale::Zh) => "该密钥的并发请求过多，请稍后再试".into(),
+            (ApiError::ConcurrencyLimited, Locale::En) => {
+                "Too many concurrent requests for this API key".into()
             }
-            continue;
-        }
-
-        let extra = if current.is_empty() { 0 } else { 2 };
-        if !current.is_empty() && current_len + extra + para_len > max_chars {
-            chunks.push(current);
-            current = paragraph.to_string();
-            current_len = para_len;
-        } else {
-            if !current.is_empty() {
-                current.push_str("\n\n");
-                current_len += 2;
+            (ApiError::Overloaded, Locale::Zh) => "服务器繁忙，请稍后再试".into(),
+            (ApiError::Overloaded, Locale::En) => {
+                "The server is busy, please try again shortly".into()
+            }
+            (ApiError::RateLimited(_), Locale::Zh) => "请求过于频繁，请稍后再试".into(),
+            (ApiError::RateLimited(_), Locale::En) => {
+                "Too many requests, please try again later".into()
+            }
+            (ApiError::LanguageRateLimited(lang, _), Locale::Zh) => {
+                format!("翻译到 {lang} 的请求过于频繁，请稍后再试")
+            }
+            (ApiError::LanguageRateLimited(lang, _), Locale::En) => {
+                format!("Too many requests for target language {lang}, please try again later")
+            }
+            (ApiError::InvalidRequest(err), Locale::Zh) => format!("无效的请求: {err}"),
+            (ApiError::InvalidRequest(err), Locale::En) => format!("Invalid request: {err}"),
+            (ApiError::BadJson(err), Locale::Zh) => format!("请求体不是有效的 JSON: {err}"),
+            (ApiError::BadJson(err), Locale::En) => format!("Malformed JSON body: {err}"),
+            (ApiError::EmptyText, Locale::Zh) => "文本不能为空".into(),
+            (ApiError::EmptyText, Locale::En) => "Text must not be empty".into(),
+            (ApiError::TextTooLong(max), Locale::Zh) => {
+                format!("文本长度超过限制（最大{max}字符）")
+            }
+            (ApiError::TextTooLong(max), Locale::En) => {
+                format!("Text exceeds the length limit ({max} characters max)")
+            }
+            (ApiError::TooManyChunks { chunks, max }, Locale::Zh) => {
+                format!("文本将被拆分为{chunks}段，超过限制（最多{max}段）")
+            }
+            (ApiError::TooManyChunks { chunks, max }, Locale::En) => {
+                format!("Text would split into {chunks} chunks ({max} max)")
+            }
+            (ApiError::EmptyTarget, Locale::Zh) => "目标语言不能为空".into(),
+            (ApiError::EmptyTarget, Locale::En) => "Target language must not be empty".into(),
+            (ApiError::UnsupportedTarget(target), Locale::Zh) => {
+                format!("不支持的目标语言: {target}")
+            }
+            (ApiError::UnsupportedTarget(target), Locale::En) => {
+                format!("Unsupported target language: {target}")
+            }
+            (ApiError::EmptyTargets, Locale::Zh) => "目标语言列表不能为空".into(),
+            (ApiError::EmptyTargets, Locale::En) => "Target language list must not be empty".into(),
+            (ApiError::MissingSource, Locale::Zh) => {
+                "请指定源语言，或使用 \"auto\" 自动检测".into()
+            }
+            (ApiError::MissingSource, Locale::En) => {
+                "Source language is required; send \"auto\" to auto-detect".into()
+            }
+            (ApiError::TooManyTargets(max), Locale::Zh) => {
+                format!("目标语言数量超过限制（最多{max}个）")
+            }
+            (ApiError::TooManyTargets(max), Locale::En) => {
+                format!("Too many target languages ({max} max)")
+            }
+            (ApiError::MissingFile, Locale::Zh) => "缺少上传文件（file 字段）".into(),
+            (ApiError::MissingFile, Locale::En) => "Missing uploaded file (file field)".into(),
+            (ApiError::FileTooLarge(max), Locale::Zh) => {
+                format!("文件大小超过限制（最大{max}字节）")
+            }
+            (ApiError::FileTooLarge(max), Locale::En) => {
+                format!("File exceeds the size limit ({max} bytes max)")
+            }
+            (ApiError::NotUtf8, Locale::Zh) => "文件不是有效的 UTF-8 文本".into(),
+            (ApiError::NotUtf8, Locale::En) => "File is not valid UTF-8 text".into(),
+            (ApiError::UnsupportedMediaType(expected), Locale::Zh) => {
+                format!("请求的 Content-Type 必须为 {expected}")
+            }
+            (ApiError::UnsupportedMediaType(expected), Locale::En) => {
+                format!("Content-Type must be {expected}")
+            }
+            (ApiError::IdempotencyConflict, Locale::Zh) => {
+                "该 Idempotency-Key 已用于内容不同的请求".into()
+            }
+            (ApiError::IdempotencyConflict, Locale::En) => {
+                "Idempotency-Key was already used for a different request".into()
+            }
+            (ApiError::JobNotFound, Locale::Zh) => "任务不存在或已过期".into(),
+            (ApiError::JobNotFound, Locale::En) => "No such job, or it has expired".into(),
+            (ApiError::TooManyJobs(max), Locale::Zh) => {
+                format!("运行中的任务已达上限（最多{max}个），请稍后再试")
+            }
+            (ApiError::TooManyJobs(max), Locale::En) => {
+                format!("Too many jobs are running ({max} max), please try again later")
+            }
+            (ApiError::InjectedFault, Locale::Zh) => "注入的测试故障 (FAULT_INJECTION_RATE)".into(),
+            (ApiError::InjectedFault, Locale::En) => {
+                "Injected test fault (FAULT_INJECTION_RATE)".into()
+            }
+            (ApiError::ContentBlocked, Locale::Zh) => "文本包含禁止翻译的内容".into(),
+            (ApiError::ContentBlocked, Locale::En) => {
+                "The text contains content that may not be translated".into()
+            }
+            (ApiError::InvalidConfig(err), Locale::Zh) => format!("配置无效: {err}"),
+            (ApiError::InvalidConfig(err), Locale::En) => format!("Invalid configuration: {err}"),
+            (ApiError::DeadlineExceeded { secs, completed }, Locale::Zh) => {
+                format!("翻译超时（{secs}秒），仅完成{completed}个分段")
+            }
+            (ApiError::DeadlineExceeded { secs, completed }, Locale::En) => {
+                format!("Translation exceeded the {secs}s deadline after {completed} chunks")
+            }
+            (ApiError::Translate(err), Locale::Zh) => format!("翻译失败: {err}"),
+            (ApiError::Translate(err), Locale::En) => {
+                format!("Translation failed: {}", english_message(err))
+            }
+            (ApiError::TranslateTarget(target, err), Locale::Zh) => {
+                format!("翻译失败（{target}）: {err}")
+            }
+            (ApiError::TranslateTarget(target, err), Locale::En) => {
+                format!("Translation failed ({target}): {}", english_message(err))
             }
-            current.push_str(paragraph);
-            current_len += para_len;
         }
     }
+}
 
-    if !current.is_empty() {
-        chunks.push(current);
+/// `TranslateError` displays in Chinese; English clients get this instead,
+/// with only an upstream error body passed through as detail.
+fn english_message(err: &TranslateError) -> String {
+    match err {
+        TranslateError::Upstream(_) => "the upstream request failed".into(),
+        TranslateError::Timeout(_) => "the upstream request timed out".into(),
+        TranslateError::Status { status, body } => {
+            format!("the upstream returned HTTP {status}: {body}")
+        }
+        TranslateError::Unavailable(_) => {
+            "the upstream service is temporarily unavailable, please try again later".into()
+        }
+        TranslateError::Placeholder(_) => {
+            "the translation lost placeholders for protected spans".into()
+        }
+        TranslateError::Internal(_) => "internal error".into(),
+        TranslateError::BudgetExceeded { .. } => "today's upstream budget is used up".into(),
     }
-
-    chunks
 }
 
-fn split_by_chars(text: &str, max_chars: usize) -> Vec<String> {
-    let mut parts = Vec::new();
-    let mut current = String::new();
-    let mut count = 0usize;
-
-    for ch in text.chars() {
-        current.push(ch);
-        count += 1;
-        if count >= max_chars {
-            parts.push(current);
-            current = String::new();
-            count = 0;
+impl From<&TranslateError> for ErrorCode {
+    fn from(err: &TranslateError) -> Self {
+        match err {
+            TranslateError::Upstream(_) | TranslateError::Status { .. } => ErrorCode::UpstreamError,
+            TranslateError::Placeholder(_) => ErrorCode::PlaceholderMismatch,
+            TranslateError::Timeout(_) => ErrorCode::UpstreamTimeout,
+            TranslateError::Unavailable(_) => ErrorCode::ServiceUnavailable,
+            TranslateError::Internal(_) => ErrorCode::InternalError,
+            TranslateError::BudgetExceeded { .. } => ErrorCode::BudgetExceeded,
         }
     }
+}
 
-    if !current.is_empty() {
-        parts.push(current);
-    }
+#[derive(Deserialize)]
+struct SeedEntry {
+    text: String,
+    source: Option<String>,
+    target: String,
+    translation: String,
+}
 
-    parts
+#[derive(Debug, Clone, Serialize, Default)]
+struct TranslateResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternatives: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stale: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_used: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detected_source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunks: Option<Vec<ChunkInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segments: Option<Vec<Segment>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+    /// The rate limit that was hit, so clients can pace themselves.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<Quality>,
 }
 
-fn load_config() -> Result<Config, String> {
-    let api_key = env::var("ARK_API_KEY").map_err(|_| "ARK_API_KEY not set".to_string())?;
-    let api_url = env::var("ARK_API_URL")
-        .unwrap_or_else(|_| "https://ark.cn-beijing.volces.com/api/v3/responses".to_string());
+/// The body shape of `/api/translate` responses, set by `RESPONSE_SCHEMA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseSchema {
+    /// The full `TranslateResponse`.
+    Default,
+    /// `{ "translation": ... }`, or `{ "error", "code" }` on failure.
+    Flat,
+    /// A chat-completion-like `choices` envelope.
+    OpenAi,
+}
 
-    let port = env::var("PORT")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(5000);
+impl std::str::FromStr for ResponseSchema {
+    type Err = String;
 
-    let cache_ttl = env_usize("CACHE_TTL", 3600);
-    let cache_max_size = env_usize("CACHE_MAX_SIZE", 1000);
-    let max_text_length = env_usize("MAX_TEXT_LENGTH", 5000);
-    let rate_limit_rpm = env_usize("RATE_LIMIT_RPM", 30);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "default" => Ok(Self::Default),
+            "flat" => Ok(Self::Flat),
+            "openai" => Ok(Self::OpenAi),
+            other => Err(format!(
+                "unknown response schema {other:?} (expected default, flat or openai)"
+            )),
+        }
+    }
+}
 
-    Ok(Config {
-        api_key,
-        api_url,
-        port,
-        cache_ttl: Duration::from_secs(cache_ttl as u64),
-        cache_max_size,
-        max_text_length,
+impl ResponseSchema {
+    /// Multi-target results have no single translation, so they keep the
+    /// default shape under every schema.
+    fn render(self, body: TranslateResponse) -> Value {
+        match (self, body.success, &body.text) {
+            (Self::Flat, true, Some(text)) => json!({ "translation": text }),
+            (Self::Flat, false, _) => json!({ "error": body.error, "code": body.code }),
+            (Self::OpenAi, true, Some(text)) => json!({
+                "object": "translation",
+                "model": body.model_used,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": text },
+                    "finish_reason": "stop",
+                }],
+            }),
+            (Self::OpenAi, false, _) => json!({
+                "error": { "message": body.error, "type": body.code, "code": body.code },
+            }),
+            _ => serde_json::to_value(body).unwrap_or_default(),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let config = match load_config() {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            eprintln!("Config error: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let client = match build_client(&config) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("HTTP client error: {err}");
+            std::process::exit(1);
+        }
+    };
+    let audit = match config.audit_log_file.as_deref() {
+        Some("-" | "stdout") => Some(AuditLog::stdout()),
+        Some(path) => match AuditLog::open(path) {
+            Ok(audit) => Some(audit),
+            Err(err) => {
+                eprintln!("Audit log error: {path}: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let tls = match &config.tls {
+        Some((cert, key)) => match load_tls_config(cert, key, config.enable_http2) {
+            Ok(tls) => Some(tls),
+            Err(err) => {
+                eprintln!("TLS error: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let cache = Cache::new(config.cache_max_size, config.cache_ttl)
+        .with_whitespace_collapse(config.cache_key_collapse_whitespace)
+        .with_ttl_jitter(config.cache_ttl_jitter_pct)
+        .with_key_prefix(&config.cache_key_prefix)
+        .with_stale_while_revalidate(config.stale_while_revalidate);
+    if let Some(path) = &config.cache_seed_file {
+        seed_cache(&cache, path).await;
+    }
+    let limiter = RateLimiter::new(Duration::from_secs(60), config.rate_limit_rpm);
+    let language_limiter = LanguageRateLimiter::new(
+        Duration::from_secs(60),
+        config.rate_limit_per_lang.iter().cloned(),
+    );
+    if !config.rate_limit_sweep_interval.is_zero() {
+        limiter.spawn_sweeper(config.rate_limit_sweep_interval);
+        language_limiter.spawn_sweeper(config.rate_limit_sweep_interval);
+    }
+
+    let breaker = CircuitBreaker::new(config.circuit_fail_threshold, config.circuit_cooldown);
+    let raw_provider = Arc::new(DoubaoProvider::new(
+        client.clone(),
+        Arc::new(config.translator.clone()),
+    ));
+    let mut translator = Translator::new(client, config.translator.clone())
+        .with_cache(cache)
+        .with_circuit_breaker(breaker)
+        .with_chunk_cache(config.cache_chunks);
+    if config.daily_request_budget.is_some() || config.daily_token_budget.is_some() {
+        let mut budget = Budget::new(config.daily_request_budget, config.daily_token_budget);
+        if let Some(path) = &config.budget_state_file {
+            budget = budget.with_state_file(path);
+        }
+        translator = translator.with_budget(budget);
+    }
+    if config.provider == "mock" {
+        println!("Mock mode: translations are faked locally, no upstream requests are made");
+        translator = translator.with_provider(Arc::new(MockProvider));
+    }
+    if let Some(pattern) = &config.protect_pattern {
+        translator = translator.with_protect_pattern(pattern.clone());
+    }
+    if let Some(pattern) = &config.redact_pattern {
+        translator = translator.with_redact_pattern(pattern.clone());
+    }
+    if config.log_translation_content {
+        eprintln!("Warning: LOG_TRANSLATION_CONTENT is on; this exposes user data in the logs");
+    }
+    if config.verify_key_on_start {
+        verify_api_key(&translator).await;
+    }
+    if config.run_selftest && !run_selftest(&config, &translator).await {
+        std::process::exit(1);
+    }
+
+    let permits = match config.per_key_concurrency {
+        0 => Semaphore::MAX_PERMITS,
+        n => n,
+    };
+    let api_keys = config
+        .server_api_keys
+        .iter()
+        .map(|key| (key.clone(), Arc::new(Semaphore::new(permits))))
+        .collect();
+
+    let config_idempotency_ttl = config.idempotency_ttl;
+    let config_max_inflight = config.max_inflight_requests;
+    let config_job_ttl = config.job_ttl;
+    let config_max_running_jobs = config.max_running_jobs;
+    let state = AppState {
+        config: Arc::new(ArcSwap::from_pointee(config)),
+        translator,
+        limiter,
+        language_limiter,
+        api_keys: Arc::new(api_keys),
+        idempotency: IdempotencyStore::new(config_idempotency_ttl),
+        jobs: JobStore::new(config_job_ttl, config_max_running_jobs),
+        inflight: (config_max_inflight > 0).then(|| Arc::new(Semaphore::new(config_max_inflight))),
+        audit,
+        raw_provider,
+    };
+
+    let config = state.config();
+    let addr = format!("0.0.0.0:{}", config.port);
+    let (enable_http2, http2_keepalive) = (config.enable_http2, config.http2_keepalive);
+    let max_connections = config.max_connections;
+
+    let mut app = Router::new()
+        .route(
+            "/api/translate",
+            post(translate_handler).get(translate_query_handler),
+        )
+        .route(
+            "/api/translate/file",
+            post(translate_file_handler).layer(DefaultBodyLimit::max(
+                config.max_upload_bytes.saturating_add(64 * 1024),
+            )),
+        )
+        .route("/api/translate/ndjson", post(translate_ndjson_handler))
+        .route("/api/translate/stream", post(translate_stream_handler))
+        .route("/api/translate/json", post(translate_json_handler))
+        .route("/api/ws", get(ws_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), shed_load))
+        // Jobs take their in-flight slot themselves, for as long as they run.
+        .route("/api/jobs", post(create_job_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), inject_faults))
+        .route("/api/languages", get(languages_handler))
+        .route("/api/health", get(health_handler))
+        .route("/api/version", get(version_handler))
+        .route(
+            "/api/jobs/:id",
+            get(job_status_handler).delete(cancel_job_handler),
+        )
+        .route("/api/stats", get(stats_handler))
+        .route("/api/admin/reload", post(reload_handler))
+        .route("/api/tm/export", get(tm_export_handler))
+        .route("/api/tm/import", post(tm_import_handler));
+    if let Some((rate, status)) = config.fault_injection {
+        eprintln!("Warning: FAULT_INJECTION_RATE is set; {rate} of translation requests fail with {status}");
+    }
+    if config.enable_debug_endpoints {
+        eprintln!("Warning: ENABLE_DEBUG_ENDPOINTS is on; POST /api/debug/raw forwards arbitrary input upstream");
+        app = app.route("/api/debug/raw", post(debug_raw_handler));
+    }
+    let static_dir = static_root(&config);
+    if let Some(dir) = &static_dir {
+        let static_service = ServeDir::new(dir);
+        let libs_service = ServeDir::new(dir.join("libs"));
+        app = app
+            .nest_service("/static", static_service)
+            .nest_service("/libs", libs_service);
+    }
+    app = match static_dir.map(|dir| dir.join("index.html")) {
+        Some(index) if index.is_file() => app.route("/", get_service(ServeFile::new(index))),
+        _ => app.route("/", get(root_fallback_handler)),
+    };
+    let app = app
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(cors_layer())
+        .with_state(state);
+    println!(
+        "Server listening on {addr}{}",
+        if tls.is_some() { " (TLS)" } else { "" }
+    );
+
+    let addr: SocketAddr = addr.parse().expect("invalid bind address");
+    let connection_limit = ConnectionLimit::new(max_connections);
+    if let Some(tls) = tls {
+        let mut server =
+            axum_server::bind_rustls(addr, tls).map(|acceptor| acceptor.acceptor(connection_limit));
+        configure_http2(server.http_builder(), enable_http2, http2_keepalive);
+        server
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .expect("server error");
+        return;
+    }
+
+    let mut server =
+        axum_server::bind(addr).acceptor(connection_limit.rejecting_h2c(!enable_http2));
+    configure_http2(server.http_builder(), enable_http2, http2_keepalive);
+    server
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .expect("server error");
+}
+
+/// Closes connections beyond `MAX_CONNECTIONS` as soon as they are accepted,
+/// before any TLS handshake, so idle or slow clients can't use up the file
+/// descriptors. Admitted streams hold a permit until they close.
+#[derive(Clone)]
+struct ConnectionLimit {
+    permits: Option<Arc<Semaphore>>,
+    reject_h2c: bool,
+}
+
+impl ConnectionLimit {
+    /// `max` of 0 admits every connection.
+    fn new(max: usize) -> Self {
+        Self {
+            permits: (max > 0).then(|| Arc::new(Semaphore::new(max))),
+            reject_h2c: false,
+        }
+    }
+
+    /// Also drops plaintext connections that open with the HTTP/2
+    /// prior-knowledge preface. The auto builder ignores `http1_only` when
+    /// serving with upgrades, as axum-server does, so this is the only place
+    /// to refuse them.
+    fn rejecting_h2c(self, reject_h2c: bool) -> Self {
+        Self { reject_h2c, ..self }
+    }
+}
+
+impl<I, S> Accept<I, S> for ConnectionLimit {
+    type Stream = LimitedStream<I>;
+    type Service = S;
+    type Future = future::Ready<io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let permit = match &self.permits {
+            Some(permits) => match Arc::clone(permits).try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => return future::ready(Err(io::Error::other("MAX_CONNECTIONS reached"))),
+            },
+            None => None,
+        };
+        future::ready(Ok((
+            LimitedStream {
+                stream,
+                _permit: permit,
+                preface_matched: self.reject_h2c.then_some(0),
+            },
+            service,
+        )))
+    }
+}
+
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+struct LimitedStream<I> {
+    stream: I,
+    _permit: Option<OwnedSemaphorePermit>,
+    /// How much of the HTTP/2 preface the client has sent so far, while it
+    /// still might be one; `None` once ruled out or when h2c is allowed.
+    preface_matched: Option<usize>,
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for LimitedStream<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        if let Err(err) = std::task::ready!(Pin::new(&mut self.stream).poll_read(cx, buf)) {
+            return Poll::Ready(Err(err));
+        }
+        if let Some(matched) = self.preface_matched {
+            let read = &buf.filled()[before..];
+            let rest = &H2_PREFACE[matched..];
+            let n = read.len().min(rest.len());
+            self.preface_matched = if read[..n] != rest[..n] {
+                None
+            } else if matched + n == H2_PREFACE.len() {
+                let err = io::Error::new(io::ErrorKind::InvalidData, "HTTP/2 is disabled");
+                return Poll::Ready(Err(err));
+            } else {
+                Some(matched + n)
+            };
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for LimitedStream<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Explicit lists rather than `*`: a wildcard `Access-Control-Allow-Headers`
+/// does not cover `Authorization`, and browsers need `Cache-Control` /
+/// `Last-Event-ID` allowed for streaming (EventSource-style) requests.
+fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([
+            header::ACCEPT,
+            header::ACCEPT_LANGUAGE,
+            header::AUTHORIZATION,
+            header::CACHE_CONTROL,
+            header::CONTENT_TYPE,
+            header::IF_NONE_MATCH,
+            HeaderName::from_static("last-event-id"),
+        ])
+        .expose_headers([
+            header::CONTENT_TYPE,
+            header::CONTENT_DISPOSITION,
+            header::ETAG,
+            header::RETRY_AFTER,
+        ])
+        .max_age(Duration::from_secs(600))
+}
+
+/// With `ENABLE_HTTP2`, plaintext connections are served as HTTP/2 when the
+/// client opens with the prior-knowledge preface, and over TLS it is
+/// negotiated through ALPN. Without it, the TLS config offers only HTTP/1.1
+/// and `ConnectionLimit::rejecting_h2c` drops plaintext h2c connections.
+fn configure_http2(
+    builder: &mut auto::Builder<TokioExecutor>,
+    enable_http2: bool,
+    keepalive: Option<Duration>,
+) {
+    if !enable_http2 {
+        return;
+    }
+    if let Some(interval) = keepalive {
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(interval);
+    }
+}
+
+fn build_client(config: &Config) -> Result<Client, String> {
+    let mut builder = Client::builder()
+        .timeout(config.http_request_timeout)
+        .pool_max_idle_per_host(config.http_pool_max_idle)
+        .pool_idle_timeout(config.http_pool_idle_timeout)
+        .tcp_keepalive(config.http_tcp_keepalive)
+        .user_agent(&config.http_user_agent)
+        .default_headers(config.http_extra_headers.clone());
+    if !config.enable_http2 {
+        builder = builder.http1_only();
+    } else if let Some(interval) = config.http2_keepalive {
+        builder = builder
+            .http2_keep_alive_interval(interval)
+            .http2_keep_alive_while_idle(true);
+    }
+
+    if config.http_disable_proxy {
+        builder = builder.no_proxy();
+    } else if let Some(proxy) = &config.http_proxy {
+        let proxy = reqwest::Proxy::all(proxy).map_err(|e| format!("invalid HTTP_PROXY: {e}"))?;
+        builder = builder.proxy(proxy);
+    }
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn load_tls_config(
+    cert_path: &str,
+    key_path: &str,
+    enable_http2: bool,
+) -> Result<RustlsConfig, String> {
+    let cert_pem =
+        std::fs::read(cert_path).map_err(|e| format!("failed to read {cert_path}: {e}"))?;
+    let key_pem = std::fs::read(key_path).map_err(|e| format!("failed to read {key_path}: {e}"))?;
+
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid certificate in {cert_path}: {e}"))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {cert_path}"));
+    }
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .map_err(|e| format!("invalid private key in {key_path}: {e}"))?
+        .ok_or_else(|| format!("no private key found in {key_path}"))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut server_config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("invalid certificate/key pair: {e}"))?;
+    server_config.alpn_protocols = if enable_http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+    Ok(RustlsConfig::from_config(Arc::new(server_config)))
+}
+
+/// Makes one tiny uncached translation so a rejected API key stops the
+/// deployment instead of failing the first real request.
+async fn verify_api_key(translator: &Translator) {
+    let params = TranslateParams {
+        source: Some("en"),
+        no_cache: true,
+        ..TranslateParams::new("zh")
+    };
+    match translator.translate_with("ok", &params).await {
+        Ok(_) => println!("Upstream API key verified"),
+        Err(TranslateError::Status {
+            status: status @ (401 | 403),
+            body,
+        }) => {
+            eprintln!("Upstream rejected the API key ({status}): {body}");
+            std::process::exit(1);
+        }
+        Err(err) => eprintln!("Could not verify the API key at startup, continuing: {err}"),
+    }
+}
+
+/// Offline unless `SELFTEST_UPSTREAM` is set: the translation check swaps in
+/// the mock provider so only the local pipeline is exercised.
+async fn run_selftest(config: &Config, translator: &Translator) -> bool {
+    let mut checks: Vec<(&str, Result<String, String>)> = Vec::new();
+
+    let source = config.default_source.as_deref().unwrap_or("en");
+    checks.push((
+        "config",
+        Ok(format!(
+            "{} languages, provider {}",
+            config.languages.len(),
+            config.provider
+        )),
+    ));
+    checks.push((
+        "languages",
+        match config
+            .sources
+            .get("LANGUAGES_FILE")
+            .filter(|path| !path.is_empty())
+        {
+            Some(path) => load_languages(path)
+                .map(|languages| format!("{} languages parsed from {path}", languages.len())),
+            None => Ok("built-in list".to_string()),
+        },
+    ));
+
+    let cache = Cache::new(1, Duration::from_secs(60));
+    cache
+        .set("selftest".to_string(), "round-trip".to_string())
+        .await;
+    checks.push((
+        "cache",
+        match cache.get("selftest").await.as_deref() {
+            Some("round-trip") => Ok("set/get round-trip".to_string()),
+            other => Err(format!("read back {other:?}")),
+        },
+    ));
+
+    let (name, translator) = if config.selftest_upstream {
+        ("translation (upstream)", translator.clone())
+    } else {
+        (
+            "translation (mock)",
+            translator.clone().with_provider(Arc::new(MockProvider)),
+        )
+    };
+    let target = if source == "zh" { "en" } else { "zh" };
+    let params = TranslateParams {
+        source: Some(source),
+        no_cache: true,
+        ..TranslateParams::new(target)
+    };
+    checks.push((
+        name,
+        match translator.translate_with("ok", &params).await {
+            Ok(translation) if !translation.text.trim().is_empty() => {
+                Ok(format!("{source} -> {target}: {:?}", translation.text))
+            }
+            Ok(_) => Err("empty translation".to_string()),
+            Err(err) => Err(err.to_string()),
+        },
+    ));
+
+    let passed = checks.iter().filter(|(_, result)| result.is_ok()).count();
+    println!("Self-test:");
+    for (name, result) in &checks {
+        match result {
+            Ok(detail) => println!("  [pass] {name}: {detail}"),
+            Err(detail) => println!("  [FAIL] {name}: {detail}"),
+        }
+    }
+    if passed == checks.len() {
+        println!("Self-test passed ({passed}/{})", checks.len());
+        true
+    } else {
+        eprintln!("Self-test failed ({passed}/{} passed)", checks.len());
+        false
+    }
+}
+
+async fn translate_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    Query(options): Query<TranslateOptions>,
+    ApiJson(mut payload): ApiJson<TranslateRequest>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    let schema = state.config().response_schema;
+    let _permit = match admit(&state, &headers) {
+        Ok(permit) => permit,
+        Err(err) => return schema_response(schema, error_response(locale, err)),
+    };
+    payload.verbose |= options.verbose;
+    payload.timing |= options.timing;
+    payload.quality |= options.quality;
+    payload.no_cache |= wants_no_store(&headers);
+    let Some(key) = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.trim().is_empty())
+    else {
+        let response = handle_translate(&state, locale, client, payload).await;
+        return schema_response(schema, response);
+    };
+
+    // Keys are per caller, and a key may only be replayed for the same body.
+    let caller = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let scope = format!("{:x}|{}", md5::compute(caller), key.trim());
+    let fingerprint = format!("{:x}", md5::compute(format!("{payload:?}")));
+    let Some(slot) = state.idempotency.slot(scope, fingerprint).await else {
+        return schema_response(
+            schema,
+            error_response(locale, ApiError::IdempotencyConflict),
+        );
+    };
+    let mut replayed = true;
+    let outcome = slot
+        .get_or_try_init(|| async {
+            replayed = false;
+            match handle_translate(&state, locale, client, payload).await {
+                (status, Json(body)) if status.is_success() => Ok(body),
+                failure => Err(failure),
+            }
+        })
+        .await;
+    match outcome {
+        Ok(body) => {
+            let mut resp = schema_response(schema, (StatusCode::OK, Json(body.clone())));
+            if replayed {
+                resp.headers_mut().insert(
+                    HeaderName::from_static("idempotent-replayed"),
+                    HeaderValue::from_static("true"),
+                );
+            }
+            resp
+        }
+        Err(failure) => schema_response(schema, failure),
+    }
+}
+
+async fn translate_query_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    Query(mut payload): Query<TranslateRequest>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    let schema = state.config().response_schema;
+    let _permit = match admit(&state, &headers) {
+        Ok(permit) => permit,
+        Err(err) => return schema_response(schema, error_response(locale, err)),
+    };
+    payload.no_cache |= wants_no_store(&headers);
+    let response = handle_translate(&state, locale, client, payload).await;
+    let Some(etag) = response_etag(&response.1) else {
+        return schema_response(schema, response);
+    };
+    let mut resp = if etag_matches(&headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        schema_response(schema, response)
+    };
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        resp.headers_mut().insert(header::ETAG, value);
+    }
+    resp
+}
+
+/// `Json`, but a missing or non-JSON `Content-Type` gets the standard error
+/// body with 415 instead of axum's plain-text rejection.
+struct ApiJson<T>(T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let locale = Locale::from_headers(req.headers());
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(JsonRejection::MissingJsonContentType(_)) => Err(http_response(error_response(
+                locale,
+                ApiError::UnsupportedMediaType("application/json"),
+            ))),
+            Err(JsonRejection::JsonDataError(err)) => Err(bad_json(locale, &err)),
+            Err(JsonRejection::JsonSyntaxError(err)) => Err(bad_json(locale, &err)),
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+}
+
+/// The caller's address and scheme: the TCP peer and whether this server
+/// terminates TLS, or with `TRUST_PROXY` what the proxy reports in
+/// `X-Forwarded-For` or `X-Real-IP` and `X-Forwarded-Proto`. Without it those
+/// headers are ignored, since any client can set them.
+#[derive(Debug, Clone, Copy)]
+struct Caller {
+    ip: Option<IpAddr>,
+    scheme: &'static str,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let config = state.config();
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let scheme = if config.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        if !config.trust_proxy {
+            return Ok(Self { ip: peer, scheme });
+        }
+        Ok(Self {
+            ip: forwarded_ip(&parts.headers).or(peer),
+            scheme: forwarded_scheme(&parts.headers).unwrap_or(scheme),
+        })
+    }
+}
+
+/// The right-most `X-Forwarded-For` entry, the one our proxy appended;
+/// entries to its left come from the client and may be forged.
+fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    header("x-forwarded-for")
+        .and_then(|v| v.rsplit(',').next())
+        .or_else(|| header("x-real-ip"))
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+fn forwarded_scheme(headers: &HeaderMap) -> Option<&'static str> {
+    let proto = headers.get("x-forwarded-proto")?.to_str().ok()?;
+    match proto.rsplit(',').next()?.trim() {
+        p if p.eq_ignore_ascii_case("https") => Some("https"),
+        p if p.eq_ignore_ascii_case("http") => Some("http"),
+        _ => None,
+    }
+}
+
+/// The serde error behind a JSON rejection carries the field path and the
+/// line/column; axum's own message wraps it in a generic prefix.
+fn bad_json(locale: Locale, err: &dyn std::error::Error) -> Response {
+    let detail = err
+        .source()
+        .map_or_else(|| err.to_string(), |source| source.to_string());
+    http_response(error_response(locale, ApiError::BadJson(detail)))
+}
+
+fn has_content_type(headers: &HeaderMap, accepted: &[&str]) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| {
+            accepted
+                .iter()
+                .any(|ok| mime.trim().eq_ignore_ascii_case(ok))
+        })
+}
+
+/// Strong ETag over the translated text only, so a cache hit and the original
+/// miss (which differ in `cached`) share a tag.
+fn response_etag(body: &TranslateResponse) -> Option<String> {
+    if !body.success {
+        return None;
+    }
+    let digest = match (&body.text, &body.results) {
+        (Some(text), _) => md5::compute(text),
+        (None, Some(results)) => md5::compute(serde_json::to_vec(results).ok()?),
+        (None, None) => return None,
+    };
+    Some(format!("\"{digest:x}\""))
+}
+
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+async fn translate_json_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    ApiJson(payload): ApiJson<TranslateJsonRequest>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    let _permit = match admit(&state, &headers) {
+        Ok(permit) => permit,
+        Err(err) => return http_response(error_response(locale, err)),
+    };
+    let started = Instant::now();
+    let record = state
+        .audit
+        .is_some()
+        .then(|| AuditRecord::for_json(client, &payload));
+    let response = handle_translate_json(&state, locale, payload).await;
+    write_audit(&state, record, started, &response.1);
+    http_response(response)
+}
+
+async fn handle_translate_json(
+    state: &AppState,
+    locale: Locale,
+    payload: TranslateJsonRequest,
+) -> ApiResponse {
+    let config = state.config();
+    if let Err(limited) = state.limiter.allow().await {
+        return error_response(locale, ApiError::RateLimited(limited));
+    }
+    let skip = match payload.skip_pattern.as_deref().map(Regex::new).transpose() {
+        Ok(skip) => skip,
+        Err(err) => {
+            let err = ApiError::InvalidRequest(format!("invalid skip_pattern: {err}"));
+            return error_response(locale, err);
+        }
+    };
+    let mut request = TranslateRequest {
+        source: payload.source,
+        target: payload.target,
+        formality: payload.formality,
+        instruction: payload.instruction,
+        ..Default::default()
+    };
+    request.canonicalize_languages(&config);
+    if let Err(err) = validate_target(state, &request.target) {
+        return error_response(locale, err);
+    }
+    if let Err(err) = check_language_limit(state, &request.target).await {
+        return error_response(locale, err);
+    }
+    if let Err(err) = request.check_source(&config) {
+        return error_response(locale, err);
+    }
+
+    let mut leaves = Vec::new();
+    collect_strings(&payload.data, &mut leaves);
+    leaves.retain(|leaf| {
+        !leaf.trim().is_empty() && !skip.as_ref().is_some_and(|skip| skip.is_match(leaf))
+    });
+    leaves.sort_unstable();
+    leaves.dedup();
+    let total_chars: usize = leaves.iter().map(|leaf| leaf.chars().count()).sum();
+    if total_chars > config.max_text_length {
+        return error_response(locale, ApiError::TextTooLong(config.max_text_length));
+    }
+    if let Err(err) = leaves
+        .iter()
+        .try_for_each(|leaf| check_denied(&config, leaf))
+    {
+        return error_response(locale, err);
+    }
+
+    // Each distinct string is translated once, with the NDJSON parallelism.
+    let params = request.params(&config, &request.target);
+    let pending: Vec<_> = leaves
+        .iter()
+        .map(|leaf| state.translator.translate_with(leaf, &params))
+        .collect();
+    let outcomes: Vec<_> = stream::iter(pending)
+        .buffered(config.ndjson_concurrency)
+        .collect()
+        .await;
+    let mut translated = HashMap::with_capacity(leaves.len());
+    for (leaf, outcome) in leaves.iter().zip(outcomes) {
+        match outcome {
+            Ok(translation) => translated.insert(leaf.to_string(), translation.text),
+            Err(err) => return error_response(locale, ApiError::Translate(&err)),
+        };
+    }
+
+    let mut data = payload.data;
+    replace_strings(&mut data, &translated);
+    (
+        StatusCode::OK,
+        Json(TranslateResponse {
+            success: true,
+            data: Some(data),
+            ..Default::default()
+        }),
+    )
+}
+
+fn collect_strings<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) => out.push(s),
+        Value::Array(items) => items.iter().for_each(|item| collect_strings(item, out)),
+        Value::Object(map) => map.values().for_each(|item| collect_strings(item, out)),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+fn replace_strings(value: &mut Value, translated: &HashMap<String, String>) {
+    match value {
+        Value::String(s) => {
+            if let Some(translation) = translated.get(s.as_str()) {
+                s.clone_from(translation);
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| replace_strings(item, translated)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|item| replace_strings(item, translated)),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Translates the chunks of one text concurrently and sends each as an SSE
+/// `chunk` event, in chunk order, as soon as it and every earlier chunk are
+/// done; joining the texts with `\n` gives the full translation. A final
+/// `done` event carries the chunk count.
+async fn translate_stream_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    ApiJson(mut payload): ApiJson<TranslateRequest>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    let permit = match admit(&state, &headers) {
+        Ok(permit) => permit,
+        Err(err) => return http_response(error_response(locale, err)),
+    };
+    let started = Instant::now();
+    let record = state
+        .audit
+        .is_some()
+        .then(|| AuditRecord::for_text(&state, client, &payload));
+    let config = state.config();
+    payload.canonicalize_languages(&config);
+    let checked = match check_payload(&state, &config, &payload).await {
+        Ok(()) if payload.targets.is_some() => Err(ApiError::InvalidRequest(
+            "targets is not supported when streaming".to_string(),
+        )),
+        Ok(()) => validate_target(&state, &payload.target),
+        Err(err) => Err(err),
+    };
+    let checked = match checked {
+        Ok(()) => check_language_limit(&state, &payload.target).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = checked {
+        let response = error_response(locale, err);
+        write_audit(&state, record, started, &response.1);
+        return http_response(response);
+    }
+
+    let chunks = state.translator.split(&payload.text, payload.format);
+    let chunk_count = chunks.len();
+    let concurrency = config.stream_concurrency;
+    let streaming = config.translator.upstream_streaming;
+    let audit = (state.audit.clone())
+        .zip(record)
+        .map(|(log, record)| StreamAudit::new(log, record, started));
+    let shared = Arc::new(StreamContext {
+        state,
+        config,
+        payload,
+        locale,
+        audit,
+    });
+    let done = shared.clone();
+    let chunks = stream::iter(chunks.into_iter().enumerate());
+    let events = if streaming {
+        // Chunks go one at a time so their `token` events arrive in order,
+        // each chunk's followed by its final `chunk` (or `error`) event.
+        chunks
+            .map(move |(index, chunk)| {
+                let shared = shared.clone();
+                let (tx, rx) = mpsc::unbounded();
+                let translate = async move {
+                    let forward = |token: &str| {
+                        let event = Event::default()
+                            .event("token")
+                            .json_data(json!({ "index": index, "text": token }));
+                        let _ = tx.unbounded_send(event);
+                    };
+                    let event =
+                        stream_chunk_event(&shared, index, &chunk, Some(TokenSink(&forward))).await;
+                    let _ = tx.unbounded_send(event);
+                };
+                stream::select(rx.map(Some), stream::once(translate).map(|()| None))
+                    .filter_map(future::ready)
+            })
+            .flatten()
+            .boxed()
+    } else {
+        // `buffered` polls up to `stream_concurrency` chunks at once but yields
+        // them in order, holding chunks that finish early until their turn.
+        chunks
+            .map(move |(index, chunk)| {
+                let shared = shared.clone();
+                async move { stream_chunk_event(&shared, index, &chunk, None).await }
+            })
+            .buffered(concurrency)
+            .boxed()
+    };
+    let events = events
+        .chain(stream::once(async move {
+            if let Some(audit) = &done.audit {
+                audit.write(true);
+            }
+            Event::default()
+                .event("done")
+                .json_data(json!({ "chunk_count": chunk_count }))
+        }))
+        .map(|event| Ok::<_, Infallible>(event.unwrap_or_default()))
+        .inspect(move |_| {
+            let _ = &permit;
+        });
+
+    Sse::new(events).into_response()
+}
+
+/// What every chunk of one SSE stream shares.
+struct StreamContext {
+    state: AppState,
+    config: Arc<Config>,
+    payload: TranslateRequest,
+    locale: Locale,
+    audit: Option<StreamAudit>,
+}
+
+async fn stream_chunk_event(
+    shared: &StreamContext,
+    index: usize,
+    chunk: &str,
+    tokens: Option<TokenSink<'_>>,
+) -> Result<Event, axum::Error> {
+    let StreamContext {
+        state,
+        config,
+        payload,
+        locale,
+        audit,
+    } = shared;
+    let params = TranslateParams {
+        tokens,
+        ..payload.params(config, &payload.target)
+    };
+    let event = Event::default();
+    let outcome = state.translator.translate_with(chunk, &params).await;
+    if let Some(audit) = audit {
+        audit.chunk(&outcome);
+    }
+    match outcome {
+        Ok(translation) => {
+            log_translation(config, &payload.target, chunk, &translation.text);
+            event.event("chunk").json_data(json!({
+                "index": index,
+                "text": translation.text,
+                "cached": translation.cached,
+            }))
+        }
+        Err(err) => {
+            let (_, Json(body)) = error_response(*locale, ApiError::Translate(&err));
+            event
+                .event("error")
+                .json_data(json!({ "index": index, "error": body }))
+        }
+    }
+}
+
+/// The audit record of one SSE stream, filled in as its chunks finish and
+/// written after the `done` event, or on drop if the client left first.
+struct StreamAudit {
+    log: AuditLog,
+    started: Instant,
+    record: std::sync::Mutex<Option<AuditRecord>>,
+}
+
+impl StreamAudit {
+    fn new(log: AuditLog, record: AuditRecord, started: Instant) -> Self {
+        let record = AuditRecord {
+            cached: true,
+            ..record
+        };
+        Self {
+            log,
+            started,
+            record: std::sync::Mutex::new(Some(record)),
+        }
+    }
+
+    fn chunk(&self, outcome: &Result<Translation, TranslateError>) {
+        let mut record = self.record.lock().unwrap();
+        let Some(record) = record.as_mut() else {
+            return;
+        };
+        match outcome {
+            Ok(translation) => {
+                record.cached &= translation.cached;
+                if record.source.is_none() {
+                    record.source.clone_from(&translation.detected_source);
+                }
+            }
+            Err(err) => {
+                record.cached = false;
+                record.code.get_or_insert(ErrorCode::from(err));
+            }
+        }
+    }
+
+    /// Writes the record once; `done` is false when the stream was cut short.
+    fn write(&self, done: bool) {
+        let Some(record) = self.record.lock().unwrap().take() else {
+            return;
+        };
+        self.log.write(&AuditRecord {
+            cached: done && record.cached,
+            latency_ms: self.started.elapsed().as_millis() as u64,
+            success: done && record.code.is_none(),
+            ..record
+        });
+    }
+}
+
+impl Drop for StreamAudit {
+    fn drop(&mut self) {
+        self.write(false);
+    }
+}
+
+async fn translate_ndjson_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    body: Body,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    if !has_content_type(&headers, &["application/x-ndjson", "application/ndjson"]) {
+        let err = ApiError::UnsupportedMediaType("application/x-ndjson");
+        return http_response(error_response(locale, err));
+    }
+    let permit = match admit(&state, &headers) {
+        Ok(permit) => permit,
+        Err(err) => return http_response(error_response(locale, err)),
+    };
+
+    let concurrency = state.config().ndjson_concurrency;
+    let lines = ndjson_lines(body.into_data_stream(), state.config().max_body_bytes);
+    let results = lines
+        .map(move |line| {
+            let state = state.clone();
+            async move {
+                let (_, Json(response)) = match line.and_then(|line| {
+                    serde_json::from_slice::<TranslateRequest>(&line)
+                        .map_err(|err| ApiError::BadJson(err.to_string()))
+                }) {
+                    Ok(payload) => handle_translate(&state, locale, client, payload).await,
+                    Err(err) => error_response(locale, err),
+                };
+                let mut line = serde_json::to_vec(&response).unwrap_or_default();
+                line.push(b'\n');
+                Ok::<_, Infallible>(Bytes::from(line))
+            }
+        })
+        .buffered(concurrency)
+        .inspect(move |_| {
+            let _ = &permit;
+        });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(results),
+    )
+        .into_response()
+}
+
+struct LineReader {
+    body: BodyDataStream,
+    buf: Vec<u8>,
+    max_line_bytes: usize,
+    done: bool,
+}
+
+fn ndjson_lines(
+    body: BodyDataStream,
+    max_line_bytes: usize,
+) -> impl Stream<Item = Result<Vec<u8>, ApiError<'static>>> {
+    let reader = LineReader {
+        body,
+        buf: Vec::new(),
+        max_line_bytes,
+        done: false,
+    };
+    stream::unfold(reader, |mut reader| async move {
+        loop {
+            if let Some(pos) = reader.buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = reader.buf.drain(..=pos).collect();
+                return Some((Ok(line), reader));
+            }
+            if reader.done {
+                if reader.buf.is_empty() {
+                    return None;
+                }
+                return Some((Ok(std::mem::take(&mut reader.buf)), reader));
+            }
+            if reader.buf.len() > reader.max_line_bytes {
+                reader.done = true;
+                reader.buf.clear();
+                let err = format!("line exceeds {} bytes", reader.max_line_bytes);
+                return Some((Err(ApiError::InvalidRequest(err)), reader));
+            }
+            match reader.body.next().await {
+                Some(Ok(chunk)) => reader.buf.extend_from_slice(&chunk),
+                Some(Err(err)) => {
+                    reader.done = true;
+                    reader.buf.clear();
+                    return Some((Err(ApiError::InvalidRequest(err.to_string())), reader));
+                }
+                None => reader.done = true,
+            }
+        }
+    })
+    .filter(|line| future::ready(!matches!(line, Ok(line) if line.trim_ascii().is_empty())))
+}
+
+/// Fails a `FAULT_INJECTION_RATE` fraction of translation requests with
+/// `FAULT_INJECTION_STATUS`, before anything reaches the upstream.
+async fn inject_faults(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some((rate, status)) = state.config().fault_injection else {
+        return next.run(request).await;
+    };
+    if fastrand::f64() >= rate {
+        return next.run(request).await;
+    }
+    eprintln!("Injected a {status} fault into {}", request.uri().path());
+    let locale = Locale::from_headers(request.headers());
+    let (_, body) = error_response(locale, ApiError::InjectedFault);
+    http_response((status, body))
+}
+
+/// Rejects translation requests beyond `MAX_INFLIGHT_REQUESTS` with 503
+/// instead of queuing them. The slot is held until the response body has
+/// been sent, so streamed responses count for as long as they run.
+async fn shed_load(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(slots) = &state.inflight else {
+        return next.run(request).await;
+    };
+    let Ok(permit) = Arc::clone(slots).try_acquire_owned() else {
+        let locale = Locale::from_headers(request.headers());
+        return http_response(error_response(locale, ApiError::Overloaded));
+    };
+    next.run(request).await.map(|body| {
+        Body::from_stream(body.into_data_stream().inspect(move |_| {
+            let _ = &permit;
+        }))
+    })
+}
+
+/// A `MAX_INFLIGHT_REQUESTS` slot for work that outlives its request, which
+/// `shed_load` would release as soon as the response is sent.
+fn inflight_slot(state: &AppState) -> Result<Option<OwnedSemaphorePermit>, ApiError<'static>> {
+    match &state.inflight {
+        Some(slots) => Arc::clone(slots)
+            .try_acquire_owned()
+            .map(Some)
+            .map_err(|_| ApiError::Overloaded),
+        None => Ok(None),
+    }
+}
+
+fn admit(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<OwnedSemaphorePermit>, ApiError<'static>> {
+    authorize(state, headers).and_then(acquire_slot)
+}
+
+fn authorize(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<Arc<Semaphore>>, ApiError<'static>> {
+    if state.api_keys.is_empty() {
+        return Ok(None);
+    }
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .and_then(|token| state.api_keys.get(token.trim()))
+        .map(|slots| Some(Arc::clone(slots)))
+        .ok_or(ApiError::Unauthorized)
+}
+
+/// Like [`authorize`], but refuses outright when no server keys are
+/// configured, so admin routes are never open on a keyless deployment.
+fn authorize_admin(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError<'static>> {
+    if state.api_keys.is_empty() {
+        return Err(ApiError::AdminDisabled);
+    }
+    authorize(state, headers).map(drop)
+}
+
+fn acquire_slot(
+    slots: Option<Arc<Semaphore>>,
+) -> Result<Option<OwnedSemaphorePermit>, ApiError<'static>> {
+    match slots {
+        Some(slots) => slots
+            .try_acquire_owned()
+            .map(Some)
+            .map_err(|_| ApiError::ConcurrencyLimited),
+        None => Ok(None),
+    }
+}
+
+async fn handle_translate(
+    state: &AppState,
+    locale: Locale,
+    client: Caller,
+    payload: TranslateRequest,
+) -> ApiResponse {
+    let started = Instant::now();
+    let timing = payload.timing;
+    let record = state
+        .audit
+        .is_some()
+        .then(|| AuditRecord::for_text(state, client, &payload));
+    let (status, Json(mut body)) = translate_payload(state, locale, payload).await;
+    write_audit(state, record, started, &body);
+    if timing {
+        body.elapsed_ms = Some(started.elapsed().as_millis() as u64);
+    }
+    (status, Json(body))
+}
+
+/// One `AUDIT_LOG_FILE` line per `/api/translate` (POST or GET), NDJSON line,
+/// WebSocket message, SSE stream, upload or JSON document. Holds sizes and
+/// outcomes, never the text itself.
+#[derive(Serialize)]
+struct AuditRecord {
+    timestamp_ms: u64,
+    request_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_ip: Option<IpAddr>,
+    /// `http` or `https`, as the client connected.
+    scheme: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    /// Comma separated for `targets` and `pair` requests.
+    target: String,
+    chars: usize,
+    chunks: usize,
+    cached: bool,
+    latency_ms: u64,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<ErrorCode>,
+}
+
+impl AuditRecord {
+    fn for_text(state: &AppState, client: Caller, payload: &TranslateRequest) -> Self {
+        let languages = payload.targets.as_ref().or(payload.pair.as_ref());
+        Self::new(
+            client,
+            payload.source.clone(),
+            languages.map_or_else(|| payload.target.clone(), |langs| langs.join(",")),
+            payload.text.chars().count(),
+            state.translator.split(&payload.text, payload.format).len(),
+        )
+    }
+
+    /// For a JSON document every string leaf counts as one chunk.
+    fn for_json(client: Caller, payload: &TranslateJsonRequest) -> Self {
+        let mut leaves = Vec::new();
+        collect_strings(&payload.data, &mut leaves);
+        Self::new(
+            client,
+            payload.source.clone(),
+            payload.target.clone(),
+            leaves.iter().map(|leaf| leaf.chars().count()).sum(),
+            leaves.len(),
+        )
+    }
+
+    fn new(
+        client: Caller,
+        source: Option<String>,
+        target: String,
+        chars: usize,
+        chunks: usize,
+    ) -> Self {
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            request_id: format!("{:016x}", fastrand::u64(..)),
+            client_ip: client.ip,
+            scheme: client.scheme,
+            source,
+            target,
+            chars,
+            chunks,
+            cached: false,
+            latency_ms: 0,
+            success: false,
+            code: None,
+        }
+    }
+
+    fn finish(self, started: Instant, body: &TranslateResponse) -> Self {
+        Self {
+            source: self.source.or_else(|| body.detected_source.clone()),
+            cached: body.cached.unwrap_or(false),
+            latency_ms: started.elapsed().as_millis() as u64,
+            success: body.success,
+            code: body.code,
+            ..self
+        }
+    }
+}
+
+/// Writes `record`, if auditing is on, with the outcome in `body`.
+fn write_audit(
+    state: &AppState,
+    record: Option<AuditRecord>,
+    started: Instant,
+    body: &TranslateResponse,
+) {
+    if let (Some(audit), Some(record)) = (&state.audit, record) {
+        audit.write(&record.finish(started, body));
+    }
+}
+
+/// The rate limit and input checks every text translation and upload passes
+/// first.
+async fn check_payload(
+    state: &AppState,
+    config: &Config,
+    payload: &TranslateRequest,
+) -> Result<(), ApiError<'static>> {
+    state.limiter.allow().await.map_err(ApiError::RateLimited)?;
+
+    let text_len = payload.text.chars().count();
+    if text_len == 0 {
+        return Err(ApiError::EmptyText);
+    }
+    if text_len > config.max_text_length {
+        return Err(ApiError::TextTooLong(config.max_text_length));
+    }
+    let chunks = state.translator.split(&payload.text, payload.format).len();
+    if chunks > config.max_chunks {
+        let max = config.max_chunks;
+        return Err(ApiError::TooManyChunks { chunks, max });
+    }
+    check_denied(config, &payload.text)?;
+    payload.check_source(config)
+}
+
+async fn translate_payload(
+    state: &AppState,
+    locale: Locale,
+    mut payload: TranslateRequest,
+) -> ApiResponse {
+    let config = state.config();
+    payload.canonicalize_languages(&config);
+    let paired = match payload.resolve_pair() {
+        Ok(paired) => paired,
+        Err(err) => return error_response(locale, err),
+    };
+    if let Err(err) = check_payload(state, &config, &payload).await {
+        return error_response(locale, err);
+    }
+
+    if let Some(targets) = &payload.targets {
+        if payload.alternatives.is_some() {
+            let err = ApiError::InvalidRequest(
+                "alternatives cannot be combined with targets".to_string(),
+            );
+            return error_response(locale, err);
+        }
+        return translate_targets(state, locale, &payload, targets).await;
+    }
+
+    if let Err(err) = validate_target(state, &payload.target) {
+        return error_response(locale, err);
+    }
+    if let Some(source) = payload.source.as_deref().filter(|_| paired) {
+        // Either pair language may be a target, so both must be supported.
+        if let Err(err) = validate_target(state, source) {
+            return error_response(locale, err);
+        }
+    }
+    if let Err(err) = check_language_limit(state, &payload.target).await {
+        return error_response(locale, err);
+    }
+    if payload.summary_max_chars == Some(0) {
+        let err = ApiError::InvalidRequest("summary_max_chars must be positive".to_string());
+        return error_response(locale, err);
+    }
+    let alternatives = payload.alternatives.unwrap_or(0);
+    if alternatives > config.max_alternatives {
+        let err = ApiError::InvalidRequest(format!(
+            "alternatives must be at most {}",
+            config.max_alternatives
+        ));
+        return error_response(locale, err);
+    }
+
+    let progress = AtomicUsize::new(0);
+    let params = TranslateParams {
+        progress: Some(&progress),
+        ..payload.params(&config, &payload.target)
+    };
+    let outcome = within_deadline(
+        &config,
+        state.translator.translate_with(&payload.text, &params),
+    )
+    .await;
+    let Some(outcome) = outcome else {
+        return deadline_response(&config, locale, &progress);
+    };
+    let translation = match outcome {
+        Ok(translation) => translation,
+        Err(err) => return error_response(locale, ApiError::Translate(&err)),
+    };
+
+    log_translation(&config, &payload.target, &payload.text, &translation.text);
+
+    let mut summary = None;
+    if payload.summarize {
+        // The summary is a second upstream call and is rate limited as one.
+        if let Err(limited) = state.limiter.allow().await {
+            return error_response(locale, ApiError::RateLimited(limited));
+        }
+        let max_chars = payload
+            .summary_max_chars
+            .unwrap_or(DEFAULT_SUMMARY_MAX_CHARS);
+        match state
+            .translator
+            .summarize(&translation.text, &params, max_chars)
+            .await
+        {
+            Ok(text) => summary = Some(text),
+            Err(err) => return error_response(locale, ApiError::Translate(&err)),
+        }
+    }
+
+    let mut candidates = None;
+    if alternatives > 0 {
+        // Like the summary, the extra calls are rate limited as one request.
+        if let Err(limited) = state.limiter.allow().await {
+            return error_response(locale, ApiError::RateLimited(limited));
+        }
+        let params = payload.params(&config, &payload.target);
+        match state
+            .translator
+            .alternatives(&payload.text, &params, alternatives)
+            .await
+        {
+            Ok(texts) => {
+                let limited = texts.into_iter().map(|text| payload.limit_output(text).0);
+                candidates = Some(limited.collect());
+            }
+            Err(err) => return error_response(locale, ApiError::Translate(&err)),
+        }
+    }
+
+    // Skipped (same-language) results are the input itself; nothing to judge.
+    let quality = (payload.quality && !translation.skipped).then(|| {
+        estimate_quality(
+            &payload.text,
+            &translation.text,
+            &payload.target,
+            config.protect_pattern.as_ref(),
+        )
+    });
+    let chunks = payload.verbose.then_some(translation.chunks);
+    let (text, truncated) = payload.limit_output(translation.text);
+    (
+        StatusCode::OK,
+        Json(TranslateResponse {
+            success: true,
+            text: Some(text),
+            summary,
+            alternatives: candidates,
+            cached: Some(translation.cached),
+            stale: translation.stale.then_some(true),
+            skipped: translation.skipped.then_some(true),
+            warning: translation
+                .empty_output
+                .then(|| empty_output_warning(locale)),
+            truncated: truncated.then_some(true),
+            model_used: translation.model_used,
+            detected_source: match paired {
+                true => payload.source.clone(),
+                false => translation.detected_source,
+            },
+            chunk_count: chunks.as_ref().map(Vec::len),
+            chunks,
+            segments: payload.include_source.then_some(translation.segments),
+            quality,
+            ..Default::default()
+        }),
+    )
+}
+
+fn empty_output_warning(locale: Locale) -> String {
+    match locale {
+        Locale::Zh => "上游返回了空白译文".into(),
+        Locale::En => "The upstream returned a blank translation".into(),
+    }
+}
+
+async fn translate_targets(
+    state: &AppState,
+    locale: Locale,
+    payload: &TranslateRequest,
+    targets: &[String],
+) -> ApiResponse {
+    let config = &state.config();
+    let mut unique: Vec<&str> = Vec::with_capacity(targets.len());
+    for target in targets {
+        if !unique.contains(&target.as_str()) {
+            unique.push(target);
+        }
+    }
+
+    if unique.is_empty() {
+        return error_response(locale, ApiError::EmptyTargets);
+    }
+    if unique.len() > config.max_targets {
+        return error_response(locale, ApiError::TooManyTargets(config.max_targets));
+    }
+    for target in &unique {
+        if let Err(err) = validate_target(state, target) {
+            return error_response(locale, err);
+        }
+    }
+    for target in &unique {
+        if let Err(err) = check_language_limit(state, target).await {
+            return error_response(locale, err);
+        }
+    }
+
+    let progress = AtomicUsize::new(0);
+    let progress = &progress;
+    let jobs = unique.iter().map(|target| async move {
+        let params = TranslateParams {
+            progress: Some(progress),
+            ..payload.params(config, target)
+        };
+        let outcome = state
+            .translator
+            .translate_with(&payload.text, &params)
+            .await;
+        (*target, outcome)
+    });
+
+    let Some(outcomes) = within_deadline(config, futures::future::join_all(jobs)).await else {
+        return deadline_response(config, locale, progress);
+    };
+    let mut results = BTreeMap::new();
+    let mut truncated = false;
+    for (target, outcome) in outcomes {
+        match outcome {
+            Ok(translation) => {
+                log_translation(config, target, &payload.text, &translation.text);
+                let (text, cut) = payload.limit_output(translation.text);
+                truncated |= cut;
+                results.insert(target.to_string(), text);
+            }
+            Err(err) => return error_response(locale, ApiError::TranslateTarget(target, &err)),
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(TranslateResponse {
+            success: true,
+            results: Some(results),
+            truncated: truncated.then_some(true),
+            ..Default::default()
+        }),
+    )
+}
+
+/// Logs lengths and a short hash, or with `LOG_TRANSLATION_CONTENT` the
+/// texts themselves.
+fn log_translation(config: &Config, target: &str, source: &str, translated: &str) {
+    if config.log_translation_content {
+        println!("[debug] Translated to {target}: {source:?} -> {translated:?}");
+    } else {
+        let hash = format!("{:x}", md5::compute(source));
+        println!(
+            "Translated {} chars to {target} ({} chars out, input {})",
+            source.chars().count(),
+            translated.chars().count(),
+            &hash[..8]
+        );
+    }
+}
+
+/// Runs `fut` under `TOTAL_TIMEOUT_SECS`; `None` means the deadline passed.
+async fn within_deadline<T>(config: &Config, fut: impl Future<Output = T>) -> Option<T> {
+    match config.total_timeout {
+        Some(limit) => tokio::time::timeout(limit, fut).await.ok(),
+        None => Some(fut.await),
+    }
+}
+
+fn deadline_response(config: &Config, locale: Locale, progress: &AtomicUsize) -> ApiResponse {
+    error_response(
+        locale,
+        ApiError::DeadlineExceeded {
+            secs: config.total_timeout.unwrap_or_default().as_secs(),
+            completed: progress.load(Ordering::Relaxed),
+        },
+    )
+}
+
+async fn translate_file_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    let _permit = match admit(&state, &headers) {
+        Ok(permit) => permit,
+        Err(err) => return http_response(error_response(locale, err)),
+    };
+    match translate_upload(&state, locale, client, multipart).await {
+        Ok(resp) => resp,
+        Err(resp) => http_response(resp),
+    }
+}
+
+async fn translate_upload(
+    state: &AppState,
+    locale: Locale,
+    client: Caller,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Response, ApiResponse> {
+    let config = state.config();
+    let max_bytes = config.max_upload_bytes;
+    let upload_error = |err: MultipartError| {
+        if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            error_response(locale, ApiError::FileTooLarge(max_bytes))
+        } else {
+            error_response(locale, ApiError::InvalidRequest(err.body_text()))
+        }
+    };
+    let mut multipart = multipart
+        .map_err(|err| error_response(locale, ApiError::InvalidRequest(err.body_text())))?;
+
+    let mut upload = None;
+    let mut source = None;
+    let mut target = String::new();
+    while let Some(mut field) = multipart.next_field().await.map_err(upload_error)? {
+        match field.name() {
+            Some("file") => {
+                let file_name = field.file_name().map(str::to_string);
+                let mut bytes = Vec::new();
+                while let Some(chunk) = field.chunk().await.map_err(upload_error)? {
+                    if bytes.len() + chunk.len() > max_bytes {
+                        return Err(error_response(locale, ApiError::FileTooLarge(max_bytes)));
+                    }
+                    bytes.extend_from_slice(&chunk);
+                }
+                upload = Some((file_name, bytes));
+            }
+            Some("source") => source = Some(field.text().await.map_err(upload_error)?),
+            Some("target") => target = field.text().await.map_err(upload_error)?,
+            _ => {}
+        }
+    }
+
+    let Some((file_name, bytes)) = upload else {
+        return Err(error_response(locale, ApiError::MissingFile));
+    };
+    let text = String::from_utf8(bytes).map_err(|_| error_response(locale, ApiError::NotUtf8))?;
+    let target = canonical_code(&config, &target).unwrap_or(target);
+    let source = source.map(|source| match is_auto_source(&source) {
+        true => source,
+        false => canonical_code(&config, &source).unwrap_or(source),
+    });
+
+    let payload = TranslateRequest {
+        text,
+        source,
+        target,
+        targets: None,
+        pair: None,
+        formality: None,
+        instruction: None,
+        verbose: false,
+        timing: false,
+        quality: false,
+        no_cache: false,
+        format: upload_format(file_name.as_deref()),
+        refresh: false,
+        use_context: false,
+        include_source: false,
+        max_output_chars: None,
+        summarize: false,
+        summary_max_chars: None,
+        alternatives: None,
+        cache_ttl_secs: None,
+    };
+    let started = Instant::now();
+    let record = state
+        .audit
+        .is_some()
+        .then(|| AuditRecord::for_text(state, client, &payload));
+    let translation = match translate_upload_text(state, &config, locale, &payload).await {
+        Ok(translation) => {
+            let body = TranslateResponse {
+                success: true,
+                cached: Some(translation.cached),
+                detected_source: translation.detected_source.clone(),
+                ..Default::default()
+            };
+            write_audit(state, record, started, &body);
+            translation
+        }
+        Err(response) => {
+            write_audit(state, record, started, &response.1);
+            return Err(response);
+        }
+    };
+    let disposition = format!(
+        "attachment; filename=\"{}\"",
+        attachment_name(file_name.as_deref(), &payload.target)
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        translation.text,
+    )
+        .into_response())
+}
+
+async fn translate_upload_text(
+    state: &AppState,
+    config: &Config,
+    locale: Locale,
+    payload: &TranslateRequest,
+) -> Result<Translation, ApiResponse> {
+    check_payload(state, config, payload)
+        .await
+        .map_err(|err| error_response(locale, err))?;
+    validate_target(state, &payload.target).map_err(|err| error_response(locale, err))?;
+    check_language_limit(state, &payload.target)
+        .await
+        .map_err(|err| error_response(locale, err))?;
+    let progress = AtomicUsize::new(0);
+    let params = TranslateParams {
+        progress: Some(&progress),
+        ..payload.params(config, &payload.target)
+    };
+    let outcome = within_deadline(
+        config,
+        state.translator.translate_with(&payload.text, &params),
+    )
+    .await;
+    let Some(outcome) = outcome else {
+        return Err(deadline_response(config, locale, &progress));
+    };
+    outcome.map_err(|err| error_response(locale, ApiError::Translate(&err)))
+}
+
+/// Uploaded `.md` files are split as Markdown.
+fn upload_format(file_name: Option<&str>) -> TextFormat {
+    let is_markdown = file_name
+        .and_then(|name| name.rsplit_once('.'))
+        .is_some_and(|(_, ext)| {
+            ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown")
+        });
+    if is_markdown {
+        TextFormat::Markdown
+    } else {
+        TextFormat::Plain
+    }
+}
+
+fn attachment_name(file_name: Option<&str>, target: &str) -> String {
+    let stem = file_name
+        .map(|name| name.rsplit_once('.').map_or(name, |(stem, _)| stem))
+        .unwrap_or_default();
+    let safe = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            .collect()
+    };
+    let stem = match safe(stem) {
+        stem if stem.is_empty() => "translation".to_string(),
+        stem => stem,
+    };
+    format!("{stem}.{}.txt", safe(target))
+}
+
+/// Refuses text matching `DENY_PATTERNS`; only the category is logged.
+fn check_denied(config: &Config, text: &str) -> Result<(), ApiError<'static>> {
+    match config
+        .deny_patterns
+        .iter()
+        .find(|(_, re)| re.is_match(text))
+    {
+        Some((category, _)) => {
+            println!("Blocked a translation request (category: {category})");
+            Err(ApiError::ContentBlocked)
+        }
+        None => Ok(()),
+    }
+}
+
+async fn check_language_limit<'a>(state: &AppState, target: &'a str) -> Result<(), ApiError<'a>> {
+    state
+        .language_limiter
+        .allow(target)
+        .await
+        .map_err(|limited| ApiError::LanguageRateLimited(target, limited))
+}
+
+fn canonical_code(config: &Config, code: &str) -> Option<String> {
+    canonical_language(code, |candidate| config.languages.contains_key(candidate))
+}
+
+fn validate_target<'a>(state: &AppState, target: &'a str) -> Result<(), ApiError<'a>> {
+    if target.trim().is_empty() {
+        return Err(ApiError::EmptyTarget);
+    }
+    if !state.config().languages.contains_key(target) {
+        return Err(ApiError::UnsupportedTarget(target));
+    }
+    Ok(())
+}
+
+async fn ws_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    let slots = match authorize(&state, &headers) {
+        Ok(slots) => slots,
+        Err(err) => return http_response(error_response(locale, err)),
+    };
+    ws.on_upgrade(move |socket| ws_session(socket, state, locale, client, slots))
+}
+
+async fn ws_session(
+    mut socket: WebSocket,
+    state: AppState,
+    locale: Locale,
+    client: Caller,
+    slots: Option<Arc<Semaphore>>,
+) {
+    let limiter = RateLimiter::new(Duration::from_secs(60), state.config().ws_rate_limit_rpm);
+
+    while let Some(message) = socket.recv().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        let (_, Json(response)) = if let Err(limited) = limiter.allow().await {
+            error_response(locale, ApiError::RateLimited(limited))
+        } else {
+            match (
+                acquire_slot(slots.clone()),
+                serde_json::from_str::<TranslateRequest>(&text),
+            ) {
+                (Err(err), _) => error_response(locale, err),
+                (Ok(_permit), Ok(payload)) => {
+                    handle_translate(&state, locale, client, payload).await
+                }
+                (Ok(_), Err(err)) => {
+                    error_response(locale, ApiError::InvalidRequest(err.to_string()))
+                }
+            }
+        };
+
+        let Ok(frame) = serde_json::to_string(&response) else {
+            break;
+        };
+        if socket.send(Message::Text(frame)).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn error_response(locale: Locale, error: ApiError) -> ApiResponse {
+    let code = error.code();
+    let limited = match error {
+        ApiError::RateLimited(limited) | ApiError::LanguageRateLimited(_, limited) => Some(limited),
+        _ => None,
+    };
+    let retry_after = match error {
+        ApiError::RateLimited(limited) | ApiError::LanguageRateLimited(_, limited) => {
+            Some(limited.retry_after.as_millis().div_ceil(1000).max(1) as u64)
+        }
+        ApiError::Translate(TranslateError::BudgetExceeded { retry_after })
+        | ApiError::TranslateTarget(_, TranslateError::BudgetExceeded { retry_after }) => {
+            Some(retry_after.as_secs().max(1))
+        }
+        ApiError::Overloaded => Some(1),
+        _ => None,
+    };
+    (
+        code.status(),
+        Json(TranslateResponse {
+            success: false,
+            error: Some(error.message(locale)),
+            code: Some(code),
+            retry_after,
+            limit: limited.map(|limited| limited.limit),
+            window_secs: limited.map(|limited| limited.window.as_secs()),
+            ..Default::default()
+        }),
+    )
+}
+
+fn wants_no_store(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+}
+
+fn schema_response(schema: ResponseSchema, (status, Json(body)): ApiResponse) -> Response {
+    if schema == ResponseSchema::Default {
+        return http_response((status, Json(body)));
+    }
+    let retry_after = body.retry_after;
+    let mut resp = (status, Json(schema.render(body))).into_response();
+    if let Some(secs) = retry_after {
+        resp.headers_mut().insert(header::RETRY_AFTER, secs.into());
+    }
+    resp
+}
+
+fn http_response((status, Json(body)): ApiResponse) -> Response {
+    let retry_after = body.retry_after;
+    let mut resp = (status, Json(body)).into_response();
+    if let Some(secs) = retry_after {
+        resp.headers_mut().insert(header::RETRY_AFTER, secs.into());
+    }
+    resp
+}
+
+impl TranslateRequest {
+    fn params<'a>(&'a self, config: &'a Config, target: &'a str) -> TranslateParams<'a> {
+        TranslateParams {
+            source: self.effective_source(config),
+            target,
+            formality: self.formality,
+            instruction: self
+                .instruction
+                .as_deref()
+                .or(config.default_instruction.as_deref())
+                .filter(|s| !s.trim().is_empty()),
+            no_cache: self.no_cache,
+            format: self.format,
+            refresh: self.refresh,
+            model: None,
+            segments: self.include_source,
+            progress: None,
+            use_context: self.use_context,
+            context: None,
+            temperature: None,
+            cache_ttl: self
+                .cache_ttl_secs
+                .map(|secs| Duration::from_secs(secs).min(config.max_request_cache_ttl)),
+            tokens: None,
+        }
+    }
+
+    fn limit_output(&self, text: String) -> (String, bool) {
+        match self
+            .max_output_chars
+            .and_then(|max| truncate_graphemes(&text, max))
+        {
+            Some(truncated) => (truncated, true),
+            None => (text, false),
+        }
+    }
+
+    fn effective_source<'a>(&'a self, config: &'a Config) -> Option<&'a str> {
+        match self.source.as_deref() {
+            None => config.default_source.as_deref(),
+            Some(source) if is_auto_source(source) => None,
+            Some(source) => Some(source),
+        }
+    }
+
+    /// Rewrites language codes to the supported codes they stand for, so
+    /// `zh-CN` and `zh` share cache entries. Unknown codes are left for
+    /// `validate_target` to reject.
+    fn canonicalize_languages(&mut self, config: &Config) {
+        let canonicalize = |code: &mut String| {
+            if let Some(canonical) = canonical_code(config, code) {
+                *code = canonical;
+            }
+        };
+        canonicalize(&mut self.target);
+        self.targets.iter_mut().flatten().for_each(canonicalize);
+        self.pair.iter_mut().flatten().for_each(canonicalize);
+        self.source
+            .iter_mut()
+            .filter(|source| !is_auto_source(source))
+            .for_each(canonicalize);
+    }
+
+    /// Turns `pair` into a `source` and `target`; `true` if it was set.
+    fn resolve_pair(&mut self) -> Result<bool, ApiError<'static>> {
+        let Some(pair) = self.pair.take() else {
+            return Ok(false);
+        };
+        let invalid = |msg: &str| ApiError::InvalidRequest(msg.to_string());
+        if self.targets.is_some() {
+            return Err(invalid("pair cannot be combined with targets"));
+        }
+        let [first, second] = <[String; 2]>::try_from(pair)
+            .map_err(|_| invalid("pair must list exactly two languages"))?;
+        if first.trim().eq_ignore_ascii_case(second.trim()) {
+            return Err(invalid("pair languages must differ"));
+        }
+        let source = detect_between(&self.text, [&first, &second]).to_string();
+        self.target = if source == first { second } else { first };
+        self.source = Some(source);
+        Ok(true)
+    }
+
+    fn check_source(&self, config: &Config) -> Result<(), ApiError<'static>> {
+        if config.require_source && self.source.is_none() && config.default_source.is_none() {
+            return Err(ApiError::MissingSource);
+        }
+        Ok(())
+    }
+}
+
+fn is_auto_source(source: &str) -> bool {
+    let source = source.trim();
+    source.is_empty() || source.eq_ignore_ascii_case("auto")
+}
+
+#[derive(Deserialize)]
+struct JobRequest {
+    items: Vec<TranslateRequest>,
+}
+
+/// Starts translating `items` in the background and answers at once with the
+/// job ID to poll. Items are translated like `/api/translate` requests, and
+/// wait out the rate limit instead of failing on it. A running job holds the
+/// caller's per-key slot, an in-flight slot and a `MAX_RUNNING_JOBS` slot
+/// until it finishes or is cancelled.
+async fn create_job_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Caller,
+    ApiJson(payload): ApiJson<JobRequest>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    let key_permit = match admit(&state, &headers) {
+        Ok(permit) => permit,
+        Err(err) => return http_response(error_response(locale, err)),
+    };
+    let config = state.config();
+    if payload.items.is_empty() || payload.items.len() > config.max_job_items {
+        let err = ApiError::InvalidRequest(format!(
+            "items must hold between 1 and {} requests",
+            config.max_job_items
+        ));
+        return http_response(error_response(locale, err));
+    }
+    let Some(job_permit) = state.jobs.reserve() else {
+        let err = ApiError::TooManyJobs(config.max_running_jobs);
+        return http_response(error_response(locale, err));
+    };
+    let inflight_permit = match inflight_slot(&state) {
+        Ok(permit) => permit,
+        Err(err) => return http_response(error_response(locale, err)),
+    };
+
+    let total = payload.items.len();
+    let (id, job) = state.jobs.insert(total).await;
+    let concurrency = config.job_concurrency;
+    tokio::spawn(async move {
+        let _permits = (key_permit, inflight_permit, job_permit);
+        let items = stream::iter(payload.items.into_iter().enumerate())
+            .map(|(index, item)| {
+                let state = &state;
+                let item = translate_job_item(state, locale, client, item);
+                async move { (index, item.await) }
+            })
+            .buffer_unordered(concurrency)
+            .for_each(|(index, response)| {
+                job.record(index, response);
+                future::ready(())
+            });
+        // Dropping `items` on cancel also drops the in-flight translations.
+        let status = tokio::select! {
+            () = items => JobStatus::Completed,
+            () = job.cancel.cancelled() => JobStatus::Cancelled,
+        };
+        job.finish(status);
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "job_id": id, "total": total })),
+    )
+        .into_response()
+}
+
+async fn translate_job_item(
+    state: &AppState,
+    locale: Locale,
+    client: Caller,
+    item: TranslateRequest,
+) -> TranslateResponse {
+    loop {
+        let (_, Json(response)) = handle_translate(state, locale, client, item.clone()).await;
+        match response.retry_after {
+            Some(secs) if response.code == Some(ErrorCode::RateLimited) => {
+                tokio::time::sleep(Duration::from_secs(secs)).await;
+            }
+            _ => return response,
+        }
+    }
+}
+
+async fn job_status_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    if let Err(err) = authorize(&state, &headers) {
+        return http_response(error_response(locale, err));
+    }
+    match state.jobs.get(&id).await {
+        Some(job) => Json(job.snapshot(&id)).into_response(),
+        None => http_response(error_response(locale, ApiError::JobNotFound)),
+    }
+}
+
+/// Stops a running job; items already translated keep their results.
+async fn cancel_job_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    if let Err(err) = authorize(&state, &headers) {
+        return http_response(error_response(locale, err));
+    }
+    let Some(job) = state.jobs.get(&id).await else {
+        return http_response(error_response(locale, ApiError::JobNotFound));
+    };
+    job.cancel.cancel();
+    job.finish(JobStatus::Cancelled);
+    Json(job.snapshot(&id)).into_response()
+}
+
+#[derive(Deserialize)]
+struct RawRequest {
+    input: Value,
+    model: Option<String>,
+}
+
+/// Forwards `input` straight upstream, bypassing chunking, the cache and the
+/// budget, and returns the upstream JSON with the upstream status. Startup
+/// already refuses `ENABLE_DEBUG_ENDPOINTS` without server keys; the admin
+/// check keeps the route closed even if that ever changes.
+async fn debug_raw_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<RawRequest>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    if let Err(err) = authorize_admin(&state, &headers) {
+        return http_response(error_response(locale, err));
+    }
+    if !payload.input.is_array() {
+        let err = ApiError::InvalidRequest("input must be an array".to_string());
+        return http_response(error_response(locale, err));
+    }
+    let json = [(header::CONTENT_TYPE, "application/json")];
+    match state
+        .raw_provider
+        .raw(&payload.input, payload.model.as_deref())
+        .await
+    {
+        Ok(body) => (json, body).into_response(),
+        Err(TranslateError::Status { status, body }) => {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+            (status, json, body).into_response()
+        }
+        Err(err) => http_response(error_response(locale, ApiError::Translate(&err))),
+    }
+}
+
+async fn reload_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let locale = Locale::from_headers(&headers);
+    if let Err(err) = authorize_admin(&state, &headers) {
+        return http_response(error_response(locale, err));
+    }
+    let fresh = match load_config() {
+        Ok(fresh) => fresh,
+        Err(err) => return http_response(error_response(locale, ApiError::InvalidConfig(err))),
+    };
+
+    let current = state.config();
+    let ignored = current.restart_only_changes(&fresh);
+    state.limiter.set_max(fresh.rate_limit_rpm);
+    state.config.store(Arc::new(current.reloaded(fresh)));
+    if ignored.is_empty() {
+        println!("Configuration reloaded");
+    } else {
+        println!(
+            "Configuration reloaded; restart to apply {}",
+            ignored.join(", ")
+        );
+    }
+    Json(json!({ "success": true, "ignored": ignored })).into_response()
+}
+
+/// Dumps the cached document translations as a translation memory that
+/// `POST /api/tm/import` accepts as is, along with the key prefix they were
+/// cached under; an import re-keys them under the importer's own prefix.
+async fn tm_export_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let locale = Locale::from_headers(&headers);
+    if let Err(err) = authorize_admin(&state, &headers) {
+        return http_response(error_response(locale, err));
+    }
+    let cache = state.translator.cache();
+    let entries = cache.export().await;
+    Json(json!({ "success": true, "key_prefix": cache.key_prefix(), "entries": entries }))
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct MemoryImport {
+    entries: Vec<MemoryEntry>,
+}
+
+async fn tm_import_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<MemoryImport>,
+) -> Response {
+    let locale = Locale::from_headers(&headers);
+    if let Err(err) = authorize_admin(&state, &headers) {
+        return http_response(error_response(locale, err));
+    }
+    let total = payload.entries.len();
+    let imported = state.translator.cache().import(payload.entries).await;
+    println!("Imported {imported} of {total} translation memory entries");
+    Json(json!({ "success": true, "imported": imported, "skipped": total - imported }))
+        .into_response()
+}
+
+/// Clients poll the language list; it only changes on reload.
+const LANGUAGES_CACHE_CONTROL: &str = "public, max-age=300";
+
+/// The `/api/languages` body, rendered once per loaded language set.
+struct LanguagesResponse {
+    body: Bytes,
+    etag: String,
+}
+
+impl LanguagesResponse {
+    fn new(languages: &BTreeMap<String, String>) -> Self {
+        let body = json!({ "success": true, "languages": languages }).to_string();
+        let etag = format!("\"{:x}\"", md5::compute(&body));
+        Self {
+            body: Bytes::from(body),
+            etag,
+        }
+    }
+}
+
+async fn languages_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let config = state.config();
+    let languages = &config.languages_response;
+    let caching = [
+        (header::ETAG, languages.etag.clone()),
+        (header::CACHE_CONTROL, LANGUAGES_CACHE_CONTROL.to_string()),
+    ];
+    if etag_matches(&headers, &languages.etag) {
+        return (StatusCode::NOT_MODIFIED, caching).into_response();
+    }
+    (
+        caching,
+        [(header::CONTENT_TYPE, "application/json")],
+        languages.body.clone(),
+    )
+        .into_response()
+}
+
+fn default_languages() -> BTreeMap<String, String> {
+    [
+        ("zh", "中文（简体）"),
+        ("zh-Hant", "中文（繁体）"),
+        ("en", "英语"),
+        ("ja", "日语"),
+        ("ko", "韩语"),
+        ("de", "德语"),
+        ("fr", "法语"),
+        ("es", "西班牙语"),
+        ("it", "意大利语"),
+        ("pt", "葡萄牙语"),
+        ("ru", "俄语"),
+        ("th", "泰语"),
+        ("vi", "越南语"),
+        ("ar", "阿拉伯语"),
+    ]
+    .into_iter()
+    .map(|(code, name)| (code.to_string(), name.to_string()))
+    .collect()
+}
+
+fn load_languages(path: &str) -> Result<BTreeMap<String, String>, String> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read LANGUAGES_FILE {path}: {e}"))?;
+    let languages: BTreeMap<String, String> = serde_json::from_str(&raw)
+        .map_err(|e| format!("LANGUAGES_FILE {path} must be a JSON object of code -> name: {e}"))?;
+    if languages.is_empty() {
+        return Err(format!("LANGUAGES_FILE {path} defines no languages"));
+    }
+    if let Some(code) = languages.keys().find(|code| code.trim().is_empty()) {
+        return Err(format!(
+            "LANGUAGES_FILE {path} contains an invalid code {code:?}"
+        ));
+    }
+    Ok(languages)
+}
+
+async fn health_handler() -> Json<Value> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Json(json!({ "status": "healthy", "time": now }))
+}
+
+/// `/` for API-only deployments, where there is no `index.html` to serve.
+async fn root_fallback_handler() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "service": "doubao-translator",
+            "version": env!("CARGO_PKG_VERSION"),
+            "error": "No web UI is served here; the API is under /api (see /api/health)",
+        })),
+    )
+}
+
+/// `build_time` is the Unix time of the built commit, so rebuilding the same
+/// commit reports the same value.
+async fn version_handler() -> Json<Value> {
+    let commit_time: Option<u64> = option_env!("GIT_COMMIT_TIME").and_then(|t| t.parse().ok());
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": option_env!("GIT_SHA"),
+        "build_time": commit_time,
+    }))
+}
+
+async fn stats_handler(State(state): State<AppState>) -> Json<Value> {
+    let mut stats = json!({ "cache": state.translator.cache().stats().await });
+    if let Some(budget) = state.translator.budget() {
+        stats["budget"] = json!(budget.usage());
+    }
+    Json(stats)
+}
+
+async fn seed_cache(cache: &Cache, path: &str) {
+    let raw = match std::fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) => {
+            eprintln!("Cache seed error: failed to read {path}: {err}");
+            return;
+        }
+    };
+    let entries: Vec<Value> = match serde_json::from_str(&raw) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("Cache seed error: {path} is not a JSON array: {err}");
+            return;
+        }
+    };
+
+    let mut loaded = 0usize;
+    for (index, entry) in entries.into_iter().enumerate() {
+        match serde_json::from_value::<SeedEntry>(entry) {
+            Ok(entry) if entry.text.is_empty() || entry.target.trim().is_empty() => {
+                eprintln!("Cache seed: skipping entry {index}: empty text or target");
+            }
+            Ok(entry) => {
+                let params = TranslateParams {
+                    source: entry.source.as_deref().filter(|s| !is_auto_source(s)),
+                    ..TranslateParams::new(&entry.target)
+                };
+                let key = cache.key(&entry.text, &params);
+                cache.set(key, entry.translation).await;
+                loaded += 1;
+            }
+            Err(err) => eprintln!("Cache seed: skipping entry {index}: {err}"),
+        }
+    }
+    println!("Cache seeded with {loaded} entries from {path}");
+}
+
+/// The directory to mount at `/`, `/static` and `/libs`, or `None` when static
+/// serving is off or the directory is missing.
+fn static_root(config: &Config) -> Option<PathBuf> {
+    if !config.serve_static {
+        return None;
+    }
+    if !config.static_dir.is_dir() {
+        eprintln!(
+            "Static dir {} not found; serving the API only (set SERVE_STATIC=false to silence this)",
+            config.static_dir.display()
+        );
+        return None;
+    }
+    Some(config.static_dir.clone())
+}
+
+/// Emails, then card-like digit runs, then phone numbers.
+const DEFAULT_REDACT_PATTERN: &str = concat!(
+    r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+    r"|\b(?:\d[ -]?){12,18}\d\b",
+    r"|\+?\d[\d ().-]{6,}\d",
+);
+
+fn load_config() -> Result<Config, String> {
+    let settings = Settings::load()?;
+    let mock_mode = settings.bool("MOCK_MODE", false);
+    let provider = settings
+        .var("PROVIDER")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| if mock_mode { "mock" } else { "doubao" }.to_string());
+    if mock_mode && provider != "mock" {
+        return Err(format!("MOCK_MODE=true conflicts with PROVIDER={provider}"));
+    }
+    if !matches!(provider.as_str(), "doubao" | "mock") {
+        return Err(format!(
+            "unknown PROVIDER {provider:?} (expected doubao or mock)"
+        ));
+    }
+    let api_key = match settings.var("ARK_API_KEY") {
+        Ok(api_key) => api_key,
+        Err(_) if provider == "mock" => String::new(),
+        Err(_) => return Err("ARK_API_KEY not set".to_string()),
+    };
+    let mut translator = TranslatorConfig::new(api_key);
+    if let Ok(api_url) = settings.var("ARK_API_URL") {
+        translator.api_url = api_url;
+    }
+    translator.api_url_backups = settings
+        .var("ARK_API_URL_BACKUPS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect();
+    translator.max_response_bytes =
+        settings.usize("MAX_RESPONSE_BYTES", translator.max_response_bytes);
+    translator.strip_model_artifacts = settings.bool("STRIP_MODEL_ARTIFACTS", false);
+    translator.retry_on_empty = settings.bool("RETRY_ON_EMPTY", true);
+    translator.upstream_streaming = settings.bool("UPSTREAM_STREAMING", false);
+    translator.skip_whitespace_chunks = settings.bool("SKIP_WHITESPACE_CHUNKS", true);
+    translator.context_chars = settings.usize("CONTEXT_MAX_CHARS", 200);
+    translator.normalize_input = settings.bool("NORMALIZE_INPUT", false);
+    translator.max_paragraphs_per_chunk = settings.usize("MAX_PARAGRAPHS_PER_CHUNK", 0);
+    if let Ok(terminators) = settings.var("SENTENCE_TERMINATORS") {
+        translator.sentence_terminators =
+            terminators.chars().filter(|c| !c.is_whitespace()).collect();
+    }
+    translator.fallback_model = settings
+        .var("FALLBACK_MODEL")
+        .ok()
+        .filter(|v| !v.is_empty());
+    if let Ok(api_format) = settings.var("ARK_API_FORMAT") {
+        translator.api_format = api_format
+            .parse()
+            .map_err(|e| format!("invalid ARK_API_FORMAT: {e}"))?;
+    }
+
+    let port = settings
+        .var("PORT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5000);
+    let tls = match (
+        settings.var("TLS_CERT_FILE").ok().filter(|v| !v.is_empty()),
+        settings.var("TLS_KEY_FILE").ok().filter(|v| !v.is_empty()),
+    ) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) => None,
+        _ => return Err("TLS_CERT_FILE and TLS_KEY_FILE must be set together".to_string()),
+    };
+
+    let serve_static = settings.bool("SERVE_STATIC", true);
+    let static_dir = settings
+        .var("STATIC_DIR")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "static".to_string())
+        .into();
+
+    let cache_ttl = settings.usize_in("CACHE_TTL", 3600, 1..=MAX_CACHE_TTL_SECS)?;
+    let max_request_cache_ttl =
+        settings.usize_in("MAX_REQUEST_CACHE_TTL", cache_ttl, 1..=MAX_CACHE_TTL_SECS)?;
+    let cache_max_size = settings.usize_in("CACHE_MAX_SIZE", 1000, 1..=MAX_CACHE_SIZE)?;
+    let cache_key_collapse_whitespace = settings.bool("CACHE_KEY_COLLAPSE_WHITESPACE", false);
+    let cache_chunks = settings.bool("CACHE_CHUNKS", true);
+    let stale_while_revalidate = settings.usize("STALE_WHILE_REVALIDATE_SECS", 0);
+    let cache_ttl_jitter_pct = settings.usize("CACHE_TTL_JITTER_PCT", 0).min(100) as u8;
+    let cache_key_prefix = settings.var("CACHE_KEY_PREFIX").unwrap_or_default();
+    let max_text_length = settings.usize("MAX_TEXT_LENGTH", 5000);
+    let max_chunks = settings.usize("MAX_CHUNKS", 50);
+    let max_targets = settings.usize("MAX_TARGETS", 10);
+    let max_alternatives = settings.usize("MAX_ALTERNATIVES", 3);
+    let max_upload_bytes = settings.usize("MAX_UPLOAD_BYTES", 1024 * 1024);
+    let max_body_bytes = settings.usize("MAX_BODY_BYTES", 2 * 1024 * 1024);
+    let idempotency_ttl = Duration::from_secs(settings.usize("IDEMPOTENCY_TTL_SECS", 300) as u64);
+    let total_timeout = match settings.usize("TOTAL_TIMEOUT_SECS", 0) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs as u64)),
+    };
+    let server_api_keys: Vec<String> = settings
+        .var("SERVER_API_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect();
+    let enable_debug_endpoints = settings.bool("ENABLE_DEBUG_ENDPOINTS", false);
+    if enable_debug_endpoints && server_api_keys.is_empty() {
+        return Err("ENABLE_DEBUG_ENDPOINTS requires SERVER_API_KEYS".to_string());
+    }
+    let fault_injection = parse_fault_injection(&settings)?;
+    let trust_proxy = settings.bool("TRUST_PROXY", false);
+    let per_key_concurrency = settings.usize("PER_KEY_CONCURRENCY", 0);
+    let max_inflight_requests = settings.usize("MAX_INFLIGHT_REQUESTS", 0);
+    let max_connections = settings.usize("MAX_CONNECTIONS", 0);
+    let ndjson_concurrency = settings.usize("NDJSON_CONCURRENCY", 4).max(1);
+    let stream_concurrency = settings.usize("STREAM_CONCURRENCY", 4).max(1);
+    let job_concurrency = settings.usize("JOB_CONCURRENCY", 4).max(1);
+    let max_job_items = settings.usize("MAX_JOB_ITEMS", 1000);
+    let job_ttl = Duration::from_secs(settings.usize("JOB_TTL_SECS", 3600) as u64);
+    let max_running_jobs = settings.usize("MAX_RUNNING_JOBS", 4).max(1);
+    let rate_limit_rpm = settings.usize("RATE_LIMIT_RPM", 30);
+    let ws_rate_limit_rpm = settings.usize("WS_RATE_LIMIT_RPM", rate_limit_rpm);
+    let rate_limit_sweep_secs = settings.usize("RATE_LIMIT_SWEEP_SECS", 30);
+    let rate_limit_per_lang =
+        parse_lang_limits(&settings.var("RATE_LIMIT_PER_LANG").unwrap_or_default())?;
+    let circuit_fail_threshold = settings.usize("CIRCUIT_FAIL_THRESHOLD", 5);
+    let circuit_cooldown_secs = settings.usize("CIRCUIT_COOLDOWN_SECS", 30);
+    let budget = |key| Some(settings.usize(key, 0) as u64).filter(|&max| max > 0);
+    let daily_request_budget = budget("DAILY_REQUEST_BUDGET");
+    let daily_token_budget = budget("DAILY_TOKEN_BUDGET");
+    let budget_state_file = settings
+        .var("BUDGET_STATE_FILE")
+        .ok()
+        .filter(|v| !v.is_empty());
+    let audit_log_file = settings
+        .var("AUDIT_LOG_FILE")
+        .ok()
+        .filter(|v| !v.is_empty());
+    let languages = match settings.var("LANGUAGES_FILE") {
+        Ok(path) if !path.is_empty() => load_languages(&path)?,
+        _ => default_languages(),
+    };
+    let default_source = settings
+        .var("DEFAULT_SOURCE_LANGUAGE")
+        .ok()
+        .filter(|v| !v.trim().is_empty());
+    if let Some(source) = &default_source {
+        if !languages.contains_key(source) {
+            return Err(format!(
+                "DEFAULT_SOURCE_LANGUAGE {source:?} is not a supported language"
+            ));
+        }
+    }
+    let require_source = settings.bool("REQUIRE_SOURCE", false);
+    let cache_seed_file = settings
+        .var("CACHE_SEED_FILE")
+        .ok()
+        .filter(|v| !v.is_empty());
+    let default_instruction = settings
+        .var("DEFAULT_INSTRUCTION")
+        .ok()
+        .filter(|v| !v.trim().is_empty());
+    let protect_pattern = match settings.var("PROTECT_PATTERN") {
+        Ok(pattern) if pattern.is_empty() => None,
+        Ok(pattern) => Some(pattern),
+        Err(_) => Some(r"\{[^}]+\}".to_string()),
+    }
+    .map(|pattern| Regex::new(&pattern).map_err(|e| format!("invalid PROTECT_PATTERN: {e}")))
+    .transpose()?;
+    let response_schema = settings
+        .var("RESPONSE_SCHEMA")
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| format!("invalid RESPONSE_SCHEMA: {e}"))?;
+    let deny_patterns = parse_deny_patterns(&settings.var("DENY_PATTERNS").unwrap_or_default())?;
+    let redact_pattern = settings
+        .bool("REDACT_PII", false)
+        .then(|| {
+            let pattern = settings
+                .var("REDACT_PATTERN")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| DEFAULT_REDACT_PATTERN.to_string());
+            Regex::new(&pattern).map_err(|e| format!("invalid REDACT_PATTERN: {e}"))
+        })
+        .transpose()?;
+    let http_pool_max_idle = settings.usize("HTTP_POOL_MAX_IDLE", 32);
+    let http_request_timeout_secs = settings.usize("HTTP_REQUEST_TIMEOUT_SECS", 30).max(1);
+    let http_pool_idle_secs = settings.usize("HTTP_POOL_IDLE_SECS", 90);
+    let http_tcp_keepalive_secs = settings.usize("HTTP_TCP_KEEPALIVE_SECS", 60);
+    let http_proxy = settings.var("HTTP_PROXY").ok().filter(|v| !v.is_empty());
+    let http_disable_proxy = settings.bool("HTTP_DISABLE_PROXY", false);
+    let http_user_agent = settings
+        .var("HTTP_USER_AGENT")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+    let http_extra_headers =
+        parse_extra_headers(&settings.var("HTTP_EXTRA_HEADERS").unwrap_or_default())?;
+    let enable_http2 = settings.bool("ENABLE_HTTP2", true);
+    let verify_key_on_start = settings.bool("VERIFY_KEY_ON_START", false);
+    let run_selftest = settings.bool("RUN_SELFTEST", false);
+    let selftest_upstream = settings.bool("SELFTEST_UPSTREAM", false);
+    let log_translation_content = settings.bool("LOG_TRANSLATION_CONTENT", false);
+    let http2_keepalive = match settings.usize("HTTP2_KEEPALIVE_SECS", 0) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs as u64)),
+    };
+
+    Ok(Config {
+        translator,
+        provider,
+        port,
+        tls,
+        serve_static,
+        static_dir,
+        cache_ttl: Duration::from_secs(cache_ttl as u64),
+        max_request_cache_ttl: Duration::from_secs(max_request_cache_ttl as u64),
+        cache_ttl_jitter_pct,
+        stale_while_revalidate: Duration::from_secs(stale_while_revalidate as u64),
+        cache_key_prefix,
+        cache_max_size,
+        cache_key_collapse_whitespace,
+        cache_chunks,
+        max_text_length,
+        max_chunks,
+        max_targets,
+        max_alternatives,
+        max_upload_bytes,
+        max_body_bytes,
+        total_timeout,
+        idempotency_ttl,
+        server_api_keys,
+        enable_debug_endpoints,
+        fault_injection,
+        trust_proxy,
+        per_key_concurrency,
+        max_inflight_requests,
+        max_connections,
+        ndjson_concurrency,
+        stream_concurrency,
+        job_concurrency,
+        max_job_items,
+        job_ttl,
+        max_running_jobs,
         rate_limit_rpm,
+        ws_rate_limit_rpm,
+        rate_limit_sweep_interval: Duration::from_secs(rate_limit_sweep_secs as u64),
+        rate_limit_per_lang,
+        circuit_fail_threshold: circuit_fail_threshold as u32,
+        circuit_cooldown: Duration::from_secs(circuit_cooldown_secs as u64),
+        daily_request_budget,
+        daily_token_budget,
+        budget_state_file,
+        audit_log_file,
+        languages_response: Arc::new(LanguagesResponse::new(&languages)),
+        languages: Arc::new(languages),
+        default_source,
+        require_source,
+        cache_seed_file,
+        default_instruction,
+        protect_pattern,
+        response_schema,
+        deny_patterns,
+        redact_pattern,
+        http_request_timeout: Duration::from_secs(http_request_timeout_secs as u64),
+        http_pool_max_idle,
+        http_pool_idle_timeout: Duration::from_secs(http_pool_idle_secs as u64),
+        http_tcp_keepalive: Duration::from_secs(http_tcp_keepalive_secs as u64),
+        http_proxy,
+        http_disable_proxy,
+        http_user_agent,
+        http_extra_headers,
+        enable_http2,
+        http2_keepalive,
+        verify_key_on_start,
+        run_selftest,
+        selftest_upstream,
+        log_translation_content,
+        sources: CONFIG_KEYS
+            .iter()
+            .filter_map(|key| settings.var(key).ok().map(|value| (*key, value)))
+            .collect(),
     })
 }
 
-fn env_usize(key: &str, default: usize) -> usize {
-    env::var(key)
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Parses `DENY_PATTERNS`, `category=regex` entries separated by `;`; a bare
+/// regex is categorised by its position.
+fn parse_deny_patterns(raw: &str) -> Result<Vec<(String, Regex)>, String> {
+    raw.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .enumerate()
+        .map(|(i, entry)| {
+            let (category, pattern) = match entry.split_once('=') {
+                Some((category, pattern))
+                    if !category.is_empty()
+                        && category
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+                {
+                    (category.to_string(), pattern)
+                }
+                _ => (format!("pattern {}", i + 1), entry),
+            };
+            let regex = Regex::new(pattern)
+                .map_err(|e| format!("invalid DENY_PATTERNS entry {category:?}: {e}"))?;
+            Ok((category, regex))
+        })
+        .collect()
+}
+
+fn parse_lang_limits(raw: &str) -> Result<Vec<(String, usize)>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (lang, max) = pair.split_once('=').ok_or_else(|| {
+                format!("invalid RATE_LIMIT_PER_LANG entry {pair:?} (expected lang=rpm)")
+            })?;
+            match max.trim().parse::<usize>() {
+                Ok(max) if max > 0 => Ok((lang.trim().to_string(), max)),
+                _ => Err(format!(
+                    "RATE_LIMIT_PER_LANG limit for {} must be a positive number, got {max:?}",
+                    lang.trim()
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Parses `HTTP_EXTRA_HEADERS`, a `name:value;name:value` list.
+fn parse_extra_headers(raw: &str) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for pair in raw
+        .split(';')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (name, value) = pair.split_once(':').ok_or_else(|| {
+            format!("invalid HTTP_EXTRA_HEADERS entry {pair:?} (expected name:value)")
+        })?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| format!("invalid header name in HTTP_EXTRA_HEADERS: {name:?}"))?;
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|_| format!("invalid header value in HTTP_EXTRA_HEADERS for {name}"))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// 30 days; longer-lived entries are almost certainly a unit mistake.
+const MAX_CACHE_TTL_SECS: usize = 30 * 24 * 3600;
+const MAX_CACHE_SIZE: usize = 1_000_000;
+
+/// Every setting `load_config` reads; `CONFIG_FILE` may only contain these.
+const CONFIG_KEYS: &[&str] = &[
+    "ARK_API_FORMAT",
+    "ARK_API_KEY",
+    "ARK_API_URL",
+    "ARK_API_URL_BACKUPS",
+    "AUDIT_LOG_FILE",
+    "BUDGET_STATE_FILE",
+    "CACHE_CHUNKS",
+    "CACHE_KEY_COLLAPSE_WHITESPACE",
+    "CACHE_KEY_PREFIX",
+    "CACHE_MAX_SIZE",
+    "CACHE_SEED_FILE",
+    "CACHE_TTL",
+    "CACHE_TTL_JITTER_PCT",
+    "CIRCUIT_COOLDOWN_SECS",
+    "CIRCUIT_FAIL_THRESHOLD",
+    "CONTEXT_MAX_CHARS",
+    "DAILY_REQUEST_BUDGET",
+    "DAILY_TOKEN_BUDGET",
+    "DEFAULT_INSTRUCTION",
+    "DEFAULT_SOURCE_LANGUAGE",
+    "DENY_PATTERNS",
+    "ENABLE_DEBUG_ENDPOINTS",
+    "ENABLE_HTTP2",
+    "FALLBACK_MODEL",
+    "FAULT_INJECTION_RATE",
+    "FAULT_INJECTION_STATUS",
+    "HTTP2_KEEPALIVE_SECS",
+    "HTTP_DISABLE_PROXY",
+    "HTTP_EXTRA_HEADERS",
+    "HTTP_POOL_IDLE_SECS",
+    "HTTP_POOL_MAX_IDLE",
+    "HTTP_PROXY",
+    "HTTP_REQUEST_TIMEOUT_SECS",
+    "HTTP_TCP_KEEPALIVE_SECS",
+    "HTTP_USER_AGENT",
+    "IDEMPOTENCY_TTL_SECS",
+    "JOB_CONCURRENCY",
+    "JOB_TTL_SECS",
+    "LANGUAGES_FILE",
+    "LOG_TRANSLATION_CONTENT",
+    "MAX_ALTERNATIVES",
+    "MAX_BODY_BYTES",
+    "MAX_CHUNKS",
+    "MAX_CONNECTIONS",
+    "MAX_INFLIGHT_REQUESTS",
+    "MAX_JOB_ITEMS",
+    "MAX_PARAGRAPHS_PER_CHUNK",
+    "MAX_REQUEST_CACHE_TTL",
+    "MAX_RESPONSE_BYTES",
+    "MAX_RUNNING_JOBS",
+    "MAX_TARGETS",
+    "MAX_TEXT_LENGTH",
+    "MAX_UPLOAD_BYTES",
+    "MOCK_MODE",
+    "NDJSON_CONCURRENCY",
+    "NORMALIZE_INPUT",
+    "PER_KEY_CONCURRENCY",
+    "PORT",
+    "PROTECT_PATTERN",
+    "PROVIDER",
+    "RATE_LIMIT_PER_LANG",
+    "RATE_LIMIT_RPM",
+    "RATE_LIMIT_SWEEP_SECS",
+    "REDACT_PATTERN",
+    "REDACT_PII",
+    "REQUIRE_SOURCE",
+    "RESPONSE_SCHEMA",
+    "RETRY_ON_EMPTY",
+    "RUN_SELFTEST",
+    "SELFTEST_UPSTREAM",
+    "SENTENCE_TERMINATORS",
+    "SERVER_API_KEYS",
+    "SERVE_STATIC",
+    "SKIP_WHITESPACE_CHUNKS",
+    "STALE_WHILE_REVALIDATE_SECS",
+    "STATIC_DIR",
+    "STREAM_CONCURRENCY",
+    "STRIP_MODEL_ARTIFACTS",
+    "TLS_CERT_FILE",
+    "TLS_KEY_FILE",
+    "TOTAL_TIMEOUT_SECS",
+    "TRUST_PROXY",
+    "UPSTREAM_STREAMING",
+    "VERIFY_KEY_ON_START",
+    "WS_RATE_LIMIT_RPM",
+];
+
+/// Reads `FAULT_INJECTION_RATE` (0 to 1) and `FAULT_INJECTION_STATUS` (an
+/// error status, 503 by default); `None` unless the rate is set above 0.
+fn parse_fault_injection(settings: &Settings) -> Result<Option<(f64, StatusCode)>, String> {
+    let rate = match settings.var("FAULT_INJECTION_RATE") {
+        Ok(raw) if !raw.trim().is_empty() => raw
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|rate| (0.0..=1.0).contains(rate))
+            .ok_or_else(|| format!("FAULT_INJECTION_RATE must be between 0 and 1, got {raw:?}"))?,
+        _ => return Ok(None),
+    };
+    let status = settings.usize("FAULT_INJECTION_STATUS", 503);
+    let status = u16::try_from(status)
         .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(default)
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .filter(|status| status.is_client_error() || status.is_server_error())
+        .ok_or_else(|| {
+            format!("FAULT_INJECTION_STATUS must be a 4xx or 5xx status, got {status}")
+        })?;
+    Ok((rate > 0.0).then_some((rate, status)))
+}
+
+/// Settings come from the environment, falling back to the optional
+/// `CONFIG_FILE` (TOML, or JSON for `.json` paths).
+struct Settings {
+    file: HashMap<String, String>,
+}
+
+impl Settings {
+    fn load() -> Result<Self, String> {
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) if !path.is_empty() => load_config_file(&path)?,
+            _ => HashMap::new(),
+        };
+        Ok(Self { file })
+    }
+
+    fn var(&self, key: &str) -> Result<String, env::VarError> {
+        env::var(key).or_else(|err| self.file.get(key).cloned().ok_or(err))
+    }
+
+    fn usize(&self, key: &str, default: usize) -> usize {
+        self.var(key)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    }
+
+    /// Like `usize`, but a malformed or too small value is an error and a too
+    /// large one is clamped with a warning.
+    fn usize_in(
+        &self,
+        key: &str,
+        default: usize,
+        range: RangeInclusive<usize>,
+    ) -> Result<usize, String> {
+        let value = match self.var(key) {
+            Ok(v) if !v.trim().is_empty() => v
+                .trim()
+                .parse()
+                .map_err(|_| format!("{key} must be a whole number, got {v:?}"))?,
+            _ => default,
+        };
+        if value < *range.start() {
+            return Err(format!("{key} must be at least {}", range.start()));
+        }
+        if value > *range.end() {
+            eprintln!("{key}={value} is above the maximum, using {}", range.end());
+            return Ok(*range.end());
+        }
+        Ok(value)
+    }
+
+    fn bool(&self, key: &str, default: bool) -> bool {
+        match self.var(key) {
+            Ok(v) => matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            ),
+            Err(_) => default,
+        }
+    }
+}
+
+/// Reads a flat table of settings. Keys are the env var names in any case;
+/// lists are joined with commas, as `SERVER_API_KEYS` expects.
+fn load_config_file(path: &str) -> Result<HashMap<String, String>, String> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+    let table: serde_json::Map<String, serde_json::Value> = if path.ends_with(".json") {
+        serde_json::from_str(&raw).map_err(|e| format!("invalid JSON in {path}: {e}"))?
+    } else {
+        toml::from_str(&raw).map_err(|e| format!("invalid TOML in {path}: {e}"))?
+    };
+
+    let mut settings = HashMap::new();
+    let mut unknown = Vec::new();
+    for (key, value) in table {
+        let name = key.to_ascii_uppercase();
+        if !CONFIG_KEYS.contains(&name.as_str()) {
+            unknown.push(key);
+            continue;
+        }
+        let value = match value {
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(|item| {
+                    item.as_str()
+                        .map_or_else(|| item.to_string(), str::to_string)
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            serde_json::Value::Object(_) | serde_json::Value::Null => {
+                return Err(format!(
+                    "{path}: {key} must be a string, number, boolean or list"
+                ))
+            }
+            other => other.to_string(),
+        };
+        settings.insert(name, value);
+    }
+    if !unknown.is_empty() {
+        return Err(format!("unknown keys in {path}: {}", unknown.join(", ")));
+    }
+    Ok(settings)
 }
@@ -0,0 +1,29 @@
+use std::borrow::Cow;
+
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+/// Offset between a full-width form (U+FF01..) and its ASCII counterpart.
+const FULLWIDTH_OFFSET: u32 = 0xFEE0;
+
+/// Rewrites `text` to NFC and folds full-width ASCII letters and digits to
+/// their half-width forms, so visually identical inputs share a cache key.
+/// Full-width punctuation is kept: it is the native form in CJK text.
+pub fn normalize_input(text: &str) -> Cow<'_, str> {
+    let nfc = matches!(is_nfc_quick(text.chars()), IsNormalized::Yes);
+    if nfc && !text.chars().any(is_fullwidth_alphanumeric) {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(text.nfc().map(fold_fullwidth).collect())
+}
+
+fn is_fullwidth_alphanumeric(c: char) -> bool {
+    matches!(c, '０'..='９' | 'Ａ'..='Ｚ' | 'ａ'..='ｚ')
+}
+
+fn fold_fullwidth(c: char) -> char {
+    if is_fullwidth_alphanumeric(c) {
+        char::from_u32(c as u32 - FULLWIDTH_OFFSET).unwrap_or(c)
+    } else {
+        c
+    }
+}
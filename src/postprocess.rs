@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use crate::artifacts;
+
+/// What a [`PostProcessor`] knows about the chunk it is rewriting.
+#[derive(Debug, Clone, Copy)]
+pub struct PostContext<'a> {
+    /// The (masked) source chunk the output was translated from.
+    pub source: &'a str,
+    pub target: &'a str,
+    /// The model that produced the output.
+    pub model: &'a str,
+}
+
+/// One step of the output pipeline, applied to every freshly translated
+/// chunk before it is cached.
+pub trait PostProcessor: Send + Sync {
+    fn process(&self, text: String, ctx: &PostContext<'_>) -> String;
+}
+
+impl<F> PostProcessor for F
+where
+    F: Fn(String, &PostContext<'_>) -> String + Send + Sync,
+{
+    fn process(&self, text: String, ctx: &PostContext<'_>) -> String {
+        self(text, ctx)
+    }
+}
+
+/// Processors run in the order they were added, each one seeing the
+/// previous one's output.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    processors: Vec<Arc<dyn PostProcessor>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub fn run(&self, text: String, ctx: &PostContext<'_>) -> String {
+        self.processors
+            .iter()
+            .fold(text, |text, processor| processor.process(text, ctx))
+    }
+}
+
+/// Drops code fences and "Translation:" labels the model adds on its own.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripArtifacts;
+
+impl PostProcessor for StripArtifacts {
+    fn process(&self, text: String, ctx: &PostContext<'_>) -> String {
+        artifacts::strip_model_artifacts(ctx.source, text)
+    }
+}
@@ -0,0 +1,67 @@
+use regex::Regex;
+
+use crate::TranslateError;
+
+pub(crate) struct Protected {
+    pub(crate) text: String,
+    spans: Vec<String>,
+    tag: &'static str,
+}
+
+pub(crate) fn protect(text: &str, pattern: &Regex) -> Protected {
+    mask(text, pattern, "")
+}
+
+/// Like [`protect`], with distinct placeholders so both can apply to one text.
+pub(crate) fn redact(text: &str, pattern: &Regex) -> Protected {
+    mask(text, pattern, "R")
+}
+
+fn mask(text: &str, pattern: &Regex, tag: &'static str) -> Protected {
+    let mut spans = Vec::new();
+    let text = pattern
+        .replace_all(text, |caps: &regex::Captures| {
+            spans.push(caps[0].to_string());
+            placeholder(tag, spans.len() - 1)
+        })
+        .into_owned();
+    Protected { text, spans, tag }
+}
+
+impl Protected {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub(crate) fn restore(&self, translated: &str) -> Result<String, TranslateError> {
+        let missing: Vec<String> = (0..self.spans.len())
+            .map(|index| placeholder(self.tag, index))
+            .filter(|token| !translated.contains(token.as_str()))
+            .collect();
+        if !missing.is_empty() {
+            return Err(TranslateError::Placeholder(format!(
+                "译文缺少受保护片段的占位符: {}",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(self.unprotect(translated))
+    }
+
+    /// Puts back whichever spans appear in `text`, without checking for missing ones.
+    pub(crate) fn unprotect(&self, text: &str) -> String {
+        let mut restored = text.to_string();
+        for (index, span) in self.spans.iter().enumerate() {
+            restored = restored.replace(&placeholder(self.tag, index), span);
+        }
+        restored
+    }
+}
+
+fn placeholder(tag: &str, index: usize) -> String {
+    format!("⟦{tag}{index}⟧")
+}
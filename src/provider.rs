@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+
+use crate::{truncate_graphemes, TranslateError, TranslateParams};
+
+#[derive(Debug, Clone, Default)]
+pub struct ProviderOutput {
+    pub text: String,
+    pub detected_source: Option<String>,
+    /// Tokens the backend billed for the call, when it reports them.
+    pub tokens: Option<u64>,
+}
+
+#[async_trait]
+pub trait TranslationProvider: Send + Sync {
+    async fn translate(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<String, TranslateError>;
+
+    /// Like [`translate`](Self::translate), plus whatever metadata the
+    /// backend reports. Override when the backend detects the source language.
+    async fn translate_detailed(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<ProviderOutput, TranslateError> {
+        Ok(ProviderOutput {
+            text: self.translate(text, params).await?,
+            detected_source: None,
+            tokens: None,
+        })
+    }
+
+    /// Summarizes `text` in `target` within roughly `max_chars` characters.
+    async fn summarize(
+        &self,
+        _text: &str,
+        _target: &str,
+        _max_chars: usize,
+    ) -> Result<String, TranslateError> {
+        Err(TranslateError::Internal(
+            "当前翻译后端不支持摘要".to_string(),
+        ))
+    }
+}
+
+/// Replies with `[target] text` (and `[target summary] text`) without
+/// touching the network.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MockProvider;
+
+#[async_trait]
+impl TranslationProvider for MockProvider {
+    async fn translate(
+        &self,
+        text: &str,
+        params: &TranslateParams<'_>,
+    ) -> Result<String, TranslateError> {
+        Ok(format!("[{}] {text}", params.target))
+    }
+
+    async fn summarize(
+        &self,
+        text: &str,
+        target: &str,
+        max_chars: usize,
+    ) -> Result<String, TranslateError> {
+        let summary = format!("[{target} summary] {text}");
+        Ok(truncate_graphemes(&summary, max_chars).unwrap_or(summary))
+    }
+}
@@ -0,0 +1,99 @@
+use regex::Regex;
+use serde::Serialize;
+
+/// A rough 0 (suspect) to 1 signal for review triage, from what the result
+/// itself shows; `flags` names what lowered it. The backends report no
+/// confidence of their own, so none is folded in.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quality {
+    pub score: f32,
+    pub flags: Vec<&'static str>,
+}
+
+/// Inputs shorter than this (in weighted characters) vary too much in length
+/// to judge the ratio.
+const MIN_RATIO_INPUT: usize = 20;
+/// Translated length over source length, counting CJK characters double.
+const LENGTH_RATIO: std::ops::RangeInclusive<f32> = 0.33..=3.0;
+
+/// Scores `translation` of `source` into `target`: blank output scores 0,
+/// and an unchanged text, a length ratio far off, output mostly in the
+/// source's script, or `protect` matches missing from the output each cut
+/// the score.
+pub fn estimate_quality(
+    source: &str,
+    translation: &str,
+    target: &str,
+    protect: Option<&Regex>,
+) -> Quality {
+    if translation.trim().is_empty() && !source.trim().is_empty() {
+        return Quality {
+            score: 0.0,
+            flags: vec!["empty"],
+        };
+    }
+
+    let mut flags = Vec::new();
+    let mut penalty = 0.0f32;
+    if translation.trim() == source.trim() && source.chars().any(char::is_alphabetic) {
+        flags.push("unchanged");
+        penalty += 0.75;
+    }
+
+    let (source_len, output_len) = (weighted_len(source), weighted_len(translation));
+    if source_len >= MIN_RATIO_INPUT
+        && !LENGTH_RATIO.contains(&(output_len as f32 / source_len as f32))
+    {
+        flags.push("length_ratio");
+        penalty += 0.5;
+    }
+
+    // Leftovers only count in a script the target doesn't use anyway.
+    let cjk_target = matches!(base_language(target), "zh" | "ja" | "ko");
+    let foreign = |c: char| match cjk_target {
+        true => c.is_ascii_alphabetic(),
+        false => is_cjk(c),
+    };
+    let letters = translation.chars().filter(|c| c.is_alphabetic()).count();
+    let leftover = translation.chars().filter(|&c| foreign(c)).count();
+    if source.chars().any(foreign) && letters >= 10 && leftover * 2 > letters {
+        flags.push("untranslated");
+        penalty += 0.5;
+    }
+
+    if let Some(pattern) = protect {
+        let missing = pattern
+            .find_iter(source)
+            .any(|span| !translation.contains(span.as_str()));
+        if missing {
+            flags.push("placeholders");
+            penalty += 0.25;
+        }
+    }
+
+    Quality {
+        score: (1.0 - penalty).max(0.0),
+        flags,
+    }
+}
+
+fn weighted_len(text: &str) -> usize {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if is_cjk(c) { 2 } else { 1 })
+        .sum()
+}
+
+/// Han, kana and Hangul.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}'
+        | '\u{f900}'..='\u{faff}')
+}
+
+fn base_language(code: &str) -> &str {
+    code.split(['-', '_']).next().unwrap_or(code)
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+
+/// Why [`RateLimiter::allow`] turned a request away, so callers can tell
+/// clients how to pace themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    pub limit: usize,
+    pub window: Duration,
+    /// Until the oldest request in the window falls out of it.
+    pub retry_after: Duration,
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    window: Duration,
+    max: Arc<AtomicUsize>,
+    hits: Arc<Mutex<VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(window: Duration, max: usize) -> Self {
+        Self {
+            window,
+            max: Arc::new(AtomicUsize::new(max.max(1))),
+            hits: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub async fn allow(&self) -> Result<(), RateLimited> {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().await;
+        self.evict_expired(&mut hits, now);
+        let limit = self.max.load(Ordering::Relaxed);
+        if hits.len() >= limit {
+            let oldest = hits.front().copied().unwrap_or(now);
+            return Err(RateLimited {
+                limit,
+                window: self.window,
+                retry_after: self.window.saturating_sub(now.duration_since(oldest)),
+            });
+        }
+        hits.push_back(now);
+        Ok(())
+    }
+
+    /// Changes the limit for this limiter and all its clones.
+    pub fn set_max(&self, max: usize) {
+        self.max.store(max.max(1), Ordering::Relaxed);
+    }
+
+    /// Hits currently held, including expired ones not yet evicted.
+    pub async fn hit_count(&self) -> usize {
+        self.hits.lock().await.len()
+    }
+
+    pub async fn sweep(&self) {
+        let mut hits = self.hits.lock().await;
+        self.evict_expired(&mut hits, Instant::now());
+        hits.shrink_to_fit();
+    }
+
+    pub fn spawn_sweeper(&self, interval: Duration) {
+        let limiter = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                limiter.sweep().await;
+            }
+        });
+    }
+
+    fn evict_expired(&self, hits: &mut VecDeque<Instant>, now: Instant) {
+        while let Some(front) = hits.front() {
+            if now.duration_since(*front) > self.window {
+                hits.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Separate limits for individual target languages, checked in addition to
+/// the global [`RateLimiter`]. Languages without a limit are always allowed.
+#[derive(Clone, Default)]
+pub struct LanguageRateLimiter {
+    limits: Arc<HashMap<String, RateLimiter>>,
+}
+
+impl LanguageRateLimiter {
+    pub fn new(window: Duration, limits: impl IntoIterator<Item = (String, usize)>) -> Self {
+        let limits = limits
+            .into_iter()
+            .map(|(lang, max)| (lang, RateLimiter::new(window, max)))
+            .collect();
+        Self {
+            limits: Arc::new(limits),
+        }
+    }
+
+    pub async fn allow(&self, lang: &str) -> Result<(), RateLimited> {
+        match self.limits.get(lang) {
+            Some(limiter) => limiter.allow().await,
+            None => Ok(()),
+        }
+    }
+
+    pub fn spawn_sweeper(&self, interval: Duration) {
+        for limiter in self.limits.values() {
+            limiter.spawn_sweeper(interval);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+/// Latin and CJK sentence-ending punctuation.
+pub const DEFAULT_SENTENCE_TERMINATORS: &str = ".!?。！？";
+
+/// Chunks like this (runs of blank lines, say) have nothing to translate.
+pub fn is_whitespace_only(chunk: &str) -> bool {
+    chunk.chars().all(char::is_whitespace)
+}
+
+pub fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    split_text_with(text, max_chars, DEFAULT_SENTENCE_TERMINATORS)
+}
+
+/// Splits on blank lines, then lines, then after any of the `terminators`
+/// characters, and only then mid-sentence.
+pub fn split_text_with(text: &str, max_chars: usize, terminators: &str) -> Vec<String> {
+    split_text_limited(text, max_chars, 0, terminators)
+}
+
+/// Like [`split_text_with`], but a chunk also ends after `max_paragraphs`
+/// blank-line-separated paragraphs, however short (0 = no limit).
+pub fn split_text_limited(
+    text: &str,
+    max_chars: usize,
+    max_paragraphs: usize,
+    terminators: &str,
+) -> Vec<String> {
+    let separators: &[&str] = if text.contains("\r\n") {
+        &["\r\n\r\n", "\r\n"]
+    } else {
+        &["\n\n", "\n"]
+    };
+    let few_paragraphs =
+        max_paragraphs == 0 || text.matches(separators[0]).count() < max_paragraphs;
+    if few_paragraphs && text.chars().count() <= max_chars {
+        return vec![text.to_string()];
+    }
+    split_on(text, separators, max_chars, max_paragraphs, terminators)
+}
+
+fn split_on(
+    text: &str,
+    separators: &[&str],
+    max_chars: usize,
+    max_paragraphs: usize,
+    terminators: &str,
+) -> Vec<String> {
+    let Some((separator, rest)) = separators.split_first() else {
+        return split_by_sentences(text, max_chars, terminators);
+    };
+    let sep_len = separator.chars().count();
+
+    let paragraphs: Vec<&str> = text.split(separator).collect();
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0usize;
+    let mut current_paragraphs = 0usize;
+
+    for paragraph in paragraphs {
+        let para_len = paragraph.chars().count();
+        if para_len > max_chars {
+            if !current.is_empty() {
+                chunks.push(current);
+                current = String::new();
+                current_len = 0;
+                current_paragraphs = 0;
+            }
+            // Lines within a paragraph are not paragraphs themselves.
+            chunks.extend(split_on(paragraph, rest, max_chars, 0, terminators));
+            continue;
+        }
+
+        let extra = if current.is_empty() { 0 } else { sep_len };
+        let full = max_paragraphs > 0 && current_paragraphs >= max_paragraphs;
+        if !current.is_empty() && (full || current_len + extra + para_len > max_chars) {
+            chunks.push(current);
+            current = paragraph.to_string();
+            current_len = para_len;
+            current_paragraphs = 1;
+        } else {
+            if !current.is_empty() {
+                current.push_str(separator);
+                current_len += sep_len;
+            }
+            current.push_str(paragraph);
+            current_len += para_len;
+            current_paragraphs += 1;
+        }
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Packs whole sentences into chunks. A sentence ends after a run of
+/// terminators plus any whitespace that follows.
+fn split_by_sentences(text: &str, max_chars: usize, terminators: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, ch)) = chars.next() {
+        if !terminators.contains(ch) {
+            continue;
+        }
+        while let Some(&(_, next)) = chars.peek() {
+            if terminators.contains(next) || next.is_whitespace() {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+        sentences.push(&text[start..end]);
+        start = end;
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0usize;
+    for sentence in sentences {
+        let len = sentence.chars().count();
+        if current_len + len > max_chars && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        if len > max_chars {
+            chunks.extend(split_by_chars(sentence, max_chars));
+            continue;
+        }
+        current.push_str(sentence);
+        current_len += len;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn split_by_chars(text: &str, max_chars: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut count = 0usize;
+
+    for ch in text.chars() {
+        current.push(ch);
+        count += 1;
+        if count >= max_chars {
+            parts.push(current);
+            current = String::new();
+            count = 0;
+        }
+    }
+
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+struct Block {
+    text: String,
+    chars: usize,
+    fence: bool,
+}
+
+/// Splits Markdown at line boundaries, so joining the chunks with `\n`
+/// restores the text. Fenced code blocks are never split, even when longer
+/// than `max_chars`, and sections that fit are kept whole so chunks break
+/// before headings where possible.
+pub fn split_markdown(text: &str, max_chars: usize, terminators: &str) -> Vec<String> {
+    if text.chars().count() <= max_chars {
+        return vec![text.to_string()];
+    }
+
+    let mut sections: Vec<Vec<Block>> = Vec::new();
+    for block in markdown_blocks(text) {
+        let starts_section = is_heading(&block.text) || sections.is_empty();
+        if starts_section {
+            sections.push(Vec::new());
+        }
+        sections
+            .last_mut()
+            .expect("a section was pushed")
+            .push(block);
+    }
+
+    let mut items = Vec::new();
+    for section in sections {
+        let chars = section.iter().map(|b| b.chars).sum::<usize>() + section.len() - 1;
+        if chars <= max_chars {
+            let text = section
+                .iter()
+                .map(|b| b.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            items.push(Block {
+                text,
+                chars,
+                fence: false,
+            });
+        } else {
+            items.extend(section);
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0usize;
+    for item in items {
+        let extra = usize::from(!current.is_empty());
+        if !current.is_empty() && current_len + extra + item.chars > max_chars {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        if item.chars > max_chars {
+            if item.fence {
+                chunks.push(item.text);
+            } else {
+                chunks.extend(split_text_with(&item.text, max_chars, terminators));
+            }
+            continue;
+        }
+        if !current.is_empty() {
+            current.push('\n');
+            current_len += 1;
+        }
+        current.push_str(&item.text);
+        current_len += item.chars;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Groups lines into headings, fenced code blocks and paragraphs; blank lines
+/// stay with the block before them.
+fn markdown_blocks(text: &str) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut lines = text.split('\n');
+    let mut open = false;
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        let fence = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker));
+        let block_text = if let Some(marker) = fence {
+            let mut fenced = vec![line];
+            for inner in lines.by_ref() {
+                fenced.push(inner);
+                if inner.trim_start().starts_with(marker) {
+                    break;
+                }
+            }
+            Some((fenced.join("\n"), true))
+        } else if is_heading(line) {
+            Some((line.to_string(), false))
+        } else {
+            None
+        };
+
+        match block_text {
+            Some((text, fence)) => {
+                blocks.push(Block {
+                    chars: text.chars().count(),
+                    text,
+                    fence,
+                });
+                open = false;
+            }
+            None => match blocks.last_mut() {
+                Some(last) if line.trim().is_empty() || (open && !last.fence) => {
+                    last.text.push('\n');
+                    last.text.push_str(line);
+                    last.chars += 1 + line.chars().count();
+                    open = !line.trim().is_empty();
+                }
+                _ => {
+                    blocks.push(Block {
+                        text: line.to_string(),
+                        chars: line.chars().count(),
+                        fence: false,
+                    });
+                    open = !line.trim().is_empty();
+                }
+            },
+        }
+    }
+    blocks
+}
+
+fn is_heading(line: &str) -> bool {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    indent < 4 && line.trim_start().starts_with('#')
+}
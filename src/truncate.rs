@@ -0,0 +1,27 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+const ELLIPSIS: char = '…';
+
+/// Cuts `text` to at most `max_chars` characters, ellipsis included, without
+/// splitting a grapheme cluster. `None` when it already fits.
+pub fn truncate_graphemes(text: &str, max_chars: usize) -> Option<String> {
+    if text.chars().count() <= max_chars {
+        return None;
+    }
+    let budget = max_chars.saturating_sub(1);
+    let mut end = 0;
+    let mut used = 0;
+    for (index, grapheme) in text.grapheme_indices(true) {
+        let len = grapheme.chars().count();
+        if used + len > budget {
+            break;
+        }
+        used += len;
+        end = index + grapheme.len();
+    }
+    let mut truncated = text[..end].trim_end().to_string();
+    if max_chars > 0 {
+        truncated.push(ELLIPSIS);
+    }
+    Some(truncated)
+}
@@ -0,0 +1,3734 @@
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use doubao_translator::{is_whitespace_only, split_text};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+struct TestServer {
+    child: Child,
+    base_url: String,
+    workdir: PathBuf,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.workdir);
+    }
+}
+
+impl TestServer {
+    async fn start(upstream: &MockServer, extra_env: &[(&str, &str)]) -> Self {
+        let port = free_port();
+        let workdir = std::env::temp_dir().join(format!("translator-test-{port}"));
+        std::fs::create_dir_all(&workdir).expect("failed to create workdir");
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_translator"));
+        command
+            .current_dir(&workdir)
+            .env_clear()
+            .env("ARK_API_KEY", "test-key")
+            .env(
+                "ARK_API_URL",
+                format!("{}/api/v3/responses", upstream.uri()),
+            )
+            .env("PORT", port.to_string())
+            .env("HTTP_DISABLE_PROXY", "true")
+            .stdout(log_file(&workdir, "stdout.log"))
+            .stderr(log_file(&workdir, "stderr.log"));
+        for (key, value) in extra_env {
+            command.env(key, value);
+        }
+        let child = command.spawn().expect("failed to start translator");
+
+        let tls = extra_env.iter().any(|(key, _)| *key == "TLS_CERT_FILE");
+        let server = Self {
+            child,
+            base_url: format!("{}://127.0.0.1:{port}", if tls { "https" } else { "http" }),
+            workdir,
+        };
+        server.wait_ready().await;
+        server
+    }
+
+    async fn wait_ready(&self) {
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        for _ in 0..100 {
+            if client.get(self.url("/api/health")).send().await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("translator did not become ready");
+    }
+
+    /// Everything the server has printed so far.
+    fn logs(&self) -> String {
+        ["stdout.log", "stderr.log"]
+            .iter()
+            .filter_map(|name| std::fs::read_to_string(self.workdir.join(name)).ok())
+            .collect()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn translate(&self, body: Value) -> (u16, Value) {
+        let resp = reqwest::Client::new()
+            .post(self.url("/api/translate"))
+            .json(&body)
+            .send()
+            .await
+            .expect("request failed");
+        let status = resp.status().as_u16();
+        (status, resp.json().await.expect("response was not JSON"))
+    }
+}
+
+fn log_file(dir: &std::path::Path, name: &str) -> Stdio {
+    std::fs::File::create(dir.join(name))
+        .map(Stdio::from)
+        .expect("failed to create log file")
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .expect("failed to find a free port")
+}
+
+fn fixture_file(name: &str, contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("translator-fixtures-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create fixture dir");
+    let path = dir.join(name);
+    std::fs::write(&path, contents).expect("failed to write fixture");
+    path
+}
+
+fn doubao_reply(text: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "status": "completed",
+        "output": [{
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "output_text", "text": text }]
+        }]
+    }))
+}
+
+fn echo_reply(req: &wiremock::Request) -> ResponseTemplate {
+    let body: Value = serde_json::from_slice(&req.body).unwrap();
+    let user = body["input"].as_array().unwrap().last().unwrap().clone();
+    let content = &user["content"][0];
+    let target = content["translation_options"]["target_language"]
+        .as_str()
+        .unwrap();
+    let text = content["text"].as_str().unwrap();
+    doubao_reply(&format!("[{target}] {text}"))
+}
+
+async fn mock_upstream(reply: impl wiremock::Respond + 'static, expected_calls: u64) -> MockServer {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(reply)
+        .expect(expected_calls)
+        .mount(&upstream)
+        .await;
+    upstream
+}
+
+#[tokio::test]
+async fn translates_text() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["text"], "你好");
+    assert_eq!(body["cached"], false);
+}
+
+#[tokio::test]
+async fn second_call_is_served_from_cache() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({ "text": "hello", "target": "zh" });
+
+    let (_, first) = server.translate(request.clone()).await;
+    let (status, second) = server.translate(request).await;
+
+    assert_eq!(first["cached"], false);
+    assert_eq!(status, 200);
+    assert_eq!(second["text"], "你好");
+    assert_eq!(second["cached"], true);
+}
+
+#[tokio::test]
+async fn get_variant_accepts_url_encoded_text() {
+    let upstream = mock_upstream(doubao_reply("hello world"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let resp = reqwest::Client::new()
+        .get(server.url("/api/translate"))
+        .query(&[("text", "你好，世界"), ("source", "zh"), ("target", "en")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["text"], "hello world");
+}
+
+#[tokio::test]
+async fn rate_limited_requests_get_429() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[("RATE_LIMIT_RPM", "1")]).await;
+
+    let (first, _) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate"))
+        .json(&json!({ "text": "hello", "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(first, 200);
+    assert_eq!(resp.status(), 429);
+    let retry_after: u64 = resp.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(
+        (58..=60).contains(&retry_after),
+        "retry-after {retry_after}"
+    );
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["success"], false);
+    assert_eq!(body["code"], "RATE_LIMITED");
+    assert_eq!(body["retry_after"], retry_after);
+}
+
+#[tokio::test]
+async fn rate_limited_responses_describe_the_limit() {
+    let upstream = mock_upstream(doubao_reply("你好"), 2).await;
+    let server = TestServer::start(&upstream, &[("RATE_LIMIT_RPM", "2")]).await;
+
+    for target in ["zh", "ja"] {
+        let (status, body) = server
+            .translate(json!({ "text": "hello", "target": target }))
+            .await;
+        assert_eq!(status, 200, "{body}");
+    }
+    let (status, body) = server
+        .translate(json!({ "text": "hello again", "target": "zh" }))
+        .await;
+    assert_eq!(status, 429);
+    assert_eq!(body["code"], "RATE_LIMITED");
+    assert_eq!(body["limit"], 2);
+    assert_eq!(body["window_secs"], 60);
+    let retry_after = body["retry_after"].as_u64().unwrap();
+    assert!((58..=60).contains(&retry_after), "{body}");
+}
+
+#[tokio::test]
+async fn invalid_input_is_rejected_without_upstream_call() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server =
+        TestServer::start(&upstream, &[("MAX_TEXT_LENGTH", "5"), ("MAX_TARGETS", "2")]).await;
+
+    for (body, code) in [
+        (json!({ "text": "", "target": "zh" }), "EMPTY_TEXT"),
+        (json!({ "text": "hello", "target": " " }), "INVALID_TARGET"),
+        (json!({ "text": "hello", "target": "xx" }), "INVALID_TARGET"),
+        (
+            json!({ "text": "too long", "target": "zh" }),
+            "TEXT_TOO_LONG",
+        ),
+        (json!({ "text": "hello", "targets": [] }), "INVALID_TARGET"),
+        (
+            json!({ "text": "hello", "targets": ["zh", "en", "ja"] }),
+            "TOO_MANY_TARGETS",
+        ),
+    ] {
+        let (status, resp) = server.translate(body).await;
+        assert_eq!(status, 400);
+        assert_eq!(resp["success"], false);
+        assert!(resp["error"].is_string());
+        assert_eq!(resp["code"], code);
+    }
+}
+
+#[tokio::test]
+async fn upstream_failure_maps_to_bad_gateway() {
+    let upstream = mock_upstream(ResponseTemplate::new(500).set_body_string("boom"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 502);
+    assert_eq!(body["success"], false);
+    assert_eq!(body["code"], "UPSTREAM_ERROR");
+}
+
+#[tokio::test]
+async fn instruction_is_sent_as_system_message() {
+    let upstream = mock_upstream(doubao_reply("Hallo"), 2).await;
+    let server = TestServer::start(&upstream, &[("DEFAULT_INSTRUCTION", "Keep it short")]).await;
+
+    server
+        .translate(json!({ "text": "hello", "target": "de" }))
+        .await;
+    let (_, body) = server
+        .translate(json!({ "text": "hello", "target": "de", "instruction": "Be formal" }))
+        .await;
+    assert_eq!(body["cached"], false);
+
+    let requests = upstream.received_requests().await.unwrap();
+    let instructions: Vec<Value> = requests
+        .iter()
+        .map(|req| {
+            let body: Value = serde_json::from_slice(&req.body).unwrap();
+            assert_eq!(body["input"][0]["role"], "system");
+            assert_eq!(body["input"][1]["role"], "user");
+            body["input"][0]["content"][0]["text"].clone()
+        })
+        .collect();
+    assert_eq!(
+        instructions,
+        vec![json!("Keep it short"), json!("Be formal")]
+    );
+}
+
+#[tokio::test]
+async fn websocket_session_translates_each_frame() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let upstream = mock_upstream(doubao_reply("你好"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let ws_url = server.url("/api/ws").replacen("http", "ws", 1);
+    let (mut socket, _) = tokio_tungstenite::connect_async(ws_url).await.unwrap();
+
+    for text in ["hello", "good morning"] {
+        let frame = json!({ "text": text, "target": "zh" }).to_string();
+        socket.send(Message::text(frame)).await.unwrap();
+        let reply = socket.next().await.unwrap().unwrap();
+        let body: Value = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+        assert_eq!(body["success"], true);
+        assert_eq!(body["text"], "你好");
+    }
+
+    socket.close(None).await.unwrap();
+}
+
+async fn upstream_bodies(upstream: &MockServer) -> Vec<Value> {
+    upstream
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|req| serde_json::from_slice(&req.body).unwrap())
+        .collect()
+}
+
+async fn upstream_texts(upstream: &MockServer) -> Vec<String> {
+    upstream
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|req| {
+            let body: Value = serde_json::from_slice(&req.body).unwrap();
+            let input = body["input"].as_array().unwrap();
+            let user = input.last().unwrap();
+            user["content"][0]["text"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn crlf_paragraphs_are_split_into_chunks() {
+    let upstream = mock_upstream(doubao_reply("ok"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let paragraph = "a".repeat(500);
+
+    let (status, _) = server
+        .translate(json!({ "text": format!("{paragraph}\r\n\r\n{paragraph}"), "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(
+        upstream_texts(&upstream).await,
+        vec![paragraph.clone(), paragraph]
+    );
+}
+
+#[tokio::test]
+async fn single_newline_documents_stay_under_chunk_limit() {
+    let upstream = mock_upstream(doubao_reply("ok"), 3).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let text = vec!["b".repeat(300); 6].join("\n");
+
+    let (status, _) = server
+        .translate(json!({ "text": text, "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 200);
+    let chunks = upstream_texts(&upstream).await;
+    assert_eq!(chunks.len(), 3);
+    assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 800));
+    assert_eq!(chunks.join("\n"), text);
+}
+
+#[tokio::test]
+async fn languages_can_be_loaded_from_file() {
+    let file = fixture_file(
+        "languages.json",
+        r#"{ "en": "English", "eo": "Esperanto" }"#,
+    );
+    let upstream = mock_upstream(doubao_reply("Saluton"), 1).await;
+    let server = TestServer::start(&upstream, &[("LANGUAGES_FILE", file.to_str().unwrap())]).await;
+
+    let languages: Value = reqwest::get(server.url("/api/languages"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        languages["languages"],
+        json!({ "en": "English", "eo": "Esperanto" })
+    );
+
+    let (status, _) = server
+        .translate(json!({ "text": "hello", "target": "eo" }))
+        .await;
+    assert_eq!(status, 200);
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["success"], false);
+}
+
+#[tokio::test]
+async fn multiple_targets_are_translated_and_cached_independently() {
+    let upstream = mock_upstream(echo_reply, 3).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, single) = server
+        .translate(json!({ "text": "Hello", "target": "ja" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(single["text"], "[ja] Hello");
+
+    let (status, multi) = server
+        .translate(json!({ "text": "Hello", "targets": ["zh", "ja", "fr"] }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        multi["results"],
+        json!({ "zh": "[zh] Hello", "ja": "[ja] Hello", "fr": "[fr] Hello" })
+    );
+
+    let (status, _) = server
+        .translate(json!({ "text": "Hello", "targets": [] }))
+        .await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn circuit_breaker_fails_fast_then_recovers() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(2)
+        .named("failing upstream")
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(
+        &upstream,
+        &[
+            ("CIRCUIT_FAIL_THRESHOLD", "2"),
+            ("CIRCUIT_COOLDOWN_SECS", "1"),
+        ],
+    )
+    .await;
+    let request = json!({ "text": "hello", "target": "zh" });
+
+    assert_eq!(server.translate(request.clone()).await.0, 502);
+    assert_eq!(server.translate(request.clone()).await.0, 502);
+    let (status, body) = server.translate(request.clone()).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["code"], "SERVICE_UNAVAILABLE");
+    upstream.verify().await;
+
+    upstream.reset().await;
+    Mock::given(method("POST"))
+        .respond_with(doubao_reply("你好"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let (status, body) = server.translate(request).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "你好");
+}
+
+#[tokio::test]
+async fn line_ending_differences_share_a_cache_entry() {
+    let upstream = mock_upstream(doubao_reply("嗨"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (_, first) = server
+        .translate(json!({ "text": "hi\r\n", "target": "zh" }))
+        .await;
+    let (_, second) = server
+        .translate(json!({ "text": "hi\n", "target": "zh" }))
+        .await;
+
+    assert_eq!(first["cached"], false);
+    assert_eq!(second["cached"], true);
+    assert_eq!(second["text"], "嗨");
+}
+
+#[tokio::test]
+async fn default_source_language_applies_only_when_source_is_omitted() {
+    let upstream = mock_upstream(echo_reply, 4).await;
+    let server = TestServer::start(&upstream, &[("DEFAULT_SOURCE_LANGUAGE", "zh")]).await;
+
+    server
+        .translate(json!({ "text": "一", "target": "en" }))
+        .await;
+    server
+        .translate(json!({ "text": "二", "source": "ja", "target": "en" }))
+        .await;
+    server
+        .translate(json!({ "text": "三", "source": "", "target": "en" }))
+        .await;
+    server
+        .translate(json!({ "text": "四", "source": "auto", "target": "en" }))
+        .await;
+
+    let sources: Vec<Value> = upstream_bodies(&upstream)
+        .await
+        .iter()
+        .map(|body| {
+            body["input"][0]["content"][0]["translation_options"]["source_language"].clone()
+        })
+        .collect();
+    assert_eq!(
+        sources,
+        vec![json!("zh"), json!("ja"), Value::Null, Value::Null]
+    );
+}
+
+#[tokio::test]
+async fn error_messages_follow_accept_language() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+
+    let mut messages = Vec::new();
+    for accept in [
+        None,
+        Some("en-US,en;q=0.9"),
+        Some("fr, zh-CN;q=0.8, en;q=0.5"),
+    ] {
+        let mut req = client
+            .post(server.url("/api/translate"))
+            .json(&json!({ "text": "", "target": "zh" }));
+        if let Some(accept) = accept {
+            req = req.header("Accept-Language", accept);
+        }
+        let resp = req.send().await.unwrap();
+        assert_eq!(resp.status(), 400);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["code"], "EMPTY_TEXT");
+        messages.push(body["error"].clone());
+    }
+
+    assert_eq!(
+        messages,
+        vec![
+            json!("文本不能为空"),
+            json!("Text must not be empty"),
+            json!("文本不能为空"),
+        ]
+    );
+
+    let failing = mock_upstream(ResponseTemplate::new(500).set_body_string("boom"), 2).await;
+    let server = TestServer::start(&failing, &[]).await;
+    let mut messages = Vec::new();
+    for accept in ["zh-CN", "en"] {
+        let resp = client
+            .post(server.url("/api/translate"))
+            .header("Accept-Language", accept)
+            .json(&json!({ "text": format!("hello {accept}"), "target": "zh" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 502);
+        let body: Value = resp.json().await.unwrap();
+        messages.push(body["error"].clone());
+    }
+    assert_eq!(
+        messages,
+        vec![
+            json!("翻译失败: API错误 500: boom"),
+            json!("Translation failed: the upstream returned HTTP 500: boom"),
+        ]
+    );
+}
+
+fn multipart_body(boundary: &str, fields: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, file_name, value) in fields {
+        body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+        match file_name {
+            Some(file_name) => body.extend_from_slice(
+                format!(
+                    "Content-Disposition: form-data; name=\"{name}\"; filename=\"{file_name}\"\r\n\
+                     Content-Type: text/plain\r\n\r\n"
+                )
+                .as_bytes(),
+            ),
+            None => body.extend_from_slice(
+                format!("Content-Disposition: form-data; name=\"{name}\"\r\n\r\n").as_bytes(),
+            ),
+        }
+        body.extend_from_slice(value);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+    body
+}
+
+#[tokio::test]
+async fn uploaded_file_is_returned_as_translated_attachment() {
+    let upstream = mock_upstream(echo_reply, 1).await;
+    let server = TestServer::start(&upstream, &[("MAX_UPLOAD_BYTES", "64")]).await;
+    let client = reqwest::Client::new();
+    let boundary = "translator-test-boundary";
+    let upload = |fields: &[(&str, Option<&str>, &[u8])]| {
+        client
+            .post(server.url("/api/translate/file"))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(multipart_body(boundary, fields))
+            .send()
+    };
+
+    let resp = upload(&[
+        ("target", None, b"en"),
+        ("file", Some("notes.txt"), "你好".as_bytes()),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers()["content-disposition"],
+        "attachment; filename=\"notes.en.txt\""
+    );
+    assert_eq!(resp.text().await.unwrap(), "[en] 你好");
+
+    let resp = upload(&[
+        ("target", None, b"en"),
+        ("file", Some("bad.txt"), &[0xff, 0xfe, 0x00]),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "INVALID_ENCODING");
+
+    let resp = upload(&[
+        ("target", None, b"en"),
+        ("file", Some("big.txt"), &[b'a'; 100]),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 413);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "FILE_TOO_LARGE");
+}
+
+#[tokio::test]
+async fn protected_spans_pass_through_untouched() {
+    let upstream = mock_upstream(echo_reply, 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "Hi {name}, you have {count} messages", "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "[zh] Hi {name}, you have {count} messages");
+    let sent = upstream_texts(&upstream).await;
+    assert!(!sent[0].contains('{'), "placeholders leaked: {sent:?}");
+}
+
+#[tokio::test]
+async fn protect_pattern_can_cover_code_spans() {
+    let upstream = mock_upstream(echo_reply, 1).await;
+    let server = TestServer::start(&upstream, &[("PROTECT_PATTERN", "`[^`]+`")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "Run `cargo test` before {pushing}", "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "[zh] Run `cargo test` before {pushing}");
+    let sent = upstream_texts(&upstream).await;
+    assert!(!sent[0].contains("cargo test"));
+    assert!(sent[0].contains("{pushing}"));
+}
+
+#[tokio::test]
+async fn dropped_placeholders_fail_without_caching() {
+    let upstream = mock_upstream(doubao_reply("你好"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({ "text": "Hello {name}", "target": "zh" });
+
+    for _ in 0..2 {
+        let (status, body) = server.translate(request.clone()).await;
+        assert_eq!(status, 502);
+        assert_eq!(body["code"], "PLACEHOLDER_MISMATCH");
+    }
+}
+
+#[tokio::test]
+async fn oversized_bodies_are_rejected_before_parsing() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(
+        &upstream,
+        &[("MAX_BODY_BYTES", "1024"), ("MAX_TEXT_LENGTH", "100000")],
+    )
+    .await;
+
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate"))
+        .json(&json!({ "text": "a".repeat(2048), "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 413);
+}
+
+#[tokio::test]
+async fn empty_translations_are_cached() {
+    // One call plus the RETRY_ON_EMPTY retry; the blank result is then cached.
+    let upstream = mock_upstream(doubao_reply(""), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({ "text": "   hmm", "target": "zh" });
+
+    let (_, first) = server.translate(request.clone()).await;
+    let (status, second) = server.translate(request).await;
+
+    assert_eq!(first["text"], "");
+    assert_eq!(first["cached"], false);
+    assert_eq!(status, 200);
+    assert_eq!(second["text"], "");
+    assert_eq!(second["cached"], true);
+}
+
+#[tokio::test]
+async fn failed_translations_are_not_cached() {
+    let upstream = mock_upstream(ResponseTemplate::new(500).set_body_string("boom"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({ "text": "hello", "target": "zh" });
+
+    assert_eq!(server.translate(request.clone()).await.0, 502);
+    assert_eq!(server.translate(request).await.0, 502);
+}
+
+fn repo_fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+#[tokio::test]
+async fn serves_https_when_tls_files_are_configured() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let cert = repo_fixture("tls-cert.pem");
+    let key = repo_fixture("tls-key.pem");
+    let server = TestServer::start(
+        &upstream,
+        &[("TLS_CERT_FILE", &cert), ("TLS_KEY_FILE", &key)],
+    )
+    .await;
+    assert!(server.url("/").starts_with("https://"));
+
+    let body: Value = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .post(server.url("/api/translate"))
+        .json(&json!({ "text": "hello", "target": "zh" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["text"], "你好");
+}
+
+#[test]
+fn malformed_tls_files_fail_startup() {
+    let not_a_cert = fixture_file("not-a-cert.pem", "hello");
+    let status = Command::new(env!("CARGO_BIN_EXE_translator"))
+        .env_clear()
+        .env("ARK_API_KEY", "test-key")
+        .env("PORT", free_port().to_string())
+        .env("TLS_CERT_FILE", &not_a_cert)
+        .env("TLS_KEY_FILE", repo_fixture("tls-key.pem"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("failed to run translator");
+    assert_eq!(status.code(), Some(1));
+}
+
+#[tokio::test]
+async fn verbose_mode_reports_chunk_level_cache_hits() {
+    let upstream = mock_upstream(echo_reply, 3).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let shared = "a".repeat(500);
+    let first_doc = format!("{shared}\n\n{}", "b".repeat(500));
+    let second_doc = format!("{shared}\n\n{}", "c".repeat(400));
+
+    let (_, first) = server
+        .translate(json!({ "text": first_doc, "target": "zh" }))
+        .await;
+    assert!(first.get("chunks").is_none());
+
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate?verbose=true"))
+        .json(&json!({ "text": second_doc, "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+    let second: Value = resp.json().await.unwrap();
+
+    assert_eq!(second["cached"], false);
+    assert_eq!(second["chunk_count"], 2);
+    assert_eq!(
+        second["chunks"],
+        json!([
+            { "chars": 500, "cached": true },
+            { "chars": 400, "cached": false },
+        ])
+    );
+    assert_eq!(
+        second["text"],
+        format!("[zh] {shared}\n[zh] {}", "c".repeat(400))
+    );
+}
+
+#[tokio::test]
+async fn shared_paragraphs_are_only_translated_once() {
+    let upstream = mock_upstream(echo_reply, 3).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let intro = "x".repeat(600);
+    let (first_body, second_body) = ("y".repeat(600), "z".repeat(600));
+
+    for body in [&first_body, &second_body] {
+        let (status, resp) = server
+            .translate(json!({ "text": format!("{intro}\n\n{body}"), "target": "zh" }))
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(resp["text"], format!("[zh] {intro}\n[zh] {body}"));
+    }
+
+    assert_eq!(
+        upstream_texts(&upstream).await,
+        vec![intro, first_body, second_body]
+    );
+}
+
+#[tokio::test]
+async fn chunk_cache_can_be_disabled() {
+    let upstream = mock_upstream(echo_reply, 4).await;
+    let server = TestServer::start(&upstream, &[("CACHE_CHUNKS", "false")]).await;
+    let intro = "x".repeat(600);
+
+    for body in ["y".repeat(600), "z".repeat(600)] {
+        let (status, _) = server
+            .translate(json!({ "text": format!("{intro}\n\n{body}"), "target": "zh" }))
+            .await;
+        assert_eq!(status, 200);
+    }
+}
+
+#[tokio::test]
+async fn no_cache_requests_neither_read_nor_write_the_cache() {
+    let upstream = mock_upstream(doubao_reply("你好"), 4).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let plain = json!({ "text": "hello", "target": "zh" });
+    let private = json!({ "text": "hello", "target": "zh", "no_cache": true });
+
+    let (_, body) = server.translate(private.clone()).await;
+    assert_eq!(body["cached"], false);
+    let (_, body) = server.translate(plain.clone()).await;
+    assert_eq!(body["cached"], false, "no_cache request must not write");
+    let (_, body) = server.translate(plain.clone()).await;
+    assert_eq!(body["cached"], true);
+    let (_, body) = server.translate(private).await;
+    assert_eq!(body["cached"], false, "no_cache request must not read");
+
+    let body: Value = reqwest::Client::new()
+        .post(server.url("/api/translate"))
+        .header("Cache-Control", "no-store")
+        .json(&plain)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["cached"], false);
+}
+
+#[tokio::test]
+async fn chat_format_sends_chat_completion_bodies() {
+    let upstream = mock_upstream(
+        ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": "Hallo" } }]
+        })),
+        1,
+    )
+    .await;
+    let server = TestServer::start(&upstream, &[("ARK_API_FORMAT", "chat")]).await;
+
+    let (status, body) = server
+        .translate(
+            json!({ "text": "hello", "source": "en", "target": "de", "formality": "formal" }),
+        )
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "Hallo");
+
+    let sent = &upstream_bodies(&upstream).await[0];
+    assert!(sent.get("input").is_none());
+    assert_eq!(sent["model"], "doubao-seed-translation-250915");
+    assert_eq!(sent["messages"][0]["role"], "system");
+    let prompt = sent["messages"][0]["content"].as_str().unwrap();
+    assert!(prompt.contains("from en to de"), "{prompt}");
+    assert!(prompt.contains("formal"), "{prompt}");
+    assert_eq!(
+        sent["messages"][1],
+        json!({ "role": "user", "content": "hello" })
+    );
+}
+
+#[tokio::test]
+async fn responses_format_is_the_default_body_shape() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[("ARK_API_FORMAT", "responses")]).await;
+
+    server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+
+    let sent = &upstream_bodies(&upstream).await[0];
+    assert!(sent.get("messages").is_none());
+    assert_eq!(
+        sent["input"],
+        json!([{
+            "role": "user",
+            "content": [{
+                "type": "input_text",
+                "text": "hello",
+                "translation_options": { "source_language": "en", "target_language": "zh" }
+            }]
+        }])
+    );
+}
+
+#[tokio::test]
+async fn oversized_upstream_responses_are_rejected() {
+    let upstream = mock_upstream(doubao_reply(&"x".repeat(4096)), 1).await;
+    let server = TestServer::start(&upstream, &[("MAX_RESPONSE_BYTES", "1024")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "UPSTREAM_ERROR");
+    assert!(body["error"].as_str().unwrap().contains("1024"));
+}
+
+#[tokio::test]
+async fn mock_provider_serves_the_full_stack_offline() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("PROVIDER", "mock")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "你好", "target": "en" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "[en] 你好");
+
+    let (_, body) = server
+        .translate(json!({ "text": "你好", "target": "en" }))
+        .await;
+    assert_eq!(body["cached"], true);
+}
+
+#[tokio::test]
+async fn auto_empty_and_omitted_sources_share_auto_detection() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let mut cached = Vec::new();
+    for request in [
+        json!({ "text": "hello", "target": "zh" }),
+        json!({ "text": "hello", "source": "", "target": "zh" }),
+        json!({ "text": "hello", "source": "AUTO", "target": "zh" }),
+    ] {
+        let (status, body) = server.translate(request).await;
+        assert_eq!(status, 200);
+        cached.push(body["cached"].clone());
+    }
+
+    assert_eq!(cached, vec![json!(false), json!(true), json!(true)]);
+    let options =
+        &upstream_bodies(&upstream).await[0]["input"][0]["content"][0]["translation_options"];
+    assert!(options.get("source_language").is_none());
+}
+
+#[tokio::test]
+async fn auto_source_overrides_default_and_required_source() {
+    let upstream = mock_upstream(echo_reply, 2).await;
+    let server = TestServer::start(&upstream, &[("REQUIRE_SOURCE", "true")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["code"], "MISSING_SOURCE");
+
+    for source in ["auto", "en"] {
+        let (status, _) = server
+            .translate(json!({ "text": "hello", "source": source, "target": "zh" }))
+            .await;
+        assert_eq!(status, 200);
+    }
+    let sources: Vec<Value> = upstream_bodies(&upstream)
+        .await
+        .iter()
+        .map(|body| {
+            body["input"][0]["content"][0]["translation_options"]["source_language"].clone()
+        })
+        .collect();
+    assert_eq!(sources, vec![Value::Null, json!("en")]);
+}
+
+#[tokio::test]
+async fn model_artifacts_are_stripped_when_enabled() {
+    let upstream = MockServer::start().await;
+    for (input, output) in [
+        ("fenced", "```\n你好\n```"),
+        ("labeled", "Translation: 你好"),
+        ("clean", "你好"),
+        ("```\nlet x = 1;\n```", "```\nlet x = 1;\n```"),
+    ] {
+        Mock::given(method("POST"))
+            .and(wiremock::matchers::body_string_contains(
+                serde_json::to_string(input).unwrap().trim_matches('"'),
+            ))
+            .respond_with(doubao_reply(output))
+            .mount(&upstream)
+            .await;
+    }
+    let server = TestServer::start(&upstream, &[("STRIP_MODEL_ARTIFACTS", "true")]).await;
+
+    for (input, expected) in [
+        ("fenced", "你好"),
+        ("labeled", "你好"),
+        ("clean", "你好"),
+        ("```\nlet x = 1;\n```", "```\nlet x = 1;\n```"),
+    ] {
+        let (status, body) = server
+            .translate(json!({ "text": input, "target": "zh" }))
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["text"], expected, "input {input:?}");
+    }
+}
+
+#[tokio::test]
+async fn model_artifacts_are_kept_by_default() {
+    let upstream = mock_upstream(doubao_reply("Translation: 你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (_, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(body["text"], "Translation: 你好");
+}
+
+#[tokio::test]
+async fn concurrency_is_limited_per_api_key() {
+    let upstream = mock_upstream(
+        doubao_reply("你好").set_delay(Duration::from_millis(600)),
+        2,
+    )
+    .await;
+    let server = TestServer::start(
+        &upstream,
+        &[
+            ("SERVER_API_KEYS", "alpha, beta"),
+            ("PER_KEY_CONCURRENCY", "1"),
+        ],
+    )
+    .await;
+    let client = reqwest::Client::new();
+    let send = |key: Option<&'static str>, text: &'static str| {
+        let mut req = client
+            .post(server.url("/api/translate"))
+            .json(&json!({ "text": text, "target": "zh" }));
+        if let Some(key) = key {
+            req = req.bearer_auth(key);
+        }
+        async move {
+            let resp = req.send().await.unwrap();
+            let status = resp.status().as_u16();
+            (status, resp.json::<Value>().await.unwrap())
+        }
+    };
+
+    let (status, body) = send(None, "anonymous").await;
+    assert_eq!(status, 401);
+    assert_eq!(body["code"], "UNAUTHORIZED");
+
+    let first = tokio::spawn(send(Some("alpha"), "one"));
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let (second, other_key) = tokio::join!(send(Some("alpha"), "two"), send(Some("beta"), "three"));
+
+    assert_eq!(first.await.unwrap().0, 200);
+    assert_eq!(second.0, 429);
+    assert_eq!(second.1["code"], "CONCURRENCY_LIMITED");
+    assert_eq!(other_key.0, 200);
+}
+
+#[tokio::test]
+async fn ndjson_lines_stream_back_in_order() {
+    let upstream = mock_upstream(echo_reply, 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let body = [
+        json!({ "text": "one", "target": "en" }).to_string(),
+        json!({ "text": "", "target": "en" }).to_string(),
+        String::new(),
+        json!({ "text": "three", "target": "ja" }).to_string(),
+    ]
+    .join("\n");
+
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate/ndjson"))
+        .header("Content-Type", "application/x-ndjson")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/x-ndjson");
+
+    let text = resp.text().await.unwrap();
+    let lines: Vec<Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["text"], "[en] one");
+    assert_eq!(lines[1]["code"], "EMPTY_TEXT");
+    assert_eq!(lines[2]["text"], "[ja] three");
+}
+
+#[tokio::test]
+async fn detected_source_is_reported_only_when_source_is_omitted() {
+    let reply = ResponseTemplate::new(200).set_body_json(json!({
+        "status": "completed",
+        "detected_source_language": "ja",
+        "output": [{
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "output_text", "text": "hello" }]
+        }]
+    }));
+    let upstream = mock_upstream(reply, 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (_, body) = server
+        .translate(json!({ "text": "こんにちは", "target": "en" }))
+        .await;
+    assert_eq!(body["detected_source"], "ja");
+
+    let (_, body) = server
+        .translate(json!({ "text": "こんばんは", "source": "ja", "target": "en" }))
+        .await;
+    assert!(body.get("detected_source").is_none());
+}
+
+#[tokio::test]
+async fn detected_source_falls_back_to_local_detection() {
+    let upstream = mock_upstream(doubao_reply("This is a Chinese sentence."), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({ "text": "这是一个用于检测语言的中文句子", "target": "en" });
+
+    let (_, body) = server.translate(request.clone()).await;
+    assert_eq!(body["detected_source"], "zh");
+
+    let (_, body) = server.translate(request).await;
+    assert_eq!(body["cached"], true);
+    assert_eq!(body["detected_source"], "zh");
+}
+
+#[tokio::test]
+async fn static_serving_can_be_disabled_or_relocated() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[("SERVE_STATIC", "false")]).await;
+    let resp = reqwest::get(server.url("/")).await.unwrap();
+    assert_eq!(resp.status(), 404);
+    let (status, _) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+
+    let dir = std::env::temp_dir().join(format!("translator-static-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "<h1>translator</h1>").unwrap();
+    let server = TestServer::start(&upstream, &[("STATIC_DIR", dir.to_str().unwrap())]).await;
+    let resp = reqwest::get(server.url("/")).await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "<h1>translator</h1>");
+}
+
+#[tokio::test]
+async fn total_timeout_bounds_multi_chunk_translations() {
+    let upstream = mock_upstream(doubao_reply("ok").set_delay(Duration::from_millis(700)), 2).await;
+    let server = TestServer::start(&upstream, &[("TOTAL_TIMEOUT_SECS", "1")]).await;
+    let paragraph = "a".repeat(500);
+
+    let (status, body) = server
+        .translate(json!({
+            "text": format!("{paragraph}\n\n{paragraph}"),
+            "target": "zh",
+        }))
+        .await;
+
+    assert_eq!(status, 504);
+    assert_eq!(body["code"], "DEADLINE_EXCEEDED");
+    assert_eq!(body["error"], "翻译超时（1秒），仅完成1个分段");
+}
+
+#[tokio::test]
+async fn include_source_returns_aligned_segments() {
+    let upstream = mock_upstream(echo_reply, 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let first = format!("{} {{name}}", "a".repeat(500));
+    let second = "b".repeat(500);
+    let request = json!({
+        "text": format!("{first}\n\n{second}"),
+        "target": "en",
+        "include_source": true,
+    });
+
+    let (status, body) = server.translate(request.clone()).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body["segments"],
+        json!([
+            { "source": first, "translation": format!("[en] {first}") },
+            { "source": second, "translation": format!("[en] {second}") },
+        ])
+    );
+    assert_eq!(body["text"], format!("[en] {first}\n[en] {second}"));
+
+    let (_, body) = server.translate(request).await;
+    assert_eq!(body["cached"], true);
+    assert_eq!(body["segments"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn redacted_pii_never_reaches_upstream() {
+    let upstream = mock_upstream(echo_reply, 1).await;
+    let server = TestServer::start(&upstream, &[("REDACT_PII", "true")]).await;
+    let pii = [
+        "alice@example.com",
+        "+1 (555) 123-4567",
+        "4111 1111 1111 1111",
+    ];
+    let text = format!("Mail {} or call {}. Card: {}.", pii[0], pii[1], pii[2]);
+
+    let (status, body) = server
+        .translate(json!({ "text": text, "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], format!("[zh] {text}"));
+    let sent = upstream_texts(&upstream).await.concat();
+    for value in pii {
+        assert!(!sent.contains(value), "{value} leaked upstream: {sent}");
+    }
+    assert!(sent.contains("⟦R0⟧") && sent.contains("⟦R2⟧"));
+}
+
+#[tokio::test]
+async fn redact_pattern_is_configurable() {
+    let upstream = mock_upstream(echo_reply, 1).await;
+    let server = TestServer::start(
+        &upstream,
+        &[("REDACT_PII", "true"), ("REDACT_PATTERN", r"EMP-\d+")],
+    )
+    .await;
+
+    let (_, body) = server
+        .translate(json!({ "text": "Ticket for EMP-4821", "target": "zh" }))
+        .await;
+
+    assert_eq!(body["text"], "[zh] Ticket for EMP-4821");
+    assert_eq!(upstream_texts(&upstream).await, vec!["Ticket for ⟦R0⟧"]);
+}
+
+#[tokio::test]
+async fn accepts_http2_prior_knowledge_connections() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[("HTTP2_KEEPALIVE_SECS", "5")]).await;
+    let client = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+
+    let resp = client
+        .post(server.url("/api/translate"))
+        .json(&json!({ "text": "hello", "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.version(), reqwest::Version::HTTP_2);
+    assert_eq!(resp.json::<Value>().await.unwrap()["text"], "你好");
+}
+
+#[tokio::test]
+async fn http2_prior_knowledge_is_refused_when_disabled() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[("ENABLE_HTTP2", "false")]).await;
+    let h2c = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+
+    let result = h2c
+        .post(server.url("/api/translate"))
+        .json(&json!({ "text": "hello", "target": "zh" }))
+        .send()
+        .await;
+    assert!(result.is_err(), "{result:?}");
+    // HTTP/1.1 clients are unaffected.
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "你好");
+}
+
+#[tokio::test]
+async fn get_responses_honor_if_none_match() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+    let url = server.url("/api/translate?text=hello&target=zh");
+
+    let first = client.get(&url).send().await.unwrap();
+    assert_eq!(first.status(), 200);
+    let etag = first.headers()["etag"].to_str().unwrap().to_string();
+    assert!(etag.starts_with('"') && etag.ends_with('"'));
+
+    let second = client
+        .get(&url)
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(second.status(), 304);
+    assert_eq!(second.headers()["etag"], etag.as_str());
+    assert!(second.text().await.unwrap().is_empty());
+
+    let other = client
+        .get(&url)
+        .header("If-None-Match", "\"stale\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(other.status(), 200);
+}
+
+#[tokio::test]
+async fn streaming_preflight_allows_stream_headers() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let resp = reqwest::Client::new()
+        .request(
+            reqwest::Method::OPTIONS,
+            server.url("/api/translate/ndjson"),
+        )
+        .header("Origin", "https://app.example.com")
+        .header("Access-Control-Request-Method", "POST")
+        .header(
+            "Access-Control-Request-Headers",
+            "authorization, cache-control, last-event-id, content-type",
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["access-control-allow-origin"], "*");
+    let allowed = resp.headers()["access-control-allow-headers"]
+        .to_str()
+        .unwrap()
+        .to_ascii_lowercase();
+    for name in [
+        "authorization",
+        "cache-control",
+        "last-event-id",
+        "content-type",
+    ] {
+        assert!(allowed.contains(name), "{name} missing from {allowed}");
+    }
+    let methods = resp.headers()["access-control-allow-methods"]
+        .to_str()
+        .unwrap();
+    assert!(methods.contains("POST"));
+}
+
+#[tokio::test]
+async fn stats_endpoint_reports_cache_evictions() {
+    let upstream = mock_upstream(echo_reply, 3).await;
+    let server = TestServer::start(
+        &upstream,
+        &[("CACHE_MAX_SIZE", "2"), ("CACHE_CHUNKS", "false")],
+    )
+    .await;
+    for text in ["one", "two", "three"] {
+        server
+            .translate(json!({ "text": text, "target": "zh" }))
+            .await;
+    }
+
+    let stats: Value = reqwest::get(server.url("/api/stats"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["cache"]["entries"], 2);
+    assert_eq!(stats["cache"]["capacity_evictions"], 1);
+    assert_eq!(stats["cache"]["ttl_expirations"], 0);
+}
+
+#[tokio::test]
+async fn stats_and_export_name_the_cache_key_prefix() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let env = [
+        ("CACHE_KEY_PREFIX", "tenant-a:"),
+        ("SERVER_API_KEYS", "admin-key"),
+    ];
+    let server = TestServer::start(&upstream, &env).await;
+    let client = reqwest::Client::new();
+    client
+        .post(server.url("/api/translate"))
+        .bearer_auth("admin-key")
+        .json(&json!({ "text": "hello", "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+
+    let stats: Value = reqwest::get(server.url("/api/stats"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["cache"]["key_prefix"], "tenant-a:");
+    let export: Value = client
+        .get(server.url("/api/tm/export"))
+        .bearer_auth("admin-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(export["key_prefix"], "tenant-a:");
+    assert_eq!(export["entries"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn long_results_are_truncated_at_grapheme_boundaries() {
+    let upstream = mock_upstream(doubao_reply("Family: 👨‍👩‍👧 and café tables"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = |max: usize| json!({ "text": "家庭", "target": "en", "max_output_chars": max });
+
+    let (status, body) = server.translate(request(11)).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "Family:…");
+    assert_eq!(body["truncated"], true);
+
+    let (_, body) = server.translate(request(14)).await;
+    assert_eq!(body["text"], "Family: 👨‍👩‍👧…");
+    assert_eq!(body["cached"], true);
+
+    let (_, body) = server.translate(request(100)).await;
+    assert_eq!(body["text"], "Family: 👨‍👩‍👧 and café tables");
+    assert!(body.get("truncated").is_none());
+}
+
+#[tokio::test]
+async fn fallback_model_serves_when_primary_fails() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_partial_json(
+            json!({ "model": "doubao-seed-translation-250915" }),
+        ))
+        .respond_with(ResponseTemplate::new(500).set_body_string("model overloaded"))
+        .expect(2)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_partial_json(
+            json!({ "model": "backup-model" }),
+        ))
+        .respond_with(doubao_reply("你好"))
+        .expect(2)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[("FALLBACK_MODEL", "backup-model")]).await;
+    let request = json!({ "text": "hello", "source": "en", "target": "zh" });
+
+    let (status, body) = server.translate(request.clone()).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "你好");
+    assert_eq!(body["model_used"], "backup-model");
+
+    // The fallback's output is not cached under the primary model's keys.
+    let (_, body) = server.translate(request).await;
+    assert_eq!(body["cached"], false);
+    assert_eq!(body["model_used"], "backup-model");
+}
+
+#[tokio::test]
+async fn post_endpoints_require_their_content_type() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+    let body = json!({ "text": "hello", "target": "zh" }).to_string();
+
+    for (path, content_type) in [
+        ("/api/translate", "text/plain"),
+        ("/api/translate/ndjson", "application/json"),
+    ] {
+        let resp = client
+            .post(server.url(path))
+            .header("Content-Type", content_type)
+            .header("Accept-Language", "en")
+            .body(body.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 415, "{path}");
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["code"], "UNSUPPORTED_MEDIA_TYPE");
+    }
+
+    let resp = client
+        .post(server.url("/api/translate"))
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["error"], "请求的 Content-Type 必须为 application/json");
+}
+
+#[tokio::test]
+async fn summarize_returns_translation_and_summary() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_string_contains(
+            "translation_options",
+        ))
+        .respond_with(doubao_reply("这是一篇很长的新闻报道。"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_string_contains(
+            "Summarize the user's text in zh in at most 50 characters",
+        ))
+        .respond_with(doubao_reply("新闻摘要"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({
+        "text": "This is a long news report.",
+        "target": "zh",
+        "summarize": true,
+        "summary_max_chars": 50,
+    });
+
+    let (status, body) = server.translate(request.clone()).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "这是一篇很长的新闻报道。");
+    assert_eq!(body["summary"], "新闻摘要");
+    let summary_input = &upstream_bodies(&upstream).await[1]["input"];
+    assert_eq!(
+        summary_input[1]["content"][0]["text"],
+        "这是一篇很长的新闻报道。"
+    );
+
+    let (_, body) = server.translate(request).await;
+    assert_eq!(body["summary"], "新闻摘要");
+}
+
+#[tokio::test]
+async fn no_cache_summaries_reach_the_upstream_every_time() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_string_contains(
+            "translation_options",
+        ))
+        .respond_with(doubao_reply("这是一篇很长的新闻报道。"))
+        .expect(2)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_string_contains("Summarize"))
+        .respond_with(doubao_reply("新闻摘要"))
+        .expect(2)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({
+        "text": "This is a long news report.",
+        "target": "zh",
+        "summarize": true,
+        "no_cache": true,
+    });
+
+    for _ in 0..2 {
+        let (status, body) = server.translate(request.clone()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["summary"], "新闻摘要");
+    }
+}
+
+#[tokio::test]
+async fn summaries_count_against_the_rate_limit() {
+    let server = TestServer::start(
+        &mock_upstream(doubao_reply("unused"), 0).await,
+        &[("PROVIDER", "mock"), ("RATE_LIMIT_RPM", "1")],
+    )
+    .await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh", "summarize": true }))
+        .await;
+    assert_eq!(status, 429);
+    assert_eq!(body["code"], "RATE_LIMITED");
+}
+
+#[tokio::test]
+async fn idempotency_key_replays_the_first_result() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+    let send = |body: Value| {
+        client
+            .post(server.url("/api/translate"))
+            .header("Idempotency-Key", "order-42")
+            .json(&body)
+            .send()
+    };
+    let request = json!({ "text": "hello", "source": "en", "target": "zh", "no_cache": true });
+
+    let first = send(request.clone()).await.unwrap();
+    assert_eq!(first.status(), 200);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first: Value = first.json().await.unwrap();
+
+    let second = send(request).await.unwrap();
+    assert_eq!(second.status(), 200);
+    assert_eq!(second.headers()["idempotent-replayed"], "true");
+    let second: Value = second.json().await.unwrap();
+    assert_eq!(first, second);
+
+    let conflict = send(json!({ "text": "bye", "target": "zh" }))
+        .await
+        .unwrap();
+    assert_eq!(conflict.status(), 422);
+    let body: Value = conflict.json().await.unwrap();
+    assert_eq!(body["code"], "IDEMPOTENCY_CONFLICT");
+}
+
+#[tokio::test]
+async fn config_file_is_read_and_env_overrides_it() {
+    let server = TestServer::start(
+        &mock_upstream(doubao_reply("unused"), 0).await,
+        &[
+            ("CONFIG_FILE", &repo_fixture("config.toml")),
+            ("MAX_TARGETS", "2"),
+        ],
+    )
+    .await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello world", "target": "zh" }))
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["code"], "TEXT_TOO_LONG");
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "targets": ["zh", "ja"] }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["results"]["ja"], "[ja] hello");
+}
+
+/// Runs the binary with `env` and returns its stderr, asserting it refused to start.
+fn config_error(env: &[(&str, &str)]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_translator"))
+        .env_clear()
+        .env("ARK_API_KEY", "test-key")
+        .envs(env.iter().copied())
+        .output()
+        .expect("failed to run translator");
+    assert!(!output.status.success());
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[tokio::test]
+async fn config_file_rejects_unknown_keys() {
+    let config = fixture_file(
+        "unknown-keys.json",
+        r#"{ "port": 5000, "max_text_lenght": 10 }"#,
+    );
+    let stderr = config_error(&[("CONFIG_FILE", config.to_str().unwrap())]);
+    assert!(stderr.contains("unknown keys"), "{stderr}");
+    assert!(stderr.contains("max_text_lenght"), "{stderr}");
+}
+
+#[tokio::test]
+async fn rejects_text_that_splits_into_too_many_chunks() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("MAX_CHUNKS", "2")]).await;
+    let paragraph = "word ".repeat(120);
+    let text = [paragraph.as_str(); 3].join("\n\n");
+
+    let (status, body) = server
+        .translate(json!({ "text": text, "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["code"], "TOO_MANY_CHUNKS");
+    assert_eq!(body["error"], "文本将被拆分为3段，超过限制（最多2段）");
+}
+
+#[tokio::test]
+async fn rejects_uploads_that_split_into_too_many_chunks() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("MAX_CHUNKS", "2")]).await;
+    let paragraph = "word ".repeat(120);
+    let text = [paragraph.as_str(); 3].join("\n\n");
+    let boundary = "translator-test-boundary";
+    let fields: &[(&str, Option<&str>, &[u8])] = &[
+        ("target", None, b"zh"),
+        ("file", Some("long.txt"), text.as_bytes()),
+    ];
+
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate/file"))
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .body(multipart_body(boundary, fields))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "TOO_MANY_CHUNKS");
+}
+
+#[tokio::test]
+async fn timing_query_reports_elapsed_ms() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("PROVIDER", "mock")]).await;
+    let request = json!({ "text": "hello", "target": "zh" });
+
+    let (_, body) = server.translate(request.clone()).await;
+    assert!(body.get("elapsed_ms").is_none());
+
+    let body: Value = reqwest::Client::new()
+        .post(server.url("/api/translate?timing=true"))
+        .json(&request)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["success"], true);
+    let elapsed = body["elapsed_ms"].as_u64().expect("elapsed_ms missing");
+    assert!(elapsed < 10_000, "{elapsed}");
+}
+
+#[tokio::test]
+async fn fails_over_to_a_backup_endpoint() {
+    let backup = mock_upstream(doubao_reply("你好"), 1).await;
+    let down = format!("http://127.0.0.1:{}/api/v3/responses", free_port());
+    let backup_url = format!("{}/api/v3/responses", backup.uri());
+    let server = TestServer::start(
+        &backup,
+        &[("ARK_API_URL", &down), ("ARK_API_URL_BACKUPS", &backup_url)],
+    )
+    .await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "你好");
+}
+
+#[tokio::test]
+async fn rejects_invalid_cache_settings() {
+    for (key, value, message) in [
+        ("CACHE_TTL", "0", "CACHE_TTL must be at least 1"),
+        ("CACHE_MAX_SIZE", "0", "CACHE_MAX_SIZE must be at least 1"),
+        (
+            "CACHE_TTL",
+            "1h",
+            r#"CACHE_TTL must be a whole number, got "1h""#,
+        ),
+    ] {
+        let stderr = config_error(&[(key, value)]);
+        assert!(stderr.contains(message), "{stderr}");
+    }
+}
+
+#[tokio::test]
+async fn clamps_oversized_cache_capacity() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("CACHE_MAX_SIZE", "50000000")]).await;
+
+    let stats: Value = reqwest::get(server.url("/api/stats"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["cache"]["capacity"], 1_000_000);
+}
+
+#[tokio::test]
+async fn sends_configured_headers_upstream() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::header(
+            "user-agent",
+            "acme-translator/2",
+        ))
+        .and(wiremock::matchers::header("x-gateway-tenant", "acme"))
+        .and(wiremock::matchers::header("x-trace", "on"))
+        .respond_with(doubao_reply("你好"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(
+        &upstream,
+        &[
+            ("HTTP_USER_AGENT", "acme-translator/2"),
+            ("HTTP_EXTRA_HEADERS", "X-Gateway-Tenant: acme; X-Trace:on"),
+        ],
+    )
+    .await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200, "{body}");
+}
+
+#[tokio::test]
+async fn default_user_agent_names_the_crate() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    let requests = upstream.received_requests().await.unwrap();
+    let agent = requests[0].headers["user-agent"].to_str().unwrap();
+    assert!(agent.starts_with("doubao-translator-rust/"), "{agent}");
+}
+
+#[tokio::test]
+async fn refresh_replaces_the_cached_translation() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(doubao_reply("旧的翻译"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(doubao_reply("新的翻译"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = json!({ "text": "hello", "source": "en", "target": "zh" });
+
+    server.translate(request.clone()).await;
+    let (_, body) = server.translate(request.clone()).await;
+    assert_eq!(body["text"], "旧的翻译");
+    assert_eq!(body["cached"], true);
+
+    let mut refresh = request.clone();
+    refresh["refresh"] = json!(true);
+    let (_, body) = server.translate(refresh).await;
+    assert_eq!(body["text"], "新的翻译");
+    assert_eq!(body["cached"], false);
+
+    let (_, body) = server.translate(request).await;
+    assert_eq!(body["text"], "新的翻译");
+    assert_eq!(body["cached"], true);
+}
+
+#[tokio::test]
+async fn translates_json_string_leaves_and_keeps_structure() {
+    // "Hello" appears twice but is translated once.
+    let upstream = mock_upstream(echo_reply, 3).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let data = json!({
+        "title": "Hello",
+        "count": 3,
+        "published": true,
+        "missing": null,
+        "links": ["https://example.com", "Read more"],
+        "sections": [{ "heading": "Hello", "body": "World", "order": 1.5 }],
+    });
+
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate/json"))
+        .json(&json!({
+            "data": data,
+            "source": "en",
+            "target": "zh",
+            "skip_pattern": "^https?://",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(
+        body["data"],
+        json!({
+            "title": "[zh] Hello",
+            "count": 3,
+            "published": true,
+            "missing": null,
+            "links": ["https://example.com", "[zh] Read more"],
+            "sections": [{ "heading": "[zh] Hello", "body": "[zh] World", "order": 1.5 }],
+        })
+    );
+}
+
+#[tokio::test]
+async fn json_endpoint_validates_its_options() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+
+    for (request, code) in [
+        (json!({ "data": ["hi"], "target": "xx" }), "INVALID_TARGET"),
+        (
+            json!({ "data": ["hi"], "target": "zh", "skip_pattern": "(" }),
+            "INVALID_REQUEST",
+        ),
+    ] {
+        let resp = client
+            .post(server.url("/api/translate/json"))
+            .json(&request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["code"], code);
+    }
+}
+
+#[tokio::test]
+async fn startup_key_check_rejects_an_unauthorized_key() {
+    let upstream = mock_upstream(
+        ResponseTemplate::new(401).set_body_string("invalid api key"),
+        1,
+    )
+    .await;
+    let url = format!("{}/api/v3/responses", upstream.uri());
+    let stderr = config_error(&[
+        ("ARK_API_URL", &url),
+        ("HTTP_DISABLE_PROXY", "true"),
+        ("VERIFY_KEY_ON_START", "true"),
+    ]);
+    assert!(stderr.contains("rejected the API key (401)"), "{stderr}");
+}
+
+#[tokio::test]
+async fn startup_key_check_tolerates_an_unreachable_upstream() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let down = format!("http://127.0.0.1:{}/api/v3/responses", free_port());
+    let server = TestServer::start(
+        &upstream,
+        &[("ARK_API_URL", &down), ("VERIFY_KEY_ON_START", "true")],
+    )
+    .await;
+
+    let resp = reqwest::get(server.url("/api/health")).await.unwrap();
+    assert_eq!(resp.status(), 200);
+}
+
+#[tokio::test]
+async fn selftest_reports_every_check_passing() {
+    let languages = fixture_file(
+        "selftest-languages.json",
+        r#"{ "en": "English", "zh": "中文" }"#,
+    );
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(
+        &upstream,
+        &[
+            ("PROVIDER", "mock"),
+            ("LANGUAGES_FILE", languages.to_str().unwrap()),
+            ("RUN_SELFTEST", "true"),
+        ],
+    )
+    .await;
+
+    let logs = server.logs();
+    for check in ["config", "languages", "cache", "translation (mock)"] {
+        assert!(logs.contains(&format!("[pass] {check}:")), "{logs}");
+    }
+    assert!(logs.contains("2 languages parsed from"), "{logs}");
+    assert!(logs.contains("Self-test passed (4/4)"), "{logs}");
+    assert!(!logs.contains("[FAIL]"), "{logs}");
+}
+
+#[tokio::test]
+async fn selftest_upstream_failure_stops_startup() {
+    let upstream =
+        mock_upstream(ResponseTemplate::new(400).set_body_string("bad request"), 1).await;
+    let url = format!("{}/api/v3/responses", upstream.uri());
+    let stderr = config_error(&[
+        ("ARK_API_URL", &url),
+        ("HTTP_DISABLE_PROXY", "true"),
+        ("RUN_SELFTEST", "true"),
+        ("SELFTEST_UPSTREAM", "true"),
+    ]);
+    assert!(stderr.contains("Self-test failed (3/4 passed)"), "{stderr}");
+}
+
+#[tokio::test]
+async fn admin_reload_applies_changed_files() {
+    let languages = fixture_file(
+        "reload-languages.json",
+        r#"{ "en": "English", "zh": "中文" }"#,
+    );
+    let config = fixture_file(
+        "reload-config.toml",
+        &format!("provider = \"mock\"\nlanguages_file = {:?}\n", languages),
+    );
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(
+        &upstream,
+        &[
+            ("CONFIG_FILE", config.to_str().unwrap()),
+            ("SERVER_API_KEYS", "admin-key"),
+        ],
+    )
+    .await;
+    let client = reqwest::Client::new();
+    let translate = |text: &str, target: &str| {
+        client
+            .post(server.url("/api/translate"))
+            .bearer_auth("admin-key")
+            .json(&json!({ "text": text, "target": target }))
+            .send()
+    };
+
+    let resp = translate("hello", "ja").await.unwrap();
+    assert_eq!(resp.status(), 400);
+
+    std::fs::write(&languages, r#"{ "en": "English", "ja": "日本語" }"#).unwrap();
+    std::fs::write(
+        &config,
+        format!(
+            "provider = \"mock\"\nlanguages_file = {:?}\nmax_text_length = 3\ncache_ttl = 60\n",
+            languages
+        ),
+    )
+    .unwrap();
+    let resp = client
+        .post(server.url("/api/admin/reload"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    let body: Value = client
+        .post(server.url("/api/admin/reload"))
+        .bearer_auth("admin-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body, json!({ "success": true, "ignored": ["CACHE_TTL"] }));
+
+    let body: Value = translate("hi", "ja").await.unwrap().json().await.unwrap();
+    assert_eq!(body["text"], "[ja] hi");
+    let body: Value = translate("hello", "ja")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["code"], "TEXT_TOO_LONG");
+}
+
+#[tokio::test]
+async fn admin_reload_is_refused_without_server_keys() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/admin/reload"))
+        .bearer_auth("anything")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "ADMIN_DISABLED");
+    assert!(!server.logs().contains("Configuration reloaded"));
+}
+
+#[tokio::test]
+async fn stale_entries_are_served_while_one_refresh_runs() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(doubao_reply("旧的翻译"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(doubao_reply("新的翻译").set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(
+        &upstream,
+        &[("CACHE_TTL", "1"), ("STALE_WHILE_REVALIDATE_SECS", "60")],
+    )
+    .await;
+    let request = json!({ "text": "hello", "source": "en", "target": "zh" });
+
+    let (_, body) = server.translate(request.clone()).await;
+    assert!(body.get("stale").is_none());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let started = std::time::Instant::now();
+    let stale = futures::future::join_all((0..3).map(|_| server.translate(request.clone()))).await;
+    assert!(started.elapsed() < Duration::from_millis(400));
+    for (status, body) in stale {
+        assert_eq!(status, 200);
+        assert_eq!(body["text"], "旧的翻译");
+        assert_eq!(body["cached"], true);
+        assert_eq!(body["stale"], true);
+    }
+
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    let (_, body) = server.translate(request).await;
+    assert_eq!(body["text"], "新的翻译");
+    assert_eq!(body["cached"], true);
+    assert!(body.get("stale").is_none());
+}
+
+#[tokio::test]
+async fn malformed_json_gets_the_standard_error_body() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+    let post = |body: &'static str| {
+        client
+            .post(server.url("/api/translate"))
+            .header("Content-Type", "application/json")
+            .header("Accept-Language", "en")
+            .body(body)
+            .send()
+    };
+
+    let resp = post(r#"{ "text": "hello", "target": "zh", }"#)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["success"], false);
+    assert_eq!(body["code"], "BAD_JSON");
+    let error = body["error"].as_str().unwrap();
+    assert!(error.starts_with("Malformed JSON body: "), "{error}");
+    assert!(error.contains("line 1 column 36"), "{error}");
+
+    let resp = post(r#"{ "text": 42, "target": "zh" }"#).await.unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "BAD_JSON");
+    let error = body["error"].as_str().unwrap();
+    assert!(
+        error.contains("text: invalid type: integer `42`"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn translation_content_is_only_logged_when_enabled() {
+    let request = json!({ "text": "my secret plan", "source": "en", "target": "zh" });
+
+    let upstream = mock_upstream(doubao_reply("我的秘密计划"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    assert_eq!(server.translate(request.clone()).await.0, 200);
+    let logs = server.logs();
+    assert!(logs.contains("Translated 14 chars to zh"), "{logs}");
+    assert!(!logs.contains("my secret plan"), "{logs}");
+    assert!(!logs.contains("我的秘密计划"), "{logs}");
+
+    let upstream = mock_upstream(doubao_reply("我的秘密计划"), 1).await;
+    let server = TestServer::start(&upstream, &[("LOG_TRANSLATION_CONTENT", "true")]).await;
+    assert_eq!(server.translate(request).await.0, 200);
+    let logs = server.logs();
+    assert!(logs.contains("exposes user data"), "{logs}");
+    assert!(
+        logs.contains(r#""my secret plan" -> "我的秘密计划""#),
+        "{logs}"
+    );
+}
+
+#[tokio::test]
+async fn languages_carry_a_stable_etag() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+
+    let first = client
+        .get(server.url("/api/languages"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(first.status(), 200);
+    assert_eq!(first.headers()["cache-control"], "public, max-age=300");
+    let etag = first.headers()["etag"].clone();
+    let body: Value = first.json().await.unwrap();
+    assert_eq!(body["languages"]["en"], "英语");
+
+    let second = client
+        .get(server.url("/api/languages"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(second.headers()["etag"], etag);
+
+    let cached = client
+        .get(server.url("/api/languages"))
+        .header("If-None-Match", etag.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cached.status(), 304);
+    assert_eq!(cached.headers()["etag"], etag);
+    assert!(cached.bytes().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn per_language_limits_apply_independently() {
+    let server = TestServer::start(
+        &mock_upstream(doubao_reply("unused"), 0).await,
+        &[("PROVIDER", "mock"), ("RATE_LIMIT_PER_LANG", "ja=1, en=2")],
+    )
+    .await;
+    let translate = |target: &str| server.translate(json!({ "text": "hello", "target": target }));
+
+    assert_eq!(translate("ja").await.0, 200);
+    let (status, body) = translate("ja").await;
+    assert_eq!(status, 429);
+    assert_eq!(body["code"], "RATE_LIMITED");
+    assert!(body["error"].as_str().unwrap().contains("ja"), "{body}");
+    assert!(body["retry_after"].as_u64().unwrap() > 0);
+
+    assert_eq!(translate("en").await.0, 200);
+    assert_eq!(translate("en").await.0, 200);
+    assert_eq!(translate("en").await.0, 429);
+    assert_eq!(translate("zh").await.0, 200);
+}
+
+#[tokio::test]
+async fn mock_mode_fakes_translations_offline() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("MOCK_MODE", "true"), ("ARK_API_KEY", "")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "[zh] hello");
+    assert_eq!(body["cached"], false);
+
+    let (_, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(body["cached"], true);
+    assert!(upstream.received_requests().await.unwrap().is_empty());
+
+    let stderr = config_error(&[("MOCK_MODE", "true"), ("PROVIDER", "doubao")]);
+    assert!(
+        stderr.contains("MOCK_MODE=true conflicts with PROVIDER=doubao"),
+        "{stderr}"
+    );
+}
+
+#[tokio::test]
+async fn client_disconnect_cancels_remaining_chunks() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("好").set_delay(Duration::from_millis(400)))
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let text = vec!["a".repeat(700); 6].join("\n");
+
+    let result = reqwest::Client::new()
+        .post(server.url("/api/translate"))
+        .timeout(Duration::from_millis(600))
+        .json(&json!({ "text": text, "source": "en", "target": "zh" }))
+        .send()
+        .await;
+    assert!(result.unwrap_err().is_timeout());
+
+    tokio::time::sleep(Duration::from_millis(2000)).await;
+    let calls = upstream.received_requests().await.unwrap().len();
+    assert!(calls <= 2, "{calls} chunk calls after the client went away");
+}
+
+#[tokio::test]
+async fn blank_output_is_retried_once() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("  "))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("你好"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "你好");
+    assert!(body.get("warning").is_none());
+}
+
+#[tokio::test]
+async fn blank_output_after_retry_carries_a_warning() {
+    let upstream = mock_upstream(doubao_reply(""), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "");
+    assert!(body["warning"].is_string(), "{body}");
+}
+
+#[tokio::test]
+async fn version_reports_the_build() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let resp = reqwest::get(server.url("/api/version")).await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    match body["git_sha"].as_str() {
+        Some(_) => assert!(body["build_time"].as_u64().unwrap() > 1_600_000_000),
+        None => assert!(body["build_time"].is_null(), "{body}"),
+    }
+}
+
+#[tokio::test]
+async fn use_context_sends_the_previous_chunk_tail() {
+    let upstream = mock_upstream(doubao_reply("好"), 2).await;
+    let server = TestServer::start(&upstream, &[("CONTEXT_MAX_CHARS", "40")]).await;
+    let text = format!(
+        "{}\nAlice lost her key near the old bridge.\n{} She found it.",
+        "x".repeat(700),
+        "y".repeat(100)
+    );
+
+    let (status, _) = server
+        .translate(json!({ "text": text, "source": "en", "target": "zh", "use_context": true }))
+        .await;
+    assert_eq!(status, 200);
+
+    let bodies: Vec<String> = upstream
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| String::from_utf8_lossy(&request.body).into_owned())
+        .collect();
+    assert!(!bodies[0].contains("For context only"), "{}", bodies[0]);
+    assert!(bodies[1].contains("For context only"), "{}", bodies[1]);
+    assert!(bodies[1].contains("lost her key near the old bridge."));
+    assert!(
+        !bodies[1].contains("xxxxx"),
+        "context is bounded: {}",
+        bodies[1]
+    );
+}
+
+#[tokio::test]
+async fn deny_patterns_block_matching_input() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(
+        &upstream,
+        &[(
+            "DENY_PATTERNS",
+            r"codename=(?i)project\s+falcon; \bTOP SECRET\b",
+        )],
+    )
+    .await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "Status of Project Falcon?", "target": "zh" }))
+        .await;
+    assert_eq!(status, 403);
+    assert_eq!(body["code"], "CONTENT_BLOCKED");
+
+    let (status, _) = server
+        .translate(json!({ "text": "TOP SECRET memo", "target": "zh" }))
+        .await;
+    assert_eq!(status, 403);
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "你好");
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let logs = server.logs();
+    assert!(logs.contains("category: codename"), "{logs}");
+    assert!(logs.contains("category: pattern 2"), "{logs}");
+    assert!(!logs.contains("Falcon"), "{logs}");
+}
+
+#[test]
+fn invalid_deny_patterns_are_rejected_at_startup() {
+    let stderr = config_error(&[("DENY_PATTERNS", "bad=(unclosed")]);
+    assert!(
+        stderr.contains("invalid DENY_PATTERNS entry \"bad\""),
+        "{stderr}"
+    );
+}
+
+#[tokio::test]
+async fn daily_request_budget_rejects_once_spent() {
+    let upstream = mock_upstream(doubao_reply("你好"), 2).await;
+    let server = TestServer::start(&upstream, &[("DAILY_REQUEST_BUDGET", "2")]).await;
+
+    for text in ["one", "two"] {
+        let (status, _) = server
+            .translate(json!({ "text": text, "target": "zh" }))
+            .await;
+        assert_eq!(status, 200);
+    }
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate"))
+        .json(&json!({ "text": "three", "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "BUDGET_EXCEEDED");
+
+    // Cache hits stay free.
+    let (status, body) = server
+        .translate(json!({ "text": "one", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["cached"], true);
+
+    let stats: Value = reqwest::get(server.url("/api/stats"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["budget"]["requests"], 2);
+}
+
+#[tokio::test]
+async fn token_budget_survives_a_restart() {
+    let upstream = mock_upstream(
+        ResponseTemplate::new(200).set_body_json(json!({
+            "status": "completed",
+            "output": [{
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "output_text", "text": "你好" }]
+            }],
+            "usage": { "total_tokens": 50 }
+        })),
+        2,
+    )
+    .await;
+    let state = std::env::temp_dir().join(format!("translator-budget-{}.json", free_port()));
+    let env = [
+        ("DAILY_TOKEN_BUDGET", "100"),
+        ("BUDGET_STATE_FILE", state.to_str().unwrap()),
+    ];
+
+    let server = TestServer::start(&upstream, &env).await;
+    for text in ["one", "two"] {
+        let (status, _) = server
+            .translate(json!({ "text": text, "target": "zh" }))
+            .await;
+        assert_eq!(status, 200);
+    }
+    drop(server);
+
+    let server = TestServer::start(&upstream, &env).await;
+    let (status, body) = server
+        .translate(json!({ "text": "three", "target": "zh" }))
+        .await;
+    let _ = std::fs::remove_file(&state);
+    assert_eq!(status, 429);
+    assert_eq!(body["code"], "BUDGET_EXCEEDED");
+}
+
+#[tokio::test]
+async fn response_schema_renames_the_translation_fields() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let request = json!({ "text": "hello", "target": "zh" });
+    let mut bodies = Vec::new();
+    for schema in ["default", "flat", "openai"] {
+        let server = TestServer::start(
+            &upstream,
+            &[("PROVIDER", "mock"), ("RESPONSE_SCHEMA", schema)],
+        )
+        .await;
+        let (status, body) = server.translate(request.clone()).await;
+        assert_eq!(status, 200);
+        let (status, error) = server
+            .translate(json!({ "text": "", "target": "zh" }))
+            .await;
+        assert_eq!(status, 400);
+        bodies.push((body, error));
+    }
+
+    let (default, error) = &bodies[0];
+    assert_eq!(default["text"], "[zh] hello");
+    assert_eq!(default["success"], true);
+    assert_eq!(error["code"], "EMPTY_TEXT");
+
+    let (flat, error) = &bodies[1];
+    assert_eq!(flat, &json!({ "translation": "[zh] hello" }));
+    assert_eq!(error["code"], "EMPTY_TEXT");
+    assert!(error.get("success").is_none());
+
+    let (openai, error) = &bodies[2];
+    assert_eq!(openai["choices"][0]["message"]["content"], "[zh] hello");
+    assert_eq!(openai["choices"][0]["finish_reason"], "stop");
+    assert!(openai.get("text").is_none());
+    assert_eq!(error["error"]["code"], "EMPTY_TEXT");
+}
+
+#[test]
+fn unknown_response_schema_is_rejected() {
+    let stderr = config_error(&[("RESPONSE_SCHEMA", "xml")]);
+    assert!(stderr.contains("invalid RESPONSE_SCHEMA"), "{stderr}");
+}
+
+#[tokio::test]
+async fn normalized_variants_share_a_cache_entry() {
+    let upstream = mock_upstream(doubao_reply("咖啡"), 3).await;
+    let server = TestServer::start(&upstream, &[("NORMALIZE_INPUT", "true")]).await;
+
+    let mut cached = Vec::new();
+    for text in ["caf\u{e9} ABC1", "cafe\u{301} ＡＢＣ１", "cafe\u{301} ABC1"] {
+        let (status, body) = server
+            .translate(json!({ "text": text, "target": "zh" }))
+            .await;
+        assert_eq!(status, 200);
+        cached.push(body["cached"].clone());
+    }
+    assert_eq!(cached, [false, true, true]);
+
+    let requests = upstream.received_requests().await.unwrap();
+    let sent = String::from_utf8_lossy(&requests[0].body).into_owned();
+    assert!(sent.contains("caf\u{e9} ABC1"), "{sent}");
+
+    // Without the setting the NFD form is a different key.
+    let server = TestServer::start(&upstream, &[]).await;
+    server
+        .translate(json!({ "text": "caf\u{e9} ABC1", "target": "zh" }))
+        .await;
+    let (_, body) = server
+        .translate(json!({ "text": "cafe\u{301} ABC1", "target": "zh" }))
+        .await;
+    assert_eq!(body["cached"], false);
+}
+
+#[tokio::test]
+async fn root_falls_back_to_json_without_an_index() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let dir = std::env::temp_dir().join(format!("translator-no-index-{}", free_port()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let missing = dir.join("missing");
+
+    for static_dir in [&dir, &missing] {
+        let server =
+            TestServer::start(&upstream, &[("STATIC_DIR", static_dir.to_str().unwrap())]).await;
+        let resp = reqwest::get(server.url("/")).await.unwrap();
+        assert_eq!(resp.status(), 404);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["service"], "doubao-translator");
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body["error"].as_str().unwrap().contains("/api"));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// `(event, data)` pairs of an SSE body.
+fn sse_events(body: &str) -> Vec<(String, Value)> {
+    body.split("\n\n")
+        .filter_map(|block| {
+            let field = |name: &str| {
+                block
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(str::trim)
+            };
+            Some((
+                field("event:")?.to_string(),
+                serde_json::from_str(field("data:")?).ok()?,
+            ))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn stream_emits_parallel_chunks_in_order() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .and(wiremock::matchers::body_string_contains("aaaa"))
+        .respond_with(doubao_reply("第一").set_delay(Duration::from_millis(800)))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .and(wiremock::matchers::body_string_contains("bbbb"))
+        .respond_with(doubao_reply("第二").set_delay(Duration::from_millis(400)))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let text = format!("{}\n{}", "a".repeat(700), "b".repeat(700));
+
+    let started = std::time::Instant::now();
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate/stream"))
+        .json(&json!({ "text": text, "source": "en", "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/event-stream"));
+    let events = sse_events(&resp.text().await.unwrap());
+
+    // The second chunk finished first but is still sent second.
+    assert_eq!(
+        events,
+        [
+            (
+                "chunk".into(),
+                json!({ "index": 0, "text": "第一", "cached": false })
+            ),
+            (
+                "chunk".into(),
+                json!({ "index": 1, "text": "第二", "cached": false })
+            ),
+            ("done".into(), json!({ "chunk_count": 2 })),
+        ]
+    );
+    assert!(
+        started.elapsed() < Duration::from_millis(1100),
+        "chunks ran one by one"
+    );
+}
+
+#[tokio::test]
+async fn upstream_streaming_forwards_tokens_as_they_arrive() {
+    let deltas = ["你", "好", "，世界"];
+    let mut sse: String = deltas
+        .iter()
+        .map(|delta| {
+            let event = json!({ "type": "response.output_text.delta", "delta": delta });
+            format!("event: response.output_text.delta\ndata: {event}\n\n")
+        })
+        .collect();
+    let completed = json!({
+        "type": "response.completed",
+        "response": { "status": "completed", "usage": { "total_tokens": 12 } }
+    });
+    sse.push_str(&format!("event: response.completed\ndata: {completed}\n\n"));
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .and(wiremock::matchers::body_partial_json(
+            json!({ "stream": true }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(sse, "text/event-stream"))
+        .with_priority(1)
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("再见"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[("UPSTREAM_STREAMING", "true")]).await;
+
+    let resp = reqwest::Client::new()
+        .post(server.url("/api/translate/stream"))
+        .json(&json!({ "text": "Hello, world", "source": "en", "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let events = sse_events(&resp.text().await.unwrap());
+    let mut expected: Vec<(String, Value)> = deltas
+        .iter()
+        .map(|delta| ("token".into(), json!({ "index": 0, "text": delta })))
+        .collect();
+    expected.push((
+        "chunk".into(),
+        json!({ "index": 0, "text": "你好，世界", "cached": false }),
+    ));
+    expected.push(("done".into(), json!({ "chunk_count": 1 })));
+    assert_eq!(events, expected);
+
+    // Plain requests have no one to forward tokens to, so they don't stream.
+    let (status, body) = server
+        .translate(json!({ "text": "Goodbye", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body["text"], "再见");
+}
+
+#[tokio::test]
+async fn pair_translates_towards_the_other_language() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("PROVIDER", "mock")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "今天天气很好，我们去公园散步吧。", "pair": ["zh", "en"] }))
+        .await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body["text"], "[en] 今天天气很好，我们去公园散步吧。");
+    assert_eq!(body["detected_source"], "zh");
+
+    let (status, body) = server
+        .translate(json!({ "text": "The weather is lovely today, let us go for a walk.", "pair": ["zh", "en"] }))
+        .await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(
+        body["text"],
+        "[zh] The weather is lovely today, let us go for a walk."
+    );
+    assert_eq!(body["detected_source"], "en");
+
+    let (status, _) = server
+        .translate(json!({ "text": "hello", "pair": ["en", "en"] }))
+        .await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn requests_beyond_max_inflight_are_shed() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("好").set_delay(Duration::from_millis(600)))
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[("MAX_INFLIGHT_REQUESTS", "3")]).await;
+
+    let client = reqwest::Client::new();
+    let requests = (0..8).map(|i| {
+        client
+            .post(server.url("/api/translate"))
+            .json(&json!({ "text": format!("hello {i}"), "target": "zh" }))
+            .send()
+    });
+    let responses = futures::future::join_all(requests).await;
+
+    let mut ok = 0;
+    let mut shed = 0;
+    for resp in responses {
+        let resp = resp.expect("request failed");
+        match resp.status().as_u16() {
+            200 => ok += 1,
+            503 => {
+                assert_eq!(resp.headers()["retry-after"], "1");
+                let body: Value = resp.json().await.unwrap();
+                assert_eq!(body["code"], "SERVICE_UNAVAILABLE");
+                shed += 1;
+            }
+            status => panic!("unexpected status {status}"),
+        }
+    }
+    assert_eq!((ok, shed), (3, 5));
+
+    // Slots are released once the responses have gone out.
+    let (status, _) = server
+        .translate(json!({ "text": "hello again", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn alternatives_come_from_extra_calls_at_higher_temperatures() {
+    let upstream = mock_upstream(doubao_reply("你好"), 3).await;
+    let server = TestServer::start(&upstream, &[("MAX_ALTERNATIVES", "2")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh", "alternatives": 2 }))
+        .await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body["text"], "你好");
+    assert_eq!(body["alternatives"], json!(["你好", "你好"]));
+
+    let mut temperatures: Vec<f64> = upstream
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|req| req.body_json::<Value>().unwrap()["temperature"].as_f64())
+        .collect();
+    temperatures.sort_by(f64::total_cmp);
+    assert_eq!(temperatures.len(), 2);
+    assert!(temperatures[0] < temperatures[1], "{temperatures:?}");
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh", "alternatives": 3 }))
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["code"], "INVALID_REQUEST");
+}
+
+#[tokio::test]
+async fn cache_ttl_secs_shortens_a_single_entry() {
+    let upstream = mock_upstream(doubao_reply("比分 1:0"), 2).await;
+    let server = TestServer::start(&upstream, &[("CACHE_TTL", "3600")]).await;
+    let request = json!({ "text": "score 1:0", "target": "zh", "cache_ttl_secs": 1 });
+
+    let (_, body) = server.translate(request.clone()).await;
+    assert_eq!(body["cached"], false);
+    let (_, body) = server.translate(request.clone()).await;
+    assert_eq!(body["cached"], true);
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let (status, body) = server.translate(request).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["cached"], false);
+}
+
+#[tokio::test]
+async fn audit_log_records_outcomes_without_content() {
+    let upstream = mock_upstream(doubao_reply("机密的译文"), 4).await;
+    let log = std::env::temp_dir().join(format!("translator-audit-{}.jsonl", free_port()));
+    let server = TestServer::start(&upstream, &[("AUDIT_LOG_FILE", log.to_str().unwrap())]).await;
+
+    let request = json!({ "text": "confidential source", "source": "en", "target": "zh" });
+    let (status, _) = server.translate(request.clone()).await;
+    assert_eq!(status, 200);
+    let (status, _) = server.translate(request).await;
+    assert_eq!(status, 200);
+    let (status, _) = server
+        .translate(json!({ "text": "", "target": "zh" }))
+        .await;
+    assert_eq!(status, 400);
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(server.url("/api/translate/stream"))
+        .json(&json!({ "text": "confidential stream", "source": "en", "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let events = sse_events(&resp.text().await.unwrap());
+    assert_eq!(events.last().unwrap().0, "done");
+    let boundary = "translator-test-boundary";
+    let fields: &[(&str, Option<&str>, &[u8])] = &[
+        ("target", None, b"zh"),
+        ("file", Some("secret.txt"), b"confidential upload"),
+    ];
+    let resp = client
+        .post(server.url("/api/translate/file"))
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .body(multipart_body(boundary, fields))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let resp = client
+        .post(server.url("/api/translate/json"))
+        .json(&json!({ "data": { "title": "confidential json" }, "target": "zh" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let raw = std::fs::read_to_string(&log).expect("audit log was written");
+    let _ = std::fs::remove_file(&log);
+    assert!(!raw.contains("confidential"), "{raw}");
+    assert!(!raw.contains("机密"), "{raw}");
+    let lines: Vec<Value> = raw
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 6, "{raw}");
+
+    let first = &lines[0];
+    for field in ["timestamp_ms", "request_id", "latency_ms"] {
+        assert!(first.get(field).is_some(), "missing {field}: {first}");
+    }
+    assert_eq!(first["source"], "en");
+    assert_eq!(first["target"], "zh");
+    assert_eq!(first["chars"], 19);
+    assert_eq!(first["chunks"], 1);
+    assert_eq!(first["cached"], false);
+    assert_eq!(first["success"], true);
+    assert!(first.get("code").is_none());
+
+    assert_eq!(lines[1]["cached"], true);
+    assert_ne!(lines[1]["request_id"], first["request_id"]);
+    assert_eq!(lines[2]["success"], false);
+    assert_eq!(lines[2]["code"], "EMPTY_TEXT");
+
+    // The stream, upload and JSON document, in that order.
+    for (line, chars) in lines[3..].iter().zip([19, 19, 17]) {
+        assert_eq!(line["target"], "zh", "{line}");
+        assert_eq!(line["chars"], chars, "{line}");
+        assert_eq!(line["chunks"], 1, "{line}");
+        assert_eq!(line["cached"], false, "{line}");
+        assert_eq!(line["success"], true, "{line}");
+    }
+}
+
+#[tokio::test]
+async fn language_aliases_share_the_canonical_cache_entry() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["cached"], false);
+    for target in ["zh-CN", "ZH", "zh_Hans", "chinese"] {
+        let (status, body) = server
+            .translate(json!({ "text": "hello", "source": "en-US", "target": target }))
+            .await;
+        assert_eq!(status, 200, "{target}: {body}");
+        assert_eq!(body["cached"], true, "{target}");
+    }
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "klingon" }))
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["code"], "INVALID_TARGET");
+}
+
+#[tokio::test]
+async fn debug_raw_forwards_input_only_when_enabled() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .and(wiremock::matchers::body_json(json!({
+            "model": "debug-model",
+            "input": [{ "role": "user", "content": "raw prompt" }]
+        })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "id": "resp-1", "raw": true })),
+        )
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let request = json!({
+        "model": "debug-model",
+        "input": [{ "role": "user", "content": "raw prompt" }]
+    });
+    let client = reqwest::Client::new();
+
+    let server = TestServer::start(&upstream, &[("SERVER_API_KEYS", "admin-key")]).await;
+    let resp = client
+        .post(server.url("/api/debug/raw"))
+        .bearer_auth("admin-key")
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    drop(server);
+
+    let env = [
+        ("SERVER_API_KEYS", "admin-key"),
+        ("ENABLE_DEBUG_ENDPOINTS", "true"),
+    ];
+    let server = TestServer::start(&upstream, &env).await;
+    let resp = client
+        .post(server.url("/api/debug/raw"))
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+
+    let resp = client
+        .post(server.url("/api/debug/raw"))
+        .bearer_auth("admin-key")
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body, json!({ "id": "resp-1", "raw": true }));
+
+    let stderr = config_error(&[("ENABLE_DEBUG_ENDPOINTS", "true")]);
+    assert!(
+        stderr.contains("ENABLE_DEBUG_ENDPOINTS requires SERVER_API_KEYS"),
+        "{stderr}"
+    );
+}
+
+async fn poll_job(server: &TestServer, id: &str, done: impl Fn(&Value) -> bool) -> Value {
+    let client = reqwest::Client::new();
+    for _ in 0..100 {
+        let job: Value = client
+            .get(server.url(&format!("/api/jobs/{id}")))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if done(&job) {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("job {id} never reached the expected state");
+}
+
+#[tokio::test]
+async fn jobs_run_in_the_background_until_complete() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("你好").set_delay(Duration::from_millis(200)))
+        .expect(3)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+
+    let items: Vec<Value> = (0..3)
+        .map(|i| json!({ "text": format!("hello {i}"), "target": "zh" }))
+        .chain([json!({ "text": "", "target": "zh" })])
+        .collect();
+    let resp = client
+        .post(server.url("/api/jobs"))
+        .json(&json!({ "items": items }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["total"], 4);
+    let id = body["job_id"].as_str().unwrap().to_string();
+
+    let job = poll_job(&server, &id, |job| job["status"] != "running").await;
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["completed"], 3);
+    assert_eq!(job["failed"], 1);
+    for i in 0..3 {
+        assert_eq!(job["results"][i]["text"], "你好");
+    }
+    assert_eq!(job["results"][3]["code"], "EMPTY_TEXT");
+
+    let resp = client
+        .get(server.url("/api/jobs/no-such-job"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+async fn cancelling_a_job_stops_the_remaining_items() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("你好").set_delay(Duration::from_millis(300)))
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[("JOB_CONCURRENCY", "1")]).await;
+    let client = reqwest::Client::new();
+
+    let items: Vec<Value> = (0..10)
+        .map(|i| json!({ "text": format!("hello {i}"), "target": "zh" }))
+        .collect();
+    let body: Value = client
+        .post(server.url("/api/jobs"))
+        .json(&json!({ "items": items }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = body["job_id"].as_str().unwrap().to_string();
+    poll_job(&server, &id, |job| job["completed"].as_u64() >= Some(1)).await;
+
+    let job: Value = client
+        .delete(server.url(&format!("/api/jobs/{id}")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(job["status"], "cancelled");
+    let sent = upstream.received_requests().await.unwrap().len();
+
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    let job = poll_job(&server, &id, |_| true).await;
+    assert_eq!(job["status"], "cancelled");
+    let completed = job["completed"].as_u64().unwrap();
+    assert!((1..10).contains(&completed), "completed {completed}");
+    assert!(job["results"][9].is_null());
+    assert_eq!(upstream.received_requests().await.unwrap().len(), sent);
+}
+
+#[tokio::test]
+async fn running_jobs_hold_their_slots_until_cancelled() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .respond_with(doubao_reply("你好").set_delay(Duration::from_millis(300)))
+        .mount(&upstream)
+        .await;
+    let env = [
+        ("SERVER_API_KEYS", "tenant-key,other-key"),
+        ("PER_KEY_CONCURRENCY", "1"),
+        ("MAX_RUNNING_JOBS", "1"),
+        ("JOB_CONCURRENCY", "1"),
+    ];
+    let server = TestServer::start(&upstream, &env).await;
+    let client = reqwest::Client::new();
+    let items: Vec<Value> = (0..20)
+        .map(|i| json!({ "text": format!("hello {i}"), "target": "zh" }))
+        .collect();
+    let create_job = |key: &'static str| {
+        client
+            .post(server.url("/api/jobs"))
+            .bearer_auth(key)
+            .json(&json!({ "items": items }))
+            .send()
+    };
+    let translate = |key: &'static str| {
+        client
+            .post(server.url("/api/translate"))
+            .bearer_auth(key)
+            .json(&json!({ "text": "quick", "target": "zh" }))
+            .send()
+    };
+
+    let resp = create_job("tenant-key").await.unwrap();
+    assert_eq!(resp.status(), 202);
+    let id = resp.json::<Value>().await.unwrap()["job_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // The job keeps the tenant's only per-key slot.
+    let body: Value = translate("tenant-key").await.unwrap().json().await.unwrap();
+    assert_eq!(body["code"], "CONCURRENCY_LIMITED", "{body}");
+    let resp = create_job("other-key").await.unwrap();
+    assert_eq!(resp.status(), 503);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "TOO_MANY_JOBS");
+    let resp = translate("other-key").await.unwrap();
+    assert_eq!(resp.status(), 200);
+
+    client
+        .delete(server.url(&format!("/api/jobs/{id}")))
+        .bearer_auth("tenant-key")
+        .send()
+        .await
+        .unwrap();
+    let mut status = 0;
+    for _ in 0..20 {
+        status = create_job("other-key").await.unwrap().status().as_u16();
+        if status == 202 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(status, 202);
+    drop(server);
+
+    // A running job also counts against MAX_INFLIGHT_REQUESTS.
+    let env = [("MAX_INFLIGHT_REQUESTS", "1"), ("JOB_CONCURRENCY", "1")];
+    let server = TestServer::start(&upstream, &env).await;
+    let resp = client
+        .post(server.url("/api/jobs"))
+        .json(&json!({ "items": items }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let (status, body) = server
+        .translate(json!({ "text": "quick", "target": "zh" }))
+        .await;
+    assert_eq!(status, 503, "{body}");
+}
+
+#[tokio::test]
+async fn fault_injection_fails_requests_without_calling_upstream() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let env = [
+        ("FAULT_INJECTION_RATE", "1.0"),
+        ("FAULT_INJECTION_STATUS", "502"),
+    ];
+    let server = TestServer::start(&upstream, &env).await;
+
+    for i in 0..5 {
+        let (status, body) = server
+            .translate(json!({ "text": format!("hello {i}"), "target": "zh" }))
+            .await;
+        assert_eq!(status, 502);
+        assert_eq!(body["code"], "INJECTED_FAULT");
+    }
+    let resp = reqwest::get(server.url("/api/health")).await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(server.logs().contains("Injected a 502 Bad Gateway fault"));
+
+    let stderr = config_error(&[("FAULT_INJECTION_RATE", "2")]);
+    assert!(
+        stderr.contains("FAULT_INJECTION_RATE must be between 0 and 1"),
+        "{stderr}"
+    );
+}
+
+#[tokio::test]
+async fn forwarded_client_ip_is_only_trusted_with_trust_proxy() {
+    // One upstream call per server; the second request is a cache hit.
+    let upstream = mock_upstream(doubao_reply("你好"), 2).await;
+    let client = reqwest::Client::new();
+    let mut seen = Vec::new();
+    for trust in ["false", "true"] {
+        let log = std::env::temp_dir().join(format!("translator-proxy-{}.jsonl", free_port()));
+        let env = [
+            ("AUDIT_LOG_FILE", log.to_str().unwrap()),
+            ("TRUST_PROXY", trust),
+        ];
+        let server = TestServer::start(&upstream, &env).await;
+        // The proxy appends the real client after whatever the client sent.
+        for (name, value) in [
+            ("x-forwarded-for", "192.0.2.66, 203.0.113.7"),
+            ("x-real-ip", "198.51.100.2"),
+        ] {
+            let resp = client
+                .post(server.url("/api/translate"))
+                .header(name, value)
+                .header("x-forwarded-proto", "https")
+                .json(&json!({ "text": "hello", "target": "zh" }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 200);
+        }
+        let raw = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_file(&log);
+        for line in raw.lines() {
+            let record: Value = serde_json::from_str(line).unwrap();
+            let ip = record["client_ip"].as_str().unwrap();
+            seen.push(format!("{}://{ip}", record["scheme"].as_str().unwrap()));
+        }
+    }
+    assert_eq!(
+        seen,
+        [
+            "http://127.0.0.1",
+            "http://127.0.0.1",
+            "https://203.0.113.7",
+            "https://198.51.100.2"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn translation_memory_survives_export_and_import() {
+    let upstream = mock_upstream(doubao_reply("你好"), 1).await;
+    let env = [("SERVER_API_KEYS", "admin-key")];
+    let client = reqwest::Client::new();
+    let translate = |server: &TestServer| {
+        client
+            .post(server.url("/api/translate"))
+            .bearer_auth("admin-key")
+            .json(&json!({ "text": "hello", "source": "en", "target": "zh" }))
+            .send()
+    };
+
+    let server = TestServer::start(&upstream, &env).await;
+    let body: Value = translate(&server).await.unwrap().json().await.unwrap();
+    assert_eq!(body["text"], "你好");
+    let resp = client
+        .get(server.url("/api/tm/export"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    let export: Value = client
+        .get(server.url("/api/tm/export"))
+        .bearer_auth("admin-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let entries = export["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1, "{export}");
+    assert_eq!(entries[0]["text"], "hello");
+    assert_eq!(entries[0]["source"], "en");
+    assert_eq!(entries[0]["target"], "zh");
+    assert_eq!(entries[0]["translation"], "你好");
+    assert!(entries[0]["created_at_ms"].as_u64().unwrap() > 0);
+    assert!(entries[0]["expires_at_ms"].as_u64() > entries[0]["created_at_ms"].as_u64());
+    drop(server);
+
+    // A fresh server starts with an empty cache; the import alone fills it.
+    let server = TestServer::start(&upstream, &env).await;
+    let resp = client
+        .post(server.url("/api/tm/import"))
+        .bearer_auth("admin-key")
+        .json(&export)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let imported: Value = resp.json().await.unwrap();
+    assert_eq!(imported["imported"], 1, "{imported}");
+
+    let body: Value = translate(&server).await.unwrap().json().await.unwrap();
+    assert_eq!(body["text"], "你好");
+    assert_eq!(body["cached"], true, "{body}");
+}
+
+#[tokio::test]
+async fn translation_memory_is_refused_without_server_keys() {
+    let upstream = mock_upstream(doubao_reply("你好"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let (_, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(body["text"], "你好");
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(server.url("/api/tm/export"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "ADMIN_DISABLED");
+    assert!(body.get("entries").is_none());
+
+    let poisoned = json!({ "entries": [{
+        "text": "goodbye", "source": "en", "target": "zh", "translation": "你好",
+        "created_at_ms": 1, "expires_at_ms": u64::MAX,
+    }] });
+    let resp = client
+        .post(server.url("/api/tm/import"))
+        .json(&poisoned)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+    let (_, body) = server
+        .translate(json!({ "text": "goodbye", "source": "en", "target": "zh" }))
+        .await;
+    assert_ne!(body["cached"], true, "{body}");
+}
+
+#[tokio::test]
+async fn whitespace_only_chunks_skip_the_upstream() {
+    let text = format!("Hello there.\n\n{}\n\nGoodbye.", "\n \n".repeat(600));
+    let chunks = split_text(&text, 800);
+    let blank = chunks
+        .iter()
+        .filter(|chunk| is_whitespace_only(chunk))
+        .count();
+    assert!(blank > 0, "{chunks:?}");
+    let upstream = mock_upstream(doubao_reply("你好"), (chunks.len() - blank) as u64).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": text, "source": "en", "target": "zh" }))
+        .await;
+
+    assert_eq!(status, 200, "{body}");
+    let expected: Vec<&str> = chunks
+        .iter()
+        .map(|chunk| {
+            if is_whitespace_only(chunk) {
+                chunk
+            } else {
+                "你好"
+            }
+        })
+        .collect();
+    assert_eq!(body["text"], expected.join("\n"));
+}
+
+#[tokio::test]
+async fn connections_beyond_max_connections_are_closed() {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("MAX_CONNECTIONS", "2")]).await;
+    let addr = server.base_url.trim_start_matches("http://").to_string();
+    // Let the readiness check's connection close and release its slot.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Slowloris-style: a request line and nothing more.
+    let open = || async {
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        stream
+            .write_all(b"GET /api/health HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        stream
+    };
+    let closed_by_server = |mut stream: TcpStream| async move {
+        let mut buf = [0u8; 64];
+        let read = tokio::time::timeout(Duration::from_millis(500), stream.read(&mut buf)).await;
+        matches!(read, Ok(Ok(0) | Err(_)))
+    };
+
+    let first = open().await;
+    let mut second = open().await;
+    let third = open().await;
+    assert!(
+        closed_by_server(third).await,
+        "third connection was admitted"
+    );
+
+    // Both held connections are still open...
+    second
+        .write_all(b"Host: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    second.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+    // ...and closing one frees its slot for a new client.
+    drop(first);
+    let mut status = None;
+    for _ in 0..20 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if let Ok(resp) = reqwest::get(server.url("/api/health")).await {
+            status = Some(resp.status());
+            break;
+        }
+    }
+    assert_eq!(status, Some(reqwest::StatusCode::OK));
+}
+
+#[tokio::test]
+async fn quality_estimate_is_added_on_request() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .and(wiremock::matchers::body_string_contains("walk"))
+        .respond_with(doubao_reply("今天天气很好，我们去公园散步吧。"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v3/responses"))
+        .and(wiremock::matchers::body_string_contains("report"))
+        .respond_with(doubao_reply("报告"))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let client = reqwest::Client::new();
+    let translate = |text: &str, quality: bool| {
+        client
+            .post(server.url(&format!("/api/translate?quality={quality}")))
+            .json(&json!({ "text": text, "source": "en", "target": "zh" }))
+            .send()
+    };
+
+    let walk = "The weather is lovely today, let us go for a walk in the park.";
+    let body: Value = translate(walk, true).await.unwrap().json().await.unwrap();
+    assert_eq!(body["quality"], json!({ "score": 1.0, "flags": [] }));
+    let body: Value = translate(walk, false).await.unwrap().json().await.unwrap();
+    assert!(body.get("quality").is_none(), "{body}");
+
+    let report = "Please find the full quarterly report attached to this message.";
+    let body: Value = translate(report, true).await.unwrap().json().await.unwrap();
+    assert_eq!(body["text"], "报告");
+    assert_eq!(body["quality"]["flags"], json!(["length_ratio"]));
+    assert!(body["quality"]["score"].as_f64().unwrap() < 0.6, "{body}");
+}
+
+#[tokio::test]
+async fn cache_seed_file_preloads_valid_entries() {
+    let seed = fixture_file(
+        "cache-seed.json",
+        r#"[
+            { "text": "hello", "source": "en", "target": "zh", "translation": "你好" },
+            { "text": "goodbye", "target": "ja", "translation": "さようなら" },
+            { "text": "", "target": "zh", "translation": "空" }
+        ]"#,
+    );
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let server = TestServer::start(&upstream, &[("CACHE_SEED_FILE", seed.to_str().unwrap())]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "你好");
+    assert_eq!(body["cached"], true, "{body}");
+    let (_, body) = server
+        .translate(json!({ "text": "goodbye", "target": "ja" }))
+        .await;
+    assert_eq!(body["text"], "さようなら");
+    assert_eq!(body["cached"], true, "{body}");
+    let logs = server.logs();
+    assert!(logs.contains("skipping entry 2"), "{logs}");
+    assert!(logs.contains("Cache seeded with 2 entries"), "{logs}");
+}
+
+#[tokio::test]
+async fn pool_and_proxy_settings_still_translate() {
+    // The proxy is a second mock that answers in place of the upstream.
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let proxy = mock_upstream(doubao_reply("你好"), 1).await;
+    let server = TestServer::start(
+        &upstream,
+        &[
+            ("HTTP_POOL_MAX_IDLE", "2"),
+            ("HTTP_POOL_IDLE_SECS", "5"),
+            ("HTTP_PROXY", &proxy.uri()),
+            ("HTTP_DISABLE_PROXY", "false"),
+        ],
+    )
+    .await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body["text"], "你好");
+}
+
+#[tokio::test]
+async fn slow_upstream_maps_to_gateway_timeout() {
+    let slow = doubao_reply("你好").set_delay(Duration::from_secs(3));
+    let upstream = mock_upstream(slow, 1).await;
+    let server = TestServer::start(&upstream, &[("HTTP_REQUEST_TIMEOUT_SECS", "1")]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 504, "{body}");
+    assert_eq!(body["success"], false);
+    assert_eq!(body["code"], "UPSTREAM_TIMEOUT");
+}
+
+#[tokio::test]
+async fn unreachable_upstream_maps_to_bad_gateway() {
+    let upstream = mock_upstream(doubao_reply("unused"), 0).await;
+    let closed = format!("http://127.0.0.1:{}/api/v3/responses", free_port());
+    let server = TestServer::start(&upstream, &[("ARK_API_URL", &closed)]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "target": "zh" }))
+        .await;
+    assert_eq!(status, 502, "{body}");
+    assert_eq!(body["code"], "UPSTREAM_ERROR");
+}
+
+#[tokio::test]
+async fn formality_is_sent_upstream_and_keys_the_cache() {
+    let upstream = mock_upstream(doubao_reply("你好"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request =
+        |formality: &str| json!({ "text": "hello", "target": "zh", "formality": formality });
+
+    for formality in ["formal", "informal", "formal"] {
+        let (status, body) = server.translate(request(formality)).await;
+        assert_eq!(status, 200, "{body}");
+    }
+    let (_, body) = server.translate(request("informal")).await;
+    assert_eq!(body["cached"], true, "{body}");
+
+    let sent: Vec<Value> = upstream_bodies(&upstream)
+        .await
+        .iter()
+        .map(|body| body["input"][0]["content"][0]["translation_options"]["formality"].clone())
+        .collect();
+    assert_eq!(sent, [json!("formal"), json!("informal")]);
+}
+
+#[tokio::test]
+async fn markdown_and_plain_requests_keep_separate_cache_entries() {
+    let upstream = mock_upstream(doubao_reply("# 你好"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+    let request = |format: &str| json!({ "text": "# hello", "target": "zh", "format": format });
+
+    for format in ["markdown", "plain"] {
+        let (status, body) = server.translate(request(format)).await;
+        assert_eq!(status, 200, "{body}");
+        assert_eq!(body["cached"], false, "{body}");
+    }
+    let (_, body) = server.translate(request("markdown")).await;
+    assert_eq!(body["cached"], true, "{body}");
+}
+
+#[tokio::test]
+async fn same_source_and_target_skip_the_upstream() {
+    let upstream = mock_upstream(doubao_reply("译文"), 2).await;
+    let server = TestServer::start(&upstream, &[]).await;
+
+    let (status, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "EN" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "hello");
+    assert_eq!(body["skipped"], true);
+    assert_eq!(body["cached"], false);
+    let (_, body) = server
+        .translate(json!({ "text": "你好", "source": "zh-CN", "target": "zh" }))
+        .await;
+    assert_eq!(body["text"], "你好");
+    assert_eq!(body["skipped"], true, "{body}");
+    assert!(upstream_bodies(&upstream).await.is_empty());
+
+    let (_, body) = server
+        .translate(json!({ "text": "hello", "source": "en", "target": "zh" }))
+        .await;
+    assert_eq!(body["text"], "译文");
+    assert!(body.get("skipped").is_none(), "{body}");
+    // Without a source nothing is known to match, even for text already in the target.
+    let (_, body) = server
+        .translate(json!({ "text": "早上好", "target": "zh" }))
+        .await;
+    assert_eq!(body["text"], "译文");
+    assert!(body.get("skipped").is_none(), "{body}");
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use doubao_translator::{Cache, TranslateParams};
+
+async fn remaining_ttls(cache: &Cache, count: usize) -> Vec<Duration> {
+    let mut ttls = Vec::with_capacity(count);
+    for i in 0..count {
+        let key = cache.key(&format!("entry {i}"), &TranslateParams::new("zh"));
+        cache.set(key.clone(), format!("条目 {i}")).await;
+        ttls.push(cache.ttl_remaining(&key).await.expect("entry was just set"));
+    }
+    ttls
+}
+
+#[tokio::test]
+async fn ttl_jitter_spreads_expiry_times() {
+    let cache = Cache::new(100, Duration::from_secs(100)).with_ttl_jitter(50);
+
+    let ttls = remaining_ttls(&cache, 50).await;
+
+    let min = *ttls.iter().min().unwrap();
+    let max = *ttls.iter().max().unwrap();
+    assert!(min >= Duration::from_secs(49), "min {min:?}");
+    assert!(max <= Duration::from_secs(150), "max {max:?}");
+    assert!(
+        max - min > Duration::from_secs(10),
+        "spread {:?}",
+        max - min
+    );
+}
+
+#[tokio::test]
+async fn ttl_is_fixed_without_jitter() {
+    let cache = Cache::new(100, Duration::from_secs(100));
+
+    let ttls = remaining_ttls(&cache, 50).await;
+
+    let min = *ttls.iter().min().unwrap();
+    let max = *ttls.iter().max().unwrap();
+    assert!(max <= Duration::from_secs(100));
+    assert!(max - min < Duration::from_secs(1), "spread {:?}", max - min);
+}
+
+#[tokio::test]
+async fn key_prefixes_keep_namespaces_apart() {
+    let ttl = Duration::from_secs(60);
+    let staging = Cache::new(10, ttl).with_key_prefix("staging:");
+    let prod = Cache::new(10, ttl).with_key_prefix("prod:");
+    let params = TranslateParams::new("zh");
+
+    let staging_key = staging.key("hello", &params);
+    let prod_key = prod.key("hello", &params);
+    assert_ne!(staging_key, prod_key);
+    assert!(staging_key.starts_with("staging:"));
+    assert!(prod_key.starts_with("prod:"));
+    assert!(staging
+        .chunk_key("model", "hello", &params)
+        .starts_with("staging:"));
+
+    staging.set(staging_key.clone(), "你好".to_string()).await;
+    assert_eq!(staging.get(&staging_key).await.as_deref(), Some("你好"));
+    assert_eq!(staging.get(&prod_key).await, None);
+}
+
+#[tokio::test]
+async fn capacity_evictions_and_expirations_are_counted_separately() {
+    let cache = Cache::new(2, Duration::from_secs(60));
+    for key in ["a", "b", "c", "d"] {
+        cache.set(key.to_string(), key.to_uppercase()).await;
+    }
+    cache.set("d".to_string(), "D2".to_string()).await;
+
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.capacity, 2);
+    assert_eq!(stats.capacity_evictions, 2);
+    assert_eq!(stats.ttl_expirations, 0);
+
+    let short = Cache::new(10, Duration::from_millis(10));
+    short.set("a".to_string(), "A".to_string()).await;
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert_eq!(short.get("a").await, None);
+    assert_eq!(short.get("a").await, None);
+    let stats = short.stats().await;
+    assert_eq!((stats.capacity_evictions, stats.ttl_expirations), (0, 1));
+}
+
+#[tokio::test]
+async fn stale_window_keeps_expired_entries_for_lookup() {
+    let cache = Cache::new(10, Duration::from_millis(20))
+        .with_stale_while_revalidate(Duration::from_secs(60));
+    cache.set("k".into(), "v".into()).await;
+    tokio::time::sleep(Duration::from_millis(40)).await;
+
+    assert_eq!(cache.get("k").await, None);
+    let hit = cache
+        .lookup("k")
+        .await
+        .expect("entry is within the stale window");
+    assert_eq!(hit.value, "v");
+    assert!(hit.stale);
+
+    let guard = cache.begin_refresh("k").expect("first refresh is claimed");
+    assert!(cache.begin_refresh("k").is_none());
+    drop(guard);
+    assert!(cache.begin_refresh("k").is_some());
+}
+
+#[tokio::test]
+async fn per_entry_ttl_overrides_the_cache_ttl() {
+    let cache = Cache::new(10, Duration::from_secs(3600));
+    cache
+        .set_with_ttl("live".into(), "1:0".into(), Some(Duration::from_millis(20)))
+        .await;
+    cache.set("static".into(), "设置".into()).await;
+    tokio::time::sleep(Duration::from_millis(40)).await;
+
+    assert_eq!(cache.get("live").await, None);
+    assert_eq!(cache.get("static").await.as_deref(), Some("设置"));
+}
+
+#[tokio::test]
+async fn exported_translations_import_under_the_new_cache_keys() {
+    let params = TranslateParams {
+        source: Some("en"),
+        ..TranslateParams::new("zh")
+    };
+    let old = Cache::new(10, Duration::from_secs(3600)).with_key_prefix("old:");
+    old.set_translation(old.key("hello", &params), "hello", &params, "你好".into())
+        .await;
+    old.set("chunk".into(), "not exported".into()).await;
+
+    let entries = old.export().await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].text, "hello");
+    assert_eq!(entries[0].source.as_deref(), Some("en"));
+
+    let new = Cache::new(10, Duration::from_secs(3600)).with_key_prefix("new:");
+    assert_eq!(new.import(entries.clone()).await, 1);
+    assert_eq!(
+        new.get(&new.key("hello", &params)).await.as_deref(),
+        Some("你好")
+    );
+    let reexported = new.export().await;
+    assert_eq!(reexported[0].created_at_ms, entries[0].created_at_ms);
+    // Expiry is carried over, up to the clock ticking between the calls.
+    assert!(
+        reexported[0]
+            .expires_at_ms
+            .abs_diff(entries[0].expires_at_ms)
+            < 1000
+    );
+}
@@ -0,0 +1,45 @@
+use doubao_translator::canonical_language;
+
+const SUPPORTED: &[&str] = &[
+    "zh", "zh-Hant", "en", "ja", "ko", "de", "fr", "es", "it", "pt", "ru", "th", "vi", "ar",
+];
+
+fn canonical(code: &str) -> Option<String> {
+    canonical_language(code, |candidate| SUPPORTED.contains(&candidate))
+}
+
+#[test]
+fn variants_map_to_supported_codes() {
+    let table = [
+        ("zh", "zh"),
+        ("ZH", "zh"),
+        (" zh-CN ", "zh"),
+        ("zh_CN", "zh"),
+        ("zh-Hans", "zh"),
+        ("zh_Hans_CN", "zh"),
+        ("chinese", "zh"),
+        ("Chinese", "zh"),
+        ("zh-hant", "zh-Hant"),
+        ("zh-Hant-TW", "zh-Hant"),
+        ("zh-TW", "zh-Hant"),
+        ("zh_HK", "zh-Hant"),
+        ("en-US", "en"),
+        ("EN_gb", "en"),
+        ("english", "en"),
+        ("jpn", "ja"),
+        ("jp", "ja"),
+        ("pt-BR", "pt"),
+        ("de-AT", "de"),
+        ("es-419", "es"),
+    ];
+    for (input, expected) in table {
+        assert_eq!(canonical(input).as_deref(), Some(expected), "{input:?}");
+    }
+}
+
+#[test]
+fn unknown_codes_have_no_canonical_form() {
+    for input in ["", "xx", "klingon", "zz-Hant", "x-klingon"] {
+        assert_eq!(canonical(input), None, "{input:?}");
+    }
+}
@@ -0,0 +1,9 @@
+use doubao_translator::normalize_input;
+
+#[test]
+fn folds_nfd_and_fullwidth_alphanumerics() {
+    assert_eq!(normalize_input("cafe\u{301}"), "caf\u{e9}");
+    assert_eq!(normalize_input("ＡＢＣ１２３ｘ"), "ABC123x");
+    // CJK punctuation keeps its full-width form.
+    assert_eq!(normalize_input("你好，世界！"), "你好，世界！");
+}
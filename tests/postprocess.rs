@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use doubao_translator::{
+    MockProvider, Pipeline, PostContext, StripArtifacts, TranslateParams, Translator,
+    TranslatorConfig,
+};
+
+fn upper(text: String, _: &PostContext<'_>) -> String {
+    text.to_uppercase()
+}
+
+fn tag_target(text: String, ctx: &PostContext<'_>) -> String {
+    format!("{text} ({})", ctx.target)
+}
+
+fn ctx<'a>(source: &'a str) -> PostContext<'a> {
+    PostContext {
+        source,
+        target: "zh",
+        model: "test-model",
+    }
+}
+
+#[test]
+fn processors_run_in_insertion_order() {
+    let upper_first = Pipeline::new().with(upper).with(tag_target);
+    let tag_first = Pipeline::new().with(tag_target).with(upper);
+
+    assert_eq!(upper_first.run("hi".into(), &ctx("hi")), "HI (zh)");
+    assert_eq!(tag_first.run("hi".into(), &ctx("hi")), "HI (ZH)");
+}
+
+#[test]
+fn builtin_processors_compose_with_custom_ones() {
+    let pipeline = Pipeline::new().with(StripArtifacts).with(tag_target);
+    let output = pipeline.run("Translation: 你好".into(), &ctx("hello"));
+    assert_eq!(output, "你好 (zh)");
+
+    // Once tagged, the output no longer ends with the fence, so it stays.
+    let pipeline = Pipeline::new().with(tag_target).with(StripArtifacts);
+    let output = pipeline.run("```\n你好\n```".into(), &ctx("hello"));
+    assert_eq!(output, "```\n你好\n``` (zh)");
+}
+
+#[tokio::test]
+async fn translator_applies_the_pipeline_to_fresh_chunks() {
+    let translator = Translator::new(reqwest::Client::new(), TranslatorConfig::new(""))
+        .with_provider(Arc::new(MockProvider))
+        .with_post_processor(upper)
+        .with_post_processor(tag_target);
+
+    let translation = translator
+        .translate_with("hello", &TranslateParams::new("ja"))
+        .await
+        .unwrap();
+    assert_eq!(translation.text, "[JA] HELLO (ja)");
+}
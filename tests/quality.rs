@@ -0,0 +1,48 @@
+use doubao_translator::estimate_quality;
+use regex::Regex;
+
+#[test]
+fn a_plausible_translation_scores_high() {
+    let quality = estimate_quality(
+        "The weather is lovely today, let us go for a walk in the park.",
+        "今天天气很好，我们去公园散步吧。",
+        "zh",
+        None,
+    );
+    assert_eq!(quality.score, 1.0);
+    assert!(quality.flags.is_empty(), "{:?}", quality.flags);
+
+    let quality = estimate_quality(
+        "今天天气很好，我们去公园散步吧。",
+        "The weather is lovely today, let us go for a walk in the park.",
+        "en",
+        None,
+    );
+    assert_eq!(quality.score, 1.0, "{:?}", quality.flags);
+}
+
+#[test]
+fn suspect_translations_are_flagged() {
+    let source = "The weather is lovely today, let us go for a walk in the park.";
+    let blank = estimate_quality(source, "  ", "zh", None);
+    assert_eq!(blank.score, 0.0);
+    assert_eq!(blank.flags, ["empty"]);
+
+    let unchanged = estimate_quality(source, source, "zh", None);
+    assert_eq!(unchanged.flags, ["unchanged", "untranslated"]);
+    assert_eq!(unchanged.score, 0.0);
+
+    let cut_short = estimate_quality(source, "天气", "zh", None);
+    assert_eq!(cut_short.flags, ["length_ratio"]);
+    assert_eq!(cut_short.score, 0.5);
+
+    let pattern = Regex::new(r"\{\w+\}").unwrap();
+    let placeholders = estimate_quality(
+        "Hello {name}, your order {order} has shipped today.",
+        "你好 {name}，你的订单今天已经发货了。",
+        "zh",
+        Some(&pattern),
+    );
+    assert_eq!(placeholders.flags, ["placeholders"]);
+    assert_eq!(placeholders.score, 0.75);
+}
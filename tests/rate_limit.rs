@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use doubao_translator::RateLimiter;
+
+#[tokio::test]
+async fn sweeper_drops_hits_once_the_window_passes() {
+    let limiter = RateLimiter::new(Duration::from_millis(100), 10);
+    for _ in 0..5 {
+        limiter.allow().await.unwrap();
+    }
+    assert_eq!(limiter.hit_count().await, 5);
+
+    limiter.spawn_sweeper(Duration::from_millis(50));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(limiter.hit_count().await, 0);
+}
+
+#[tokio::test]
+async fn hits_stay_until_swept_or_checked() {
+    let limiter = RateLimiter::new(Duration::from_millis(50), 10);
+    limiter.allow().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(limiter.hit_count().await, 1);
+
+    limiter.sweep().await;
+    assert_eq!(limiter.hit_count().await, 0);
+}
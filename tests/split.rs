@@ -0,0 +1,114 @@
+use doubao_translator::{
+    is_whitespace_only, split_markdown, split_text, split_text_limited, split_text_with,
+    DEFAULT_SENTENCE_TERMINATORS,
+};
+
+#[test]
+fn long_lines_break_after_sentences() {
+    let text = "First sentence here. Second sentence here! Third one?";
+
+    assert_eq!(
+        split_text(text, 25),
+        [
+            "First sentence here. ",
+            "Second sentence here! ",
+            "Third one?"
+        ]
+    );
+}
+
+#[test]
+fn custom_terminators_change_the_break_points() {
+    // Version numbers and abbreviations should not end a sentence here.
+    let text = "Use v1.2 of the API; then call init; done";
+
+    assert_eq!(
+        split_text_with(text, 21, ";"),
+        ["Use v1.2 of the API; ", "then call init; done"]
+    );
+    assert_eq!(
+        split_text_with(text, 21, "."),
+        ["Use v1.", "2 of the API; then ca", "ll init; done"]
+    );
+}
+
+#[test]
+fn cjk_terminators_are_recognised_by_default() {
+    let text = "今天天气很好。我们去公园吧！好的。";
+
+    assert_eq!(
+        split_text(text, 8),
+        ["今天天气很好。", "我们去公园吧！", "好的。"]
+    );
+    assert_eq!(
+        split_text_with(text, 8, ""),
+        ["今天天气很好。我", "们去公园吧！好的", "。"]
+    );
+}
+
+fn markdown_doc() -> String {
+    let code = (0..12)
+        .map(|i| format!("let value_{i} = compute({i});"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "# Install\n\nRun the installer first.\n\n```rust\n{code}\n```\n\n## Usage\n\nCall the API.\nThen read the result.\n"
+    )
+}
+
+#[test]
+fn markdown_keeps_code_fences_whole() {
+    let doc = markdown_doc();
+    let chunks = split_markdown(&doc, 80, DEFAULT_SENTENCE_TERMINATORS);
+
+    let fence: Vec<&String> = chunks.iter().filter(|c| c.contains("```")).collect();
+    assert_eq!(fence.len(), 1, "{chunks:#?}");
+    assert!(fence[0].starts_with("```rust\n") && fence[0].contains("value_11"));
+    assert!(fence[0].trim_end().ends_with("```"));
+    assert_eq!(chunks.join("\n"), doc);
+}
+
+#[test]
+fn markdown_breaks_before_headings() {
+    let doc = "# One\n\nFirst section text.\n\n# Two\n\nSecond section text.\n";
+    let chunks = split_markdown(doc, 40, DEFAULT_SENTENCE_TERMINATORS);
+
+    assert_eq!(
+        chunks,
+        [
+            "# One\n\nFirst section text.\n",
+            "# Two\n\nSecond section text.\n"
+        ]
+    );
+}
+
+#[test]
+fn paragraph_limit_breaks_short_paragraphs_apart() {
+    let paragraphs: Vec<String> = (0..10).map(|i| format!("Line {i}.")).collect();
+    let text = paragraphs.join("\n\n");
+
+    assert_eq!(split_text_limited(&text, 1000, 0, "."), [text.as_str()]);
+
+    let chunks = split_text_limited(&text, 1000, 3, ".");
+    assert_eq!(chunks.len(), 4);
+    assert_eq!(chunks[0], "Line 0.\n\nLine 1.\n\nLine 2.");
+    assert_eq!(chunks[3], "Line 9.");
+    assert_eq!(chunks.join("\n\n"), text);
+
+    // The character limit still applies within the paragraph limit.
+    let chunks = split_text_limited(&text, 20, 3, ".");
+    for chunk in &chunks {
+        assert!(chunk.chars().count() <= 20, "{chunk:?}");
+        assert!(chunk.split("\n\n").count() <= 3, "{chunk:?}");
+    }
+    assert_eq!(chunks.len(), 5);
+    assert_eq!(chunks.join("\n\n"), text);
+}
+
+#[test]
+fn whitespace_only_chunks_are_recognised() {
+    assert!(is_whitespace_only("\n \n\t\r\n"));
+    assert!(is_whitespace_only("\u{3000}"));
+    assert!(is_whitespace_only(""));
+    assert!(!is_whitespace_only("\n\n.\n"));
+}